@@ -10,7 +10,7 @@ fn get_renamer() -> MatchAndReplacer<'static> {
     )
     .unwrap();
 
-    return MatchAndReplacer::new(expr);
+    MatchAndReplacer::new(expr)
 }
 
 fn create_file_paths(count: usize) -> Vec<PathBuf> {
@@ -18,7 +18,7 @@ fn create_file_paths(count: usize) -> Vec<PathBuf> {
         .map(|i| PathBuf::from(format!("./files/g-{i}-a-{i}-al-{i}")))
         .collect::<Vec<_>>();
 
-    return paths;
+    paths
 }
 
 fn renaming_files(c: &mut Criterion) {
@@ -0,0 +1,35 @@
+use std::str::FromStr;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mrp::{parser::MatchAndReplaceRules, MatchAndReplaceStrategy, MatchAndReplacer};
+
+const EXP: &str = "IMG_(n:int)->photo-(n:lpad 5 '0')";
+
+/// File names like those of a large photo library, where only some of them match.
+fn names() -> Vec<String> {
+    (0..100_000)
+        .map(|i| match i % 4 {
+            0 => format!("IMG_{i}.jpg"),
+            1 => format!("DSC_{i}.jpg"),
+            2 => format!("vacation/2023/beach-{i}.png"),
+            _ => format!("screenshots/Screenshot from 2023-08-01 {i}.png"),
+        })
+        .collect()
+}
+
+fn bulk_renames_benchmark(c: &mut Criterion) {
+    let names = names();
+    let rules = MatchAndReplaceRules::from_str(EXP).unwrap();
+    let r = MatchAndReplacer::from_rules(rules);
+
+    c.bench_function("mrp bulk renames", |b| {
+        b.iter(|| {
+            for name in &names {
+                r.apply(name);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bulk_renames_benchmark);
+criterion_main!(benches);
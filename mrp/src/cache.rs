@@ -0,0 +1,139 @@
+//! Expressions kept by their source, so that one that is parsed over and over again, like in a
+//! server or a watch mode, is only parsed and compiled the first time.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::OwnedParseError,
+    parser::{MatchAndReplaceRulesOwned, MatchExpressionOwned},
+};
+
+/// Parsed expressions, shared between threads, by the source they were parsed from.
+#[derive(Debug, Default)]
+pub struct ExpressionCache {
+    expressions: Mutex<HashMap<Box<str>, Arc<MatchExpressionOwned>>>,
+    rules: Mutex<HashMap<Box<str>, Arc<MatchAndReplaceRulesOwned>>>,
+}
+
+impl ExpressionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The match expression written in the source, with its compiled program; see
+    /// [`MatchExpressionOwned::compiled`]. Only a source that parses is kept.
+    pub fn expression(&self, source: &str) -> Result<Arc<MatchExpressionOwned>, OwnedParseError> {
+        let mut expressions = self
+            .expressions
+            .lock()
+            .expect("cache shouldn't be poisoned");
+
+        if let Some(compiled) = expressions.get(source) {
+            return Ok(Arc::clone(compiled));
+        }
+
+        let parsed = Arc::new(MatchExpressionOwned::from_str(source)?);
+        expressions.insert(source.into(), Arc::clone(&parsed));
+
+        Ok(parsed)
+    }
+
+    /// The rules written in the source. Only a source that parses is kept.
+    pub fn rules(&self, source: &str) -> Result<Arc<MatchAndReplaceRulesOwned>, OwnedParseError> {
+        let mut rules = self.rules.lock().expect("cache shouldn't be poisoned");
+
+        if let Some(parsed) = rules.get(source) {
+            return Ok(Arc::clone(parsed));
+        }
+
+        let parsed = Arc::new(MatchAndReplaceRulesOwned::from_str(source)?);
+        rules.insert(source.into(), Arc::clone(&parsed));
+
+        Ok(parsed)
+    }
+
+    /// How many sources have been parsed.
+    pub fn len(&self) -> usize {
+        self.expressions
+            .lock()
+            .expect("cache shouldn't be poisoned")
+            .len()
+            + self
+                .rules
+                .lock()
+                .expect("cache shouldn't be poisoned")
+                .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget every expression; those that are still used elsewhere are kept until they aren't.
+    pub fn clear(&self) {
+        self.expressions
+            .lock()
+            .expect("cache shouldn't be poisoned")
+            .clear();
+        self.rules
+            .lock()
+            .expect("cache shouldn't be poisoned")
+            .clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn sharing_expressions_parsed_from_the_same_source() {
+        let cache = ExpressionCache::new();
+
+        let first = cache.expression("IMG_(n:int)").unwrap();
+        let second = cache.expression("IMG_(n:int)").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(first.compiled().is_match("IMG_12"));
+
+        let rules = cache.rules("(n:int)->(n:hex)").unwrap();
+        assert!(Arc::ptr_eq(
+            &rules,
+            &cache.rules("(n:int)->(n:hex)").unwrap()
+        ));
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn not_keeping_sources_that_dont_parse() {
+        let cache = ExpressionCache::new();
+
+        assert!(cache.expression("(n:int").is_err());
+        assert!(cache.rules("->x").is_err());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn sharing_the_cache_between_threads() {
+        let cache = Arc::new(ExpressionCache::new());
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || cache.expression("(t:alpha)-(n:int)").unwrap())
+            })
+            .collect();
+
+        let compiled: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert!(compiled.iter().all(|c| Arc::ptr_eq(c, &compiled[0])));
+        assert_eq!(cache.len(), 1);
+    }
+}
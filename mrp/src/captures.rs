@@ -1,10 +1,15 @@
+use std::{borrow::Cow, ops::Index};
+
 #[derive(Debug, PartialEq)]
 struct Capture<'source, 'input> {
     name: &'source str,
-    value: &'input str,
+    /// Usually the captured text, but can be a value worked out from it, like the number in a
+    /// word such as `three`.
+    value: Cow<'input, str>,
 }
 
-#[derive(Debug, PartialEq)]
+/// What a match captured, by the names of the captures.
+#[derive(Debug, PartialEq, Default)]
 pub struct Captures<'source, 'input> {
     inner: Vec<Capture<'source, 'input>>,
 }
@@ -13,10 +18,43 @@ impl<'source, 'input> Captures<'source, 'input> {
     pub fn new() -> Self {
         Self { inner: vec![] }
     }
-    pub fn put(&mut self, name: &'source str, value: &'input str) {
-        self.inner.push(Capture { name, value });
+    pub fn put(&mut self, name: &'source str, value: impl Into<Cow<'input, str>>) {
+        self.inner.push(Capture {
+            name,
+            value: value.into(),
+        });
     }
     pub fn get(&self, name: &str) -> Option<&str> {
-        self.inner.iter().find(|c| c.name == name).map(|c| c.value)
+        self.inner
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.value.as_ref())
+    }
+
+    /// The names of the captures, and their values, in the order they were captured.
+    pub fn iter(&self) -> impl Iterator<Item = (&'source str, &str)> {
+        self.inner.iter().map(|c| (c.name, c.value.as_ref()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Forget what was captured, for a match that is started over.
+    pub(crate) fn clear(&mut self) {
+        self.inner.clear();
+    }
+}
+
+/// The value of a capture, by the order it was captured in.
+impl<'source, 'input> Index<usize> for Captures<'source, 'input> {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        &self.inner[index].value
     }
 }
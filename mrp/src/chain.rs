@@ -0,0 +1,85 @@
+//! Applying several strategies one after the other, like the expressions given with `-e`.
+
+use std::borrow::Cow;
+
+use crate::{MatchAndReplaceStrategy, Variables};
+
+/// Strategies applied in order, each on the result of the ones before it; a value that none of
+/// them matches isn't replaced. Unlike the rules of one expression, each strategy keeps its own
+/// settings, like its `(#)` counter.
+pub struct MatchAndReplaceChain<S> {
+    strategies: Vec<S>,
+}
+
+impl<S> MatchAndReplaceChain<S> {
+    pub fn new(strategies: Vec<S>) -> Self {
+        Self { strategies }
+    }
+
+    pub fn strategies(&self) -> &[S] {
+        &self.strategies
+    }
+}
+
+impl<'input, S: for<'a> MatchAndReplaceStrategy<'a>> MatchAndReplaceStrategy<'input>
+    for MatchAndReplaceChain<S>
+{
+    fn apply(&self, value: &'input str) -> Option<Cow<'input, str>> {
+        self.apply_with(value, &Variables::new())
+    }
+
+    fn apply_with(&self, value: &'input str, variables: &Variables) -> Option<Cow<'input, str>> {
+        let mut current = Cow::Borrowed(value);
+        let mut replaced = false;
+
+        for strategy in &self.strategies {
+            let new = strategy
+                .apply_with(&current, variables)
+                .map(Cow::into_owned);
+            if let Some(new) = new {
+                current = Cow::Owned(new);
+                replaced = true;
+            }
+        }
+
+        replaced.then_some(current)
+    }
+
+    fn uses_variable(&self, name: &str) -> bool {
+        self.strategies.iter().any(|s| s.uses_variable(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{parser::MatchAndReplaceExpression, MatchAndReplacer};
+
+    fn replacer(source: &str) -> MatchAndReplacer<'_> {
+        MatchAndReplacer::new(MatchAndReplaceExpression::from_str(source).unwrap())
+    }
+
+    #[test]
+    fn each_strategy_gets_the_result_of_the_ones_before() {
+        let chain = MatchAndReplaceChain::new(vec![
+            replacer("IMG->photo"),
+            replacer("(n:int)->(n:lpad 4 '0')"),
+        ]);
+
+        assert_eq!(chain.apply("IMG_12.jpg").unwrap(), "photo_0012.jpg");
+        assert_eq!(chain.apply("DSC_7.jpg").unwrap(), "DSC_0007.jpg");
+        assert_eq!(chain.apply("IMG.jpg").unwrap(), "photo.jpg");
+        assert_eq!(chain.apply("notes.txt"), None);
+    }
+
+    #[test]
+    fn uses_the_variables_of_every_strategy() {
+        let chain =
+            MatchAndReplaceChain::new(vec![replacer("IMG->photo"), replacer("(n:int)->(n)(ext)")]);
+
+        assert!(chain.uses_variable("ext"));
+        assert!(!chain.uses_variable("stem"));
+    }
+}
@@ -0,0 +1,706 @@
+//! Lowering a match expression into a flat program, so that the work of telling what each part of
+//! it is, and how it's matched, is done once instead of at every position of every input.
+
+use std::borrow::Cow;
+
+use memchr::memmem::{self, Finder};
+
+use crate::{
+    captures::Captures,
+    matcher::{
+        next_char, scan_date, scan_exactly, scan_timestamp, scan_word_number, CaptureMatches,
+        Match, Matches,
+    },
+    parser::{AbstractMatchingExpression, CaptureType, MatchExpression},
+    registry::CustomCaptureType,
+    size,
+};
+
+/// How a capture that is matched as a whole is found.
+#[derive(Debug, Clone)]
+enum Scanner {
+    /// Exactly this many characters of a type.
+    Exactly(CaptureType, usize),
+    Date,
+    Timestamp,
+    Size,
+    WordNum,
+    Custom(CustomCaptureType),
+}
+
+#[derive(Debug, Clone)]
+enum Instruction<'source> {
+    Literal(Cow<'source, str>),
+    Lookahead(Cow<'source, str>),
+    Digit {
+        identifier: &'source str,
+        optional: bool,
+    },
+    /// A capture of as many characters of a type as there are, like `(n:int)`.
+    Repeat {
+        identifier: &'source str,
+        identifier_type: CaptureType,
+        optional: bool,
+        /// The text that comes next when it starts with a character of the type, like `1x` in
+        /// `(n:int)1x`, which the capture gives characters back to.
+        yields_to: Option<Cow<'source, str>>,
+    },
+    Scan {
+        identifier: &'source str,
+        scanner: Scanner,
+        optional: bool,
+    },
+}
+
+impl<'source> Instruction<'source> {
+    fn is_optional(&self) -> bool {
+        match self {
+            Instruction::Literal(_) | Instruction::Lookahead(_) => false,
+            Instruction::Digit { optional, .. }
+            | Instruction::Repeat { optional, .. }
+            | Instruction::Scan { optional, .. } => *optional,
+        }
+    }
+}
+
+/// A match expression that is ready to be matched; see [`MatchExpression::compile`].
+#[derive(Debug, Clone)]
+pub struct CompiledMatchExpression<'source> {
+    program: Box<[Instruction<'source>]>,
+    /// A search for the longest literal of the expression, which has to be somewhere in an input
+    /// for it to match, so that inputs without it are passed over without running the program.
+    prefilter: Option<(Cow<'source, str>, Finder<'static>)>,
+    /// Whether a match has to start where the search does, and go on to the end of the input.
+    pub(crate) anchored: bool,
+    /// Whether literals match text in any case.
+    pub(crate) case_insensitive: bool,
+    /// How many positions a search tries a match at before it gives up.
+    pub(crate) max_positions: Option<usize>,
+    /// Whether the last match is found, instead of the first one.
+    pub(crate) rightmost: bool,
+}
+
+impl<'source> MatchExpression<'source> {
+    /// The expression as a program, for matching it on many inputs.
+    pub fn compile(&self) -> CompiledMatchExpression<'source> {
+        let program = self
+            .expressions
+            .iter()
+            .enumerate()
+            .map(|(idx, e)| match e {
+                AbstractMatchingExpression::Literal(literal) => {
+                    Instruction::Literal(literal.clone())
+                }
+                AbstractMatchingExpression::Lookahead(text) => Instruction::Lookahead(text.clone()),
+                AbstractMatchingExpression::Capture {
+                    identifier,
+                    identifier_type,
+                    optional,
+                    length,
+                } => {
+                    let (identifier, optional) = (*identifier, *optional);
+
+                    let scanner = match (identifier_type, length) {
+                        (CaptureType::Digit, _) => {
+                            return Instruction::Digit {
+                                identifier,
+                                optional,
+                            }
+                        }
+                        (_, Some(len)) => Scanner::Exactly(identifier_type.clone(), *len),
+                        (CaptureType::Int | CaptureType::Alpha | CaptureType::Hex, None) => {
+                            let yields_to = match self.expressions.get(idx + 1) {
+                                Some(
+                                    AbstractMatchingExpression::Literal(text)
+                                    | AbstractMatchingExpression::Lookahead(text),
+                                ) => Some(text.clone()).filter(|text| {
+                                    text.chars()
+                                        .next()
+                                        .is_some_and(|c| identifier_type.accepts(c))
+                                }),
+                                _ => None,
+                            };
+
+                            return Instruction::Repeat {
+                                identifier,
+                                identifier_type: identifier_type.clone(),
+                                optional,
+                                yields_to,
+                            };
+                        }
+                        (CaptureType::Date, None) => Scanner::Date,
+                        (CaptureType::Timestamp, None) => Scanner::Timestamp,
+                        (CaptureType::Size, None) => Scanner::Size,
+                        (CaptureType::WordNum, None) => Scanner::WordNum,
+                        (CaptureType::Custom(custom), None) => Scanner::Custom(custom.clone()),
+                    };
+
+                    Instruction::Scan {
+                        identifier,
+                        scanner,
+                        optional,
+                    }
+                }
+            })
+            .collect();
+
+        let prefilter = self
+            .expressions
+            .iter()
+            .filter_map(|e| match e {
+                AbstractMatchingExpression::Literal(literal) => Some(literal),
+                _ => None,
+            })
+            .max_by_key(|literal| literal.len())
+            .map(|literal| {
+                (
+                    literal.clone(),
+                    Finder::new(literal.as_bytes()).into_owned(),
+                )
+            });
+
+        CompiledMatchExpression {
+            program,
+            prefilter,
+            anchored: false,
+            case_insensitive: false,
+            max_positions: None,
+            rightmost: false,
+        }
+    }
+}
+
+/// Where a capture of the characters from `start` up to `end` stops, so that what `follows` can
+/// come right after it: the capture gives back as few characters as it can for that, but keeps at
+/// least one, and gives back none if it can't come after any of them.
+fn give_back(input: &str, start: usize, end: usize, follows: impl Fn(usize) -> bool) -> usize {
+    if follows(end) {
+        return end;
+    }
+
+    input[start..end]
+        .char_indices()
+        .rev()
+        .map(|(i, _)| start + i)
+        .filter(|position| *position > start)
+        .find(|position| follows(*position))
+        .unwrap_or(end)
+}
+
+/// The position of the character after the one at the given position, so that matching never
+/// starts inside of a character.
+fn step(input: &str, position: usize) -> usize {
+    position + next_char(input, position).map_or(1, char::len_utf8)
+}
+
+impl<'source> CompiledMatchExpression<'source> {
+    /// When true, only a match that starts where the search does, and takes the rest of the
+    /// input, is found; like one that a whole file name has to match.
+    pub fn anchored(mut self, anchored: bool) -> Self {
+        self.anchored = anchored;
+        self
+    }
+
+    /// When true, literals match text in any case, so that `img_` matches `IMG_`.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// When true, a search finds the last match in the input instead of the first one, so that
+    /// `(n:int)` captures `17` in `v2-img-17`.
+    pub fn rightmost(mut self, rightmost: bool) -> Self {
+        self.rightmost = rightmost;
+        self
+    }
+
+    /// Give up on a search once it has tried to match at this many positions, so that a long input
+    /// can't hold it up for long; no limit is set by default.
+    pub fn max_positions(mut self, max_positions: Option<usize>) -> Self {
+        self.max_positions = max_positions;
+        self
+    }
+
+    /// Length of the text at the position in the input, if it's there.
+    fn text_at(&self, input: &str, position: usize, text: &str) -> Option<usize> {
+        if !self.case_insensitive {
+            let rest = input.as_bytes().get(position..)?;
+            return rest.starts_with(text.as_bytes()).then_some(text.len());
+        }
+
+        let mut chars = input.get(position..)?.chars();
+        let mut len = 0;
+
+        for expected in text.chars() {
+            let c = chars.next()?;
+            if c != expected && !c.to_lowercase().eq(expected.to_lowercase()) {
+                return None;
+            }
+            len += c.len_utf8();
+        }
+
+        Some(len)
+    }
+
+    /// Position and length of the first occurence of the text in the input, from a position on.
+    fn find_text(&self, input: &str, from: usize, text: &str) -> Option<(usize, usize)> {
+        if !self.case_insensitive {
+            let offset = memmem::find(input.as_bytes().get(from..)?, text.as_bytes())?;
+            return Some((from + offset, text.len()));
+        }
+
+        (from..input.len())
+            .filter(|position| input.is_char_boundary(*position))
+            .find_map(|position| Some((position, self.text_at(input, position, text)?)))
+    }
+
+    pub fn find_at_capturing<'input>(
+        &self,
+        input: &'input str,
+        start: usize,
+    ) -> (Option<Match<'input>>, Captures<'source, 'input>) {
+        let mut captures = Captures::new();
+        let m = self.run(input, start, Some(&mut captures));
+        (m, captures)
+    }
+
+    /// Find the leftmost-first match in the input starting at the given position
+    pub fn find_at<'input>(&self, input: &'input str, start: usize) -> Option<Match<'input>> {
+        self.run(input, start, None)
+    }
+
+    /// Whether the expression matches anywhere in the input; nothing is captured, so that checking
+    /// many inputs is cheap.
+    pub fn is_match(&self, input: &str) -> bool {
+        self.run(input, 0, None).is_some()
+    }
+
+    /// The literal that has to be in an input for the expression to match it, if there's one
+    /// that is matched as it's written.
+    pub(crate) fn required_literal(&self) -> Option<&str> {
+        match self.case_insensitive {
+            true => None,
+            false => self.prefilter.as_ref().map(|(literal, _)| literal.as_ref()),
+        }
+    }
+
+    /// Look for a match from the start position on, recording what it captures if there's
+    /// somewhere to.
+    fn run<'input>(
+        &self,
+        input: &'input str,
+        start: usize,
+        captures: Option<&mut Captures<'source, 'input>>,
+    ) -> Option<Match<'input>> {
+        // an anchored match can only start in one place
+        if !self.rightmost || self.anchored {
+            return self.run_leftmost(input, start, captures);
+        }
+
+        let mut last_start = None;
+        let mut position = start;
+
+        while position <= input.len() {
+            let Some(m) = self.run_leftmost(input, position, None) else {
+                break;
+            };
+            last_start = Some(m.start);
+            position = match m.is_empty() {
+                true => step(input, m.end),
+                false => m.end,
+            };
+        }
+
+        self.run_leftmost(input, last_start?, captures)
+    }
+
+    /// Look for the first match from the start position on.
+    fn run_leftmost<'input>(
+        &self,
+        input: &'input str,
+        start: usize,
+        captures: Option<&mut Captures<'source, 'input>>,
+    ) -> Option<Match<'input>> {
+        if let Some((literal, finder)) = &self.prefilter {
+            let found = match self.case_insensitive {
+                true => self.find_text(input, start, literal).is_some(),
+                false => input
+                    .as_bytes()
+                    .get(start..)
+                    .is_some_and(|rest| finder.find(rest).is_some()),
+            };
+
+            if !found {
+                return None;
+            }
+        }
+
+        self.run_program(input, start, captures)
+    }
+
+    /// Like [`CompiledMatchExpression::run_leftmost`], for an input that is known to have the literal of
+    /// the prefilter.
+    pub(crate) fn run_program<'input>(
+        &self,
+        input: &'input str,
+        start: usize,
+        mut captures: Option<&mut Captures<'source, 'input>>,
+    ) -> Option<Match<'input>> {
+        let mut curr_position = start;
+        let mut legit_start = start;
+        let mut state = 0;
+        let mut capture_slice_start = None;
+        let mut capture_candidate_found = None;
+        let input_bytes = input.as_bytes();
+        let mut positions_tried = 1;
+        let mut last_start = start;
+
+        while state < self.program.len() && curr_position < input_bytes.len() {
+            if self.anchored && legit_start != start {
+                return None;
+            }
+
+            if legit_start != last_start {
+                last_start = legit_start;
+                positions_tried += 1;
+                if self.max_positions.is_some_and(|max| positions_tried > max) {
+                    return None;
+                }
+            }
+
+            match &self.program[state] {
+                Instruction::Literal(literal) => {
+                    // what follows a literal that isn't here can only match where it is next
+                    let (position, len) = self.find_text(input, curr_position, literal)?;
+
+                    if position > curr_position {
+                        legit_start = position;
+                    }
+
+                    state += 1;
+                    curr_position = position + len;
+                }
+                Instruction::Lookahead(text) => {
+                    if self.text_at(input, curr_position, text).is_some() {
+                        // checked, but not consumed
+                        state += 1;
+                    } else {
+                        if curr_position == legit_start {
+                            curr_position = step(input, curr_position);
+                        }
+                        legit_start = curr_position;
+                        state = 0;
+                        if let Some(captures) = captures.as_deref_mut() {
+                            captures.clear();
+                        }
+                    }
+                }
+                Instruction::Digit {
+                    identifier,
+                    optional,
+                } => {
+                    let digit = next_char(input, curr_position).filter(char::is_ascii_digit);
+
+                    if digit.is_some() {
+                        if let Some(captures) = captures.as_deref_mut() {
+                            captures.put(identifier, &input[curr_position..curr_position + 1]);
+                        }
+                        curr_position += 1;
+                        state += 1;
+                    } else if *optional {
+                        state += 1;
+                    } else {
+                        curr_position = step(input, curr_position);
+                        legit_start = curr_position;
+                        state = 0;
+                        if let Some(captures) = captures.as_deref_mut() {
+                            captures.clear();
+                        }
+                    }
+                }
+                Instruction::Scan {
+                    identifier,
+                    scanner,
+                    optional,
+                } => {
+                    let rest = input.get(curr_position..).unwrap_or_default();
+                    let scanned = match scanner {
+                        Scanner::WordNum => scan_word_number(input, curr_position)
+                            .map(|(len, n)| (len, Cow::Owned(n.to_string()))),
+                        _ => match scanner {
+                            Scanner::Exactly(identifier_type, len) => {
+                                scan_exactly(rest, *len, |c| identifier_type.accepts(c))
+                            }
+                            Scanner::Timestamp => scan_timestamp(rest.as_bytes()),
+                            Scanner::Size => size::scan_size(rest),
+                            Scanner::Custom(custom) => custom.scan(rest),
+                            _ => scan_date(rest.as_bytes()),
+                        }
+                        .map(|len| (len, Cow::Borrowed(&rest[..len]))),
+                    };
+
+                    match scanned {
+                        Some((len, captured)) => {
+                            if state == 0 {
+                                legit_start = curr_position;
+                            }
+                            if let Some(captures) = captures.as_deref_mut() {
+                                captures.put(identifier, captured);
+                            }
+                            curr_position += len;
+                            state += 1;
+                        }
+                        None if *optional => state += 1,
+                        None => {
+                            curr_position = step(input, curr_position);
+                            legit_start = curr_position;
+                            state = 0;
+                            if let Some(captures) = captures.as_deref_mut() {
+                                captures.clear();
+                            }
+                        }
+                    }
+                }
+                Instruction::Repeat {
+                    identifier,
+                    identifier_type,
+                    optional,
+                    yields_to,
+                } => {
+                    // a position inside of a multibyte character has no candidate
+                    let candidate =
+                        next_char(input, curr_position).filter(|c| identifier_type.accepts(*c));
+
+                    // gives where the capture ends
+                    let mut capture = |start: usize, curr_position: usize| {
+                        let end = match yields_to {
+                            Some(text) => give_back(input, start, curr_position, |position| {
+                                self.text_at(input, position, text).is_some()
+                            }),
+                            None => curr_position,
+                        };
+                        let captured = std::str::from_utf8(&input_bytes[start..end]).unwrap();
+                        if let Some(captures) = captures.as_deref_mut() {
+                            captures.put(identifier, captured);
+                        }
+                        end
+                    };
+
+                    if let Some(ch) = candidate {
+                        if capture_slice_start.is_none() {
+                            capture_slice_start = Some(curr_position);
+                            if state == 0 {
+                                legit_start = curr_position;
+                            }
+                        }
+
+                        capture_candidate_found = Some(true);
+                        curr_position += ch.len_utf8();
+
+                        if curr_position == input_bytes.len() {
+                            state += 1;
+                            curr_position = capture(capture_slice_start.unwrap(), curr_position);
+                            capture_slice_start = None;
+                            capture_candidate_found = None;
+                        }
+                    } else if *optional && capture_slice_start.is_none() {
+                        state += 1;
+                    } else if capture_candidate_found.is_some() {
+                        state += 1;
+                        curr_position = capture(capture_slice_start.unwrap(), curr_position);
+                        capture_slice_start = None;
+                        capture_candidate_found = None;
+                    } else {
+                        curr_position = step(input, curr_position);
+                        legit_start = curr_position;
+                        state = 0;
+                        if let Some(captures) = captures.as_deref_mut() {
+                            captures.clear();
+                        }
+                    }
+                }
+            }
+        }
+
+        // optional captures left over at the end of the input don't need to match anything
+        while self
+            .program
+            .get(state)
+            .is_some_and(Instruction::is_optional)
+        {
+            state += 1;
+        }
+
+        let is_whole = legit_start == start && curr_position == input.len();
+
+        if state == self.program.len() && (is_whole || !self.anchored) {
+            return Some(Match {
+                input,
+                start: legit_start,
+                end: curr_position,
+            });
+        }
+
+        None
+    }
+
+    /// Every match in the input, from the left.
+    pub fn find_iter<'input>(&self, input: &'input str) -> Matches<'_, 'input, 'source> {
+        Matches {
+            inner: self.captures_iter(input),
+        }
+    }
+
+    /// Every match in the input, from the left, including those that overlap; see
+    /// [`MatchExpression::find_overlapping_iter`].
+    pub fn find_overlapping_iter<'input>(
+        &self,
+        input: &'input str,
+    ) -> Matches<'_, 'input, 'source> {
+        let mut matches = self.find_iter(input);
+        matches.inner.overlapping = true;
+        matches
+    }
+
+    /// Every match in the input, from the left, with what each of them captured.
+    pub fn captures_iter<'input>(&self, input: &'input str) -> CaptureMatches<'_, 'input, 'source> {
+        CaptureMatches {
+            input,
+            compiled: Cow::Borrowed(self),
+            next_start: 0,
+            overlapping: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn passing_over_inputs_without_the_longest_literal() {
+        let compiled = MatchExpression::from_str("a(n:int)_final")
+            .unwrap()
+            .compile();
+
+        assert_eq!(compiled.prefilter.as_ref().unwrap().0, "_final");
+        assert!(compiled.find_at("a1_fin", 0).is_none());
+        assert_eq!(
+            compiled.find_at("xa12_final", 0).unwrap().as_str(),
+            "a12_final"
+        );
+        assert!(compiled.find_at("a1_final", 1).is_none());
+    }
+
+    #[test]
+    fn matching_in_any_case() {
+        let compiled = MatchExpression::from_str("img_(n:int)(?=.jpg)")
+            .unwrap()
+            .compile()
+            .case_insensitive(true);
+
+        assert_eq!(
+            compiled.find_at("IMG_12.JPG", 0).unwrap().as_str(),
+            "IMG_12"
+        );
+        assert_eq!(
+            compiled.find_at("x-Img_3.jpg", 0).unwrap().as_str(),
+            "Img_3"
+        );
+        assert!(compiled.find_at("IMG_12.png", 0).is_none());
+
+        let compiled = MatchExpression::from_str("été(n:int)")
+            .unwrap()
+            .compile()
+            .case_insensitive(true);
+        assert_eq!(compiled.find_at("ÉTÉ7", 0).unwrap().as_str(), "ÉTÉ7");
+    }
+
+    #[test]
+    fn matching_on_character_boundaries() {
+        for pattern in [
+            "a(d:dig)",
+            "(n:int)(?=—)",
+            "(t:alpha)-(h:hex)",
+            "x(w:wordnum)",
+            "(d:date)é",
+            "(s:size?)〰",
+        ] {
+            let compiled = MatchExpression::from_str(pattern).unwrap().compile();
+
+            for input in [
+                "a—a—a9",
+                "〰1—x〰",
+                "日本-ff〰",
+                "xé—two",
+                "ñ2023-08-01é",
+                "é12k〰",
+            ] {
+                let mut start = 0;
+                while let Some(m) = compiled.find_at(input, start) {
+                    assert!(input.is_char_boundary(m.start), "{pattern} on {input}");
+                    assert!(input.is_char_boundary(m.end), "{pattern} on {input}");
+                    start = m.end.max(m.start + 1);
+                    while !input.is_char_boundary(start) {
+                        start += 1;
+                    }
+                }
+            }
+        }
+
+        let compiled = MatchExpression::from_str("a(d:dig)").unwrap().compile();
+        assert_eq!(compiled.find_at("xaxa1", 0).unwrap().as_str(), "a1");
+    }
+
+    #[test]
+    fn checking_for_a_match() {
+        let compiled = MatchExpression::from_str("IMG_(n:int)").unwrap().compile();
+
+        assert!(compiled.is_match("trip/IMG_4032.jpg"));
+        assert!(!compiled.is_match("trip/IMG_.jpg"));
+        assert!(!compiled.is_match("DSC_1.jpg"));
+    }
+
+    #[test]
+    fn matching_anchored() {
+        let compiled = MatchExpression::from_str("ep(n:int)")
+            .unwrap()
+            .compile()
+            .anchored(true);
+
+        assert_eq!(compiled.find_at("ep12", 0).unwrap().as_str(), "ep12");
+        assert!(compiled.find_at("ep12.mp4", 0).is_none());
+        assert!(compiled.find_at("show-ep12", 0).is_none());
+        assert_eq!(compiled.find_at("show-ep12", 5).unwrap().as_str(), "ep12");
+    }
+
+    #[test]
+    fn giving_up_after_trying_some_positions() {
+        let compiled = MatchExpression::from_str("(n:int)").unwrap().compile();
+
+        let limited = compiled.clone().max_positions(Some(3));
+        assert_eq!(limited.find_at("ab12", 0).unwrap().as_str(), "12");
+        assert!(limited.find_at("abcdef12", 0).is_none());
+        assert_eq!(limited.find_at("abcdef12", 4).unwrap().as_str(), "12");
+
+        let unlimited = compiled.max_positions(None);
+        assert_eq!(unlimited.find_at("abcdef12", 0).unwrap().as_str(), "12");
+    }
+
+    #[test]
+    fn finding_the_last_match() {
+        let compiled = MatchExpression::from_str("(n:int)")
+            .unwrap()
+            .compile()
+            .rightmost(true);
+
+        let (m, captures) = compiled.find_at_capturing("v2-img-17", 0);
+        assert_eq!(m.unwrap().as_str(), "17");
+        assert_eq!(captures.get("n"), Some("17"));
+        assert_eq!(compiled.find_at("v2-img-17", 8).unwrap().as_str(), "7");
+        assert!(compiled.find_at("img", 0).is_none());
+        assert_eq!(compiled.find_iter("1-2-3").count(), 1);
+    }
+}
@@ -1,6 +1,8 @@
-use colored::Colorize;
+use std::ops::Range;
 
-use crate::lexer::{Token, TokenKind};
+use colored::{ColoredString, Colorize};
+
+use crate::lexer::{Lexer, SemanticKind, Token, TokenKind};
 
 pub type Result<'source, T> = std::result::Result<T, ParseError<'source>>;
 
@@ -13,6 +15,7 @@ pub enum ParseErrorKind<'source> {
         position: usize,
     },
     UnsupportedToken(Token<'source>),
+    UnsupportedTransform(Token<'source>),
     UnexpectedToken {
         unexpected: TokenKind,
         previous: TokenKind,
@@ -23,20 +26,27 @@ pub enum ParseErrorKind<'source> {
         declared: Vec<&'source str>,
         position: usize,
     },
+    /// A rule with nothing before its `->`, like `->foo`, which would match nothing useful.
+    EmptyMatchExpression {
+        position: usize,
+    },
 }
 
 impl TokenKind {
     fn description(&self) -> &str {
         use TokenKind::*;
 
-        return match self {
+        match self {
             Literal => "literal",
             Type => "type keyword",
             Ident => "identifier",
             Arrow => "pattern seperator",
+            Semicolon => "rule seperator",
+            Str => "string",
+            Lookahead => "lookahead",
             End => "end of expression",
             _ => "special character",
-        };
+        }
     }
 }
 
@@ -46,37 +56,218 @@ pub struct ParseError<'source> {
     pub(crate) kind: ParseErrorKind<'source>,
 }
 
+/// Color a part of an expression by what it is.
+fn highlight(text: &str, kind: Option<SemanticKind>) -> ColoredString {
+    match kind {
+        Some(SemanticKind::Literal) => text.yellow(),
+        Some(SemanticKind::Identifier) => text.blue(),
+        Some(SemanticKind::Type | SemanticKind::Transform) => text.purple(),
+        Some(SemanticKind::Keyword) => text.purple().bold(),
+        Some(SemanticKind::Operator) => text.red(),
+        Some(SemanticKind::String) => text.green(),
+        Some(SemanticKind::Comment) => text.dimmed(),
+        Some(SemanticKind::Punctuation) | None => text.normal(),
+    }
+}
+
+/// The most characters of a line shown with an error, so that a long expression doesn't wrap.
+const MAX_LINE_WIDTH: usize = 80;
+
 impl<'t> ParseError<'t> {
+    /// The range of the source that the error is about, which is at least one character long.
+    pub(crate) fn error_span(&self) -> Range<usize> {
+        let start = *self.error_location();
+        let len = match &self.kind {
+            ParseErrorKind::UnsupportedToken(t) | ParseErrorKind::UnsupportedTransform(t) => {
+                t.text.len()
+            }
+            ParseErrorKind::ExpectedToken { text, .. } => text.len(),
+            ParseErrorKind::UndeclaredIdentifier { ident, .. } => ident.len(),
+            ParseErrorKind::UnexpectedToken { .. } => 0,
+            ParseErrorKind::EmptyMatchExpression { .. } => "->".len(),
+        };
+
+        start..start + len.max(1)
+    }
+
     fn error_location(&self) -> &usize {
         match &self.kind {
             ParseErrorKind::UnsupportedToken(t) => &t.start,
+            ParseErrorKind::UnsupportedTransform(t) => &t.start,
             ParseErrorKind::ExpectedToken { position, .. } => position,
             ParseErrorKind::UnexpectedToken { position, .. } => position,
             ParseErrorKind::UndeclaredIdentifier { position, .. } => position,
+            ParseErrorKind::EmptyMatchExpression { position } => position,
         }
     }
 }
 
 impl<'t> std::error::Error for ParseError<'t> {}
 
+/// A [`ParseError`] that doesn't borrow the expression it came from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct OwnedParseError {
+    message: String,
+    plain_message: String,
+}
+
+impl OwnedParseError {
+    /// The error as it's displayed; see [`ParseError::render`].
+    pub fn render(&self, plain: bool) -> &str {
+        match plain {
+            true => &self.plain_message,
+            false => &self.message,
+        }
+    }
+}
+
+impl<'t> From<ParseError<'t>> for OwnedParseError {
+    fn from(e: ParseError<'t>) -> Self {
+        Self {
+            message: e.to_string(),
+            plain_message: e.render(true),
+        }
+    }
+}
+
+/// Every error, one after the other.
+impl<'t> From<Vec<ParseError<'t>>> for OwnedParseError {
+    fn from(errors: Vec<ParseError<'t>>) -> Self {
+        Self {
+            message: errors.iter().map(|e| e.to_string()).collect(),
+            plain_message: errors.iter().map(|e| e.render(true)).collect(),
+        }
+    }
+}
+
+impl std::error::Error for OwnedParseError {}
+
+impl std::fmt::Display for OwnedParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
 impl<'t> std::fmt::Display for ParseError<'t> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, false)
+    }
+}
+
+impl<'t> ParseError<'t> {
+    /// The line that the error is on, counted from 1, and its column on that line in characters,
+    /// counted from 0, as they are shown with the error.
+    pub fn line_and_column(&self) -> (usize, usize) {
+        let start = self.error_span().start.min(self.source.len());
+        let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+
+        (
+            self.source[..line_start].matches('\n').count() + 1,
+            self.source[line_start..start].chars().count(),
+        )
+    }
+
+    /// The error as it's displayed, with the part of the source that it's about. `plain` leaves
+    /// out colors, which are otherwise used unless they're turned off, e.g. with `NO_COLOR`.
+    pub fn render(&self, plain: bool) -> String {
+        let mut rendered = String::new();
+        self.write(&mut rendered, plain)
+            .expect("writing to a string shouldn't fail");
+        rendered
+    }
+
+    fn write(&self, f: &mut impl std::fmt::Write, plain: bool) -> std::fmt::Result {
         use ParseErrorKind::*;
 
-        writeln!(f, "\n{}", self.source.yellow())?;
+        let paint = |text: ColoredString| if plain { text.clear() } else { text };
+
+        let span = self.error_span();
+        let start = span.start.min(self.source.len());
+
+        // only the line with the error is shown, which matters for rules read from a file
+        let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.source[start..]
+            .find('\n')
+            .map_or(self.source.len(), |i| start + i);
+        let line = &self.source[line_start..line_end];
+        let (line_number, column) = self.line_and_column();
+        let width = self.source[start..span.end.min(line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        // and only the part of a long line around the error
+        let line_width = line.chars().count();
+        let shown_from = column
+            .saturating_sub(MAX_LINE_WIDTH / 2)
+            .min(line_width.saturating_sub(MAX_LINE_WIDTH));
+        let shown = line
+            .char_indices()
+            .skip(shown_from)
+            .take(MAX_LINE_WIDTH)
+            .map(|(i, c)| (line_start + i, c));
+        let mut shown_width = (line_width - shown_from).min(MAX_LINE_WIDTH);
+        let mut offset = column - shown_from;
+
+        writeln!(f)?;
+
+        if shown_from > 0 {
+            f.write_char('\u{2026}')?;
+            shown_width += 1;
+            offset += 1;
+        }
+
+        // the parts of the line are colored by what they are
+        let kinds: Vec<_> = Lexer::new(self.source).classify().collect();
+        let kind_at = |position: usize| {
+            kinds
+                .iter()
+                .find(|(span, _)| span.contains(&position))
+                .map(|(_, kind)| *kind)
+        };
+        let mut part = String::new();
+        let mut part_kind = None;
+
+        for (position, c) in shown {
+            let kind = kind_at(position);
+            if kind != part_kind && !part.is_empty() {
+                write!(f, "{}", paint(highlight(&part, part_kind)))?;
+                part.clear();
+            }
+            part_kind = kind;
+            part.push(c);
+        }
+
+        write!(f, "{}", paint(highlight(&part, part_kind)))?;
+
+        if line_width > shown_from + MAX_LINE_WIDTH {
+            f.write_char('\u{2026}')?;
+            shown_width += 1;
+        }
+
+        writeln!(f)?;
+
+        let width = width.min(shown_width.saturating_sub(offset)).max(1);
+        let padding = " ".repeat(offset);
 
-        let location = self.error_location();
+        writeln!(f, "{padding}{}", paint("^".repeat(width).red().bold()))?;
 
-        for _ in 0..*location {
-            write!(f, " ")?;
+        write!(f, "{padding}{} ", paint("\u{21B3}".red().bold()))?;
+
+        if self.source.contains('\n') {
+            write!(
+                f,
+                "{}:{} ",
+                paint("@line".red().bold()),
+                paint(line_number.to_string().bold())
+            )?;
         }
 
         write!(
             f,
-            "{} {}:{} ",
-            "\u{21B3}".red().bold(),
-            "@col".red().bold(),
-            location.to_string().bold()
+            "{}:{} ",
+            paint("@col".red().bold()),
+            paint(column.to_string().bold())
         )?;
 
         match &self.kind {
@@ -89,30 +280,57 @@ impl<'t> std::fmt::Display for ParseError<'t> {
                 write!(
                     f,
                     "expected {}, but found a {}, {}",
-                    expected.description().blue(),
-                    found.description().red(),
-                    format!("\"{text}\"").yellow()
+                    paint(expected.description().blue()),
+                    paint(found.description().red()),
+                    paint(format!("\"{text}\"").yellow())
                 )
             }
             UnsupportedToken(t) => {
                 let result = write!(
                     f,
                     "unsupported token: {} {}",
-                    t.kind.description().red(),
-                    format!("\"{}\"", t.text).yellow()
+                    paint(t.kind.description().red()),
+                    paint(format!("\"{}\"", t.text).yellow())
                 );
 
                 if let TokenKind::Type = t.kind {
                     return write!(
                         f,
-                        " - supported types are: {}, {}",
-                        "int".purple(),
-                        "dig".purple()
+                        " - supported types are: {}, {}, {}, {}, {}, {}, {}, {}, or one declared like {}",
+                        paint("int".purple()),
+                        paint("dig".purple()),
+                        paint("alpha".purple()),
+                        paint("hex".purple()),
+                        paint("date".purple()),
+                        paint("timestamp".purple()),
+                        paint("size".purple()),
+                        paint("wordnum".purple()),
+                        paint("type ep = int{2};".purple())
                     );
                 }
 
                 result
             }
+            UnsupportedTransform(t) => {
+                write!(
+                    f,
+                    "unsupported transform: {} - supported transforms are: {}, {}, {}, {}, {}, {}, a unit like {}, {}, {}, {}, {}, a range like {}, or a date format like {}",
+                    paint(format!("\"{}\"", t.text).yellow()),
+                    paint("upper".purple()),
+                    paint("lower".purple()),
+                    paint("title".purple()),
+                    paint("hex".purple()),
+                    paint("dec".purple()),
+                    paint("bytes".purple()),
+                    paint("MiB".purple()),
+                    paint("trim".purple()),
+                    paint("lpad 3 '0'".purple()),
+                    paint("rpad 3 '0'".purple()),
+                    paint("replace '_' '-'".purple()),
+                    paint("0..8".purple()),
+                    paint("%Y%m%d".purple())
+                )
+            }
             UnexpectedToken {
                 unexpected,
                 previous,
@@ -121,8 +339,8 @@ impl<'t> std::fmt::Display for ParseError<'t> {
                 write!(
                     f,
                     "unexpected {}, after a {}",
-                    unexpected.description().red(),
-                    previous.description().blue()
+                    paint(unexpected.description().red()),
+                    paint(previous.description().blue())
                 )
             }
             UndeclaredIdentifier {
@@ -131,14 +349,21 @@ impl<'t> std::fmt::Display for ParseError<'t> {
                 write!(
                     f,
                     "undeclared identifier {}; declared: {}",
-                    ident.to_string().red(),
+                    paint(ident.to_string().red()),
                     declared
                         .iter()
-                        .map(|i| i.blue().to_string())
+                        .map(|i| paint(i.blue()).to_string())
                         .collect::<Vec<String>>()
                         .join(", ")
                 )
             }
+            EmptyMatchExpression { .. } => {
+                write!(
+                    f,
+                    "empty match expression; there is nothing before the {} to match",
+                    paint(TokenKind::Arrow.description().blue())
+                )
+            }
         }
     }
 }
@@ -148,7 +373,7 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
-    use crate::parser::MatchAndReplaceExpression;
+    use crate::parser::{MatchAndReplaceExpression, MatchAndReplaceRules};
     use ParseErrorKind::*;
     use TokenKind::*;
 
@@ -245,6 +470,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rejecting_empty_match_expressions() {
+        assert_error!("->foo", EmptyMatchExpression { position: 0 });
+
+        let source = "a->b; ->c";
+        assert_eq!(
+            MatchAndReplaceRules::from_str(source).unwrap_err(),
+            ParseError {
+                source,
+                kind: EmptyMatchExpression { position: 6 }
+            }
+        );
+    }
+
     #[test]
     fn rejecting_undeclared_identifers() {
         assert_error!(
@@ -286,5 +525,163 @@ mod tests {
                 start: 4
             })
         );
+
+        assert_error!(
+            "t(n:dig{2})8",
+            ParseErrorKind::UnsupportedToken(Token {
+                kind: TokenKind::Type,
+                text: crate::lexer::TokenText::Slice("dig{2}"),
+                start: 4
+            })
+        );
+
+        assert_error!(
+            "type ep = num; (e:ep)->(e)",
+            ParseErrorKind::UnsupportedToken(Token {
+                kind: TokenKind::Type,
+                text: crate::lexer::TokenText::Slice("num"),
+                start: 10
+            })
+        );
+    }
+
+    #[test]
+    fn rejecting_unknown_transforms() {
+        assert_error!(
+            "(n:int)->(n:shout)",
+            UnsupportedTransform(Token {
+                kind: TokenKind::Type,
+                text: crate::lexer::TokenText::Slice("shout"),
+                start: 12
+            })
+        );
+
+        assert_error!(
+            "(n:int)->(n:8..2)",
+            UnsupportedTransform(Token {
+                kind: TokenKind::Type,
+                text: crate::lexer::TokenText::Slice("8..2"),
+                start: 12
+            })
+        );
+
+        assert_error!(
+            "(n:int)->(n:lpad x '0')",
+            UnsupportedTransform(Token {
+                kind: TokenKind::Type,
+                text: crate::lexer::TokenText::Slice("lpad x '0'"),
+                start: 12
+            })
+        );
+
+        assert_error!(
+            "(n:int)->(n:replace '_')",
+            UnsupportedTransform(Token {
+                kind: TokenKind::Type,
+                text: crate::lexer::TokenText::Slice("replace '_'"),
+                start: 12
+            })
+        );
+
+        assert_error!(
+            "(n:int)->(n:)",
+            ExpectedToken {
+                expected: Type,
+                found: Rparen,
+                text: ")",
+                position: 12
+            }
+        );
+    }
+
+    #[test]
+    fn expecting_one_rule() {
+        assert_error!(
+            "a->b; c->d",
+            ExpectedToken {
+                expected: End,
+                found: Semicolon,
+                text: ";",
+                position: 4
+            }
+        );
+    }
+
+    #[test]
+    fn expecting_replacement_exp_before_rule_seperator() {
+        let err = MatchAndReplaceRules::from_str("a->;b->c").unwrap_err();
+
+        assert_eq!(
+            err,
+            ParseError {
+                source: "a->;b->c",
+                kind: UnexpectedToken {
+                    unexpected: Semicolon,
+                    previous: Arrow,
+                    position: 3
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn rejecting_malformed_conditionals() {
+        assert_error!(
+            "(n:int?)->(n ? \"a\")",
+            ExpectedToken {
+                expected: Colon,
+                found: Rparen,
+                text: ")",
+                position: 18
+            }
+        );
+
+        assert_error!(
+            "(n:int?)->(n ? \"(x)\" : \"\")",
+            UndeclaredIdentifier {
+                ident: "x",
+                declared: vec!["n"],
+                position: 17
+            }
+        );
+    }
+
+    #[test]
+    fn underlining_the_whole_range() {
+        let err = MatchAndReplaceExpression::from_str("(n:int)->(n:shout)").unwrap_err();
+        assert_eq!(
+            err.render(true),
+            "\n(n:int)->(n:shout)\n            ^^^^^\n            \u{21B3} @col:12 unsupported transform: \"shout\" - supported transforms are: upper, lower, title, hex, dec, bytes, a unit like MiB, trim, lpad 3 '0', rpad 3 '0', replace '_' '-', a range like 0..8, or a date format like %Y%m%d"
+        );
+
+        let err = MatchAndReplaceRules::from_str("a->b;\n# a comment\nc(n:in)->d").unwrap_err();
+        let rendered = err.render(true);
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines[1], "c(n:in)->d");
+        assert_eq!(lines[2], "    ^^");
+        assert!(lines[3].starts_with("    \u{21B3} @line:3 @col:4 unsupported token"));
+
+        let long = format!("{}(n:int)->(m)", "a".repeat(100));
+        let err = MatchAndReplaceExpression::from_str(&long).unwrap_err();
+        let rendered = err.render(true);
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines[1].chars().count(), MAX_LINE_WIDTH + 1);
+        assert!(lines[1].starts_with('\u{2026}'));
+        assert!(lines[1].ends_with("(n:int)->(m)"));
+        assert_eq!(lines[2], format!("{}^", " ".repeat(79)));
+    }
+
+    #[test]
+    fn rendering_without_colors() {
+        let err = MatchAndReplaceExpression::from_str("(n:int)->(m)").unwrap_err();
+
+        colored::control::set_override(true);
+        let colored = err.render(false);
+        let plain = err.render(true);
+        colored::control::unset_override();
+
+        assert!(colored.contains('\x1b'));
+        assert!(!plain.contains('\x1b'));
+        assert_eq!(OwnedParseError::from(err).render(true), plain);
     }
 }
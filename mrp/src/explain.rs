@@ -0,0 +1,202 @@
+//! Describing expressions in words, to check what one does before running it.
+
+use crate::parser::{
+    AbstractMatchingExpression, AbstractReplaceExpression, CaptureType, MatchAndReplaceExpression,
+    MatchAndReplaceRules, Transform,
+};
+
+impl<'source> MatchAndReplaceExpression<'source> {
+    /// What the expression matches and what it replaces that with, in words; e.g. `match "hello",
+    /// then an integer as n, and replace it with "hi", then n`.
+    pub fn explain(&self) -> String {
+        let matched = sequence(&self.mex.expressions, explain_match, |e| match e {
+            AbstractMatchingExpression::Literal(text) => Some(text),
+            _ => None,
+        });
+
+        format!(
+            "match {matched}, and replace it with {}",
+            explain_replacement(&self.rex.expressions)
+        )
+    }
+}
+
+impl<'source> MatchAndReplaceRules<'source> {
+    /// Each rule in words, one per line.
+    pub fn explain(&self) -> String {
+        self.rules
+            .iter()
+            .map(|rule| rule.explain())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// The explained expressions, one after the other, where literals that were split up, like
+/// `photo` and `-` in `photo-`, are written as one.
+fn sequence<'e, T>(
+    expressions: &'e [T],
+    explain: impl Fn(&'e T) -> String,
+    literal: impl Fn(&'e T) -> Option<&'e str>,
+) -> String {
+    let mut parts = vec![];
+    let mut text = String::new();
+
+    for e in expressions {
+        match literal(e) {
+            Some(literal) => text.push_str(literal),
+            None => {
+                if !text.is_empty() {
+                    parts.push(format!("{:?}", std::mem::take(&mut text)));
+                }
+                parts.push(explain(e));
+            }
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(format!("{text:?}"));
+    }
+
+    parts.join(", then ")
+}
+
+fn explain_match(expression: &AbstractMatchingExpression) -> String {
+    match expression {
+        AbstractMatchingExpression::Literal(text) => format!("{text:?}"),
+        AbstractMatchingExpression::Capture {
+            identifier,
+            identifier_type,
+            optional,
+            length,
+        } => {
+            let mut explained = match length {
+                Some(length) => format!(
+                    "{} of exactly {length} characters as {identifier}",
+                    describe(identifier_type)
+                ),
+                None => format!("{} as {identifier}", describe(identifier_type)),
+            };
+
+            if *optional {
+                explained.push_str(", if there is one");
+            }
+
+            explained
+        }
+        AbstractMatchingExpression::Lookahead(text) => {
+            format!("a check that {text:?} follows, without matching it")
+        }
+    }
+}
+
+fn describe(identifier_type: &CaptureType) -> String {
+    match identifier_type {
+        CaptureType::Int => "an integer".to_string(),
+        CaptureType::Digit => "a digit".to_string(),
+        CaptureType::Alpha => "letters".to_string(),
+        CaptureType::Hex => "a hexadecimal number".to_string(),
+        CaptureType::Date => "a date".to_string(),
+        CaptureType::Timestamp => "a timestamp".to_string(),
+        CaptureType::Size => "a size".to_string(),
+        CaptureType::WordNum => "a number written as a word".to_string(),
+        CaptureType::Custom(custom) => format!("a {}", custom.name()),
+    }
+}
+
+fn explain_replacement(expressions: &[AbstractReplaceExpression]) -> String {
+    if expressions.is_empty() {
+        return "nothing".to_string();
+    }
+
+    sequence(expressions, explain_replace, |e| match e {
+        AbstractReplaceExpression::Literal(text) => Some(text),
+        _ => None,
+    })
+}
+
+fn explain_replace(expression: &AbstractReplaceExpression) -> String {
+    match expression {
+        AbstractReplaceExpression::Literal(text) => format!("{text:?}"),
+        AbstractReplaceExpression::Identifier(identifier) => identifier.to_string(),
+        AbstractReplaceExpression::Transformed {
+            identifier,
+            transform,
+        } => format!("{identifier} {}", explain_transform(transform)),
+        AbstractReplaceExpression::Fallback {
+            identifier,
+            default,
+        } => format!("{identifier}, or {default:?} if it wasn't captured"),
+        AbstractReplaceExpression::Counter => "the counter".to_string(),
+        AbstractReplaceExpression::Conditional {
+            identifier,
+            then,
+            otherwise,
+        } => format!(
+            "(if {identifier} was captured: {}; otherwise: {})",
+            explain_replacement(then),
+            explain_replacement(otherwise)
+        ),
+    }
+}
+
+fn explain_transform(transform: &Transform) -> String {
+    match transform {
+        Transform::Upper => "in upper case".to_string(),
+        Transform::Lower => "in lower case".to_string(),
+        Transform::Title => "in title case".to_string(),
+        Transform::Slice { start, end: None } => format!("from character {start} on"),
+        Transform::Slice {
+            start,
+            end: Some(end),
+        } => format!("from character {start} up to {end}"),
+        Transform::Hex => "in hexadecimal".to_string(),
+        Transform::Dec => "in decimal".to_string(),
+        Transform::DateFormat(format) => format!("formatted as {format:?}"),
+        Transform::Bytes => "in bytes".to_string(),
+        Transform::SizeIn(unit) => format!("in {unit}"),
+        Transform::Trim => "without whitespace around it".to_string(),
+        Transform::PadLeft { width, fill } => {
+            format!("padded to {width} characters with {fill:?} on the left")
+        }
+        Transform::PadRight { width, fill } => {
+            format!("padded to {width} characters with {fill:?} on the right")
+        }
+        Transform::Replace { from, to } => format!("with every {from:?} replaced by {to:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn explain(source: &str) -> String {
+        MatchAndReplaceRules::from_str(source).unwrap().explain()
+    }
+
+    #[test]
+    fn explaining_expressions() {
+        assert_eq!(
+            explain("hello(n:int)->hi(n)"),
+            "match \"hello\", then an integer as n, and replace it with \"hi\", then n"
+        );
+        assert_eq!(
+            explain("IMG_(n:int)->photo-(n)"),
+            "match \"IMG_\", then an integer as n, and replace it with \"photo-\", then n"
+        );
+        assert_eq!(
+            explain("(t:alpha{3})(n:int?)(?=.mp4)->(t:upper)(n|0)(#)"),
+            "match letters of exactly 3 characters as t, then an integer as n, if there is one, \
+            then a check that \".mp4\" follows, without matching it, and replace it with t in \
+            upper case, then n, or \"0\" if it wasn't captured, then the counter"
+        );
+        assert_eq!(
+            explain("(n:int?)->(n ? \"_(n:lpad 2 '0')\" : \"\"); a->b"),
+            "match an integer as n, if there is one, and replace it with (if n was captured: \
+            \"_\", then n padded to 2 characters with '0' on the left; otherwise: nothing)\n\
+            match \"a\", and replace it with \"b\""
+        );
+    }
+}
@@ -11,7 +11,14 @@ pub enum TokenKind {
     Type,
     Ident,
     Colon,
+    Pipe,
     Arrow,
+    Semicolon,
+    Question,
+    /// The `?=` that starts a lookahead, like `(?=.mp4)`
+    Lookahead,
+    /// Text between double quotes, not including the quotes
+    Str,
     End,
 }
 
@@ -60,6 +67,28 @@ impl<'source> Display for TokenText<'source> {
     }
 }
 
+/// What a part of an expression is, for highlighting it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SemanticKind {
+    /// Text that is matched or written as it is
+    Literal,
+    /// The name of a capture or a variable, like `n` in `(n:int)`
+    Identifier,
+    /// The type of a capture, like `int`, or the name of a declared one
+    Type,
+    /// A transform of a value, like `upper` or `lpad 3 '0'`
+    Transform,
+    /// The `type` that starts a declaration
+    Keyword,
+    /// The `->` between the sides of a rule, or the `;` between rules
+    Operator,
+    /// Characters like `(`, `:` and `|` that hold the other parts together
+    Punctuation,
+    /// The quotes around the branches of a conditional
+    String,
+    Comment,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Token<'source> {
     pub kind: TokenKind,
@@ -71,13 +100,39 @@ pub struct Token<'source> {
 pub struct Lexer<'source> {
     input: &'source [u8],
     position: usize,
+    /// Where the lexer stops reading, which is the end of the input unless the lexer was made
+    /// for only part of it.
+    end: usize,
+    /// Whether the lexer is between a `(` and its `)`.
+    in_parens: bool,
+    /// Whether a `#` can start a comment, which it can't in the branches of a conditional.
+    comments: bool,
 }
 
 impl<'source> Lexer<'source> {
     pub fn new(input: &'source str) -> Self {
+        Self {
+            comments: true,
+            ..Self::with_range(input, 0..input.len())
+        }
+    }
+
+    /// A lexer reading only part of the input, but still giving positions from the start of it.
+    pub(crate) fn with_range(input: &'source str, range: Range<usize>) -> Self {
         Self {
             input: input.as_bytes(),
-            position: 0,
+            position: range.start,
+            end: range.end,
+            in_parens: false,
+            comments: false,
+        }
+    }
+
+    /// Let a `#` start a comment, as it does for a lexer of the whole input.
+    pub(crate) fn with_comments(self) -> Self {
+        Self {
+            comments: true,
+            ..self
         }
     }
 
@@ -90,10 +145,10 @@ impl<'source> Lexer<'source> {
     }
 
     fn char_at(&self, position: usize) -> Option<&u8> {
-        if position < self.input.len() {
+        if position < self.end {
             return Some(&self.input[position]);
         }
-        return None;
+        None
     }
 
     fn ch(&self) -> Option<&u8> {
@@ -137,14 +192,83 @@ impl<'source> Lexer<'source> {
             self.step();
         }
 
-        return (start_pos, self.position + 1);
+        (start_pos, self.position + 1)
+    }
+
+    /// Skip whitespace that only spaces out the parts of a conditional, like in
+    /// `(n ? "a" : "b")`.
+    fn skip_conditional_whitespace(&mut self) {
+        let mut position = self.position;
+
+        while let Some(c) = self.char_at(position) {
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+            position += 1;
+        }
+
+        if let Some(b'?' | b'"' | b':' | b')') = self.char_at(position) {
+            self.position = position;
+        }
+    }
+
+    /// Whether a `#` at the given position starts a comment, which it does outside of parens
+    /// at the start of the input or after whitespace.
+    fn is_comment_start(&self, position: usize) -> bool {
+        self.comments
+            && !self.in_parens
+            && self.char_at(position) == Some(&b'#')
+            && (position == 0 || self.input[position - 1].is_ascii_whitespace())
+    }
+
+    /// Skip comments, which run from a `#` to the end of the line, and the whitespace around them.
+    fn skip_comments(&mut self) {
+        let mut skipped = false;
+
+        loop {
+            let mut position = self.position;
+
+            while self
+                .char_at(position)
+                .is_some_and(|c| c.is_ascii_whitespace())
+            {
+                position += 1;
+            }
+
+            if !self.is_comment_start(position) {
+                if skipped {
+                    self.position = position;
+                }
+                return;
+            }
+
+            skipped = true;
+
+            while self.char_at(position).is_some_and(|c| *c != b'\n') {
+                position += 1;
+            }
+
+            self.position = position;
+        }
     }
 
     pub fn next_token(&mut self) -> Token<'source> {
+        if self.in_parens {
+            self.skip_conditional_whitespace();
+        } else {
+            self.skip_comments();
+        }
+
         let t = match self.ch() {
             Some(ch) => match ch {
-                b'(' => self.char_token(TokenKind::Lparen),
-                b')' => self.char_token(TokenKind::Rparen),
+                b'(' => {
+                    self.in_parens = true;
+                    self.char_token(TokenKind::Lparen)
+                }
+                b')' => {
+                    self.in_parens = false;
+                    self.char_token(TokenKind::Rparen)
+                }
                 b'-' if self.if_peek(b'>') => {
                     let t = Token {
                         kind: TokenKind::Arrow,
@@ -155,6 +279,29 @@ impl<'source> Lexer<'source> {
                     t
                 }
                 b':' => self.char_token(TokenKind::Colon),
+                b'|' if self.in_parens => self.char_token(TokenKind::Pipe),
+                b'?' if self.if_previous(b'(') && self.if_peek(b'=') => {
+                    let t = Token {
+                        kind: TokenKind::Lookahead,
+                        text: TokenText::Slice(self.input_slice(self.position..self.position + 2)),
+                        start: self.position,
+                    };
+                    self.step();
+                    t
+                }
+                b'?' if self.in_parens && !self.if_previous(b':') => {
+                    self.char_token(TokenKind::Question)
+                }
+                b'"' if self.in_parens => self.string(),
+                b';' if !self.in_parens => {
+                    let t = self.char_token(TokenKind::Semicolon);
+                    // whitespace between rules isn't part of the next rule
+                    self.read_while(|c| c.is_ascii_whitespace());
+                    t
+                }
+                _ if self.in_parens && (self.if_previous(b'|') || self.if_previous(b'=')) => {
+                    self.fallback_value()
+                }
                 _ if self.if_previous(b':') => self.type_token(),
                 _ if self.if_previous(b'(') => self.identifier_token(),
                 _ => self.literal(),
@@ -173,7 +320,28 @@ impl<'source> Lexer<'source> {
 
     fn type_token(&mut self) -> Token<'source> {
         let start = self.position;
-        let (s, e) = self.read_while(|c| c.is_ascii_alphabetic());
+        let (s, mut e) = if let Some(b'%') = self.ch() {
+            // a date format, which may contain almost anything
+            self.read_while(|c| *c != b')')
+        } else {
+            self.read_while(|c| c.is_ascii_alphanumeric() || matches!(c, b'.' | b'?' | b'{' | b'}'))
+        };
+
+        // how much of a digest to keep, like `sha256:8`
+        if self.in_parens
+            && self.if_peek(b':')
+            && self
+                .char_at(self.position + 2)
+                .is_some_and(|c| c.is_ascii_digit())
+        {
+            self.step();
+            (_, e) = self.read_while(|c| c.is_ascii_digit());
+        }
+
+        if self.in_parens && self.arguments_follow() {
+            e = self.arguments();
+        }
+
         let slice = self.input_slice(s..e);
         Token {
             kind: TokenKind::Type,
@@ -182,9 +350,56 @@ impl<'source> Lexer<'source> {
         }
     }
 
+    /// Whether arguments, like the ones in `replace '_' '-'`, follow the current character.
+    fn arguments_follow(&self) -> bool {
+        let mut position = self.position + 1;
+
+        while let Some(b' ') = self.char_at(position) {
+            position += 1;
+        }
+
+        position > self.position + 1 && !matches!(self.char_at(position), Some(b')') | None)
+    }
+
+    /// Read the arguments of a function like `replace '_' '-'`, up to the closing paren, where
+    /// quoted arguments may contain a `)`. Returns where the last argument ends.
+    fn arguments(&mut self) -> usize {
+        let mut in_quotes = false;
+        let mut end = self.position + 1;
+
+        while let Some(&c) = self.peek_char() {
+            if c == b')' && !in_quotes {
+                break;
+            }
+
+            if c == b'\'' {
+                in_quotes = !in_quotes;
+            }
+
+            self.step();
+
+            if !c.is_ascii_whitespace() {
+                end = self.position + 1;
+            }
+        }
+
+        end
+    }
+
     fn identifier_token(&mut self) -> Token<'source> {
         let start = self.position;
-        let (s, e) = self.read_while(|c| c.is_ascii_alphabetic());
+        let (s, mut e) = self.read_while(|c| c.is_ascii_alphabetic());
+
+        // a variable of a group, like `exif.date`
+        while self.if_peek(b'.')
+            && self
+                .char_at(self.position + 2)
+                .is_some_and(|c| c.is_ascii_alphabetic())
+        {
+            self.step();
+            (_, e) = self.read_while(|c| c.is_ascii_alphabetic());
+        }
+
         let slice = self.input_slice(s..e);
 
         Token {
@@ -196,7 +411,42 @@ impl<'source> Lexer<'source> {
 
     fn literal(&mut self) -> Token<'source> {
         let start = self.position;
-        let (s, e) = self.read_while(|c| !matches!(c, b'(' | b')' | b':' | b'-'));
+        let (s, mut e) = self.read_while(|c| !matches!(c, b'(' | b')' | b':' | b'-' | b';'));
+
+        if let Some(comment_start) = (s + 1..e).find(|p| self.is_comment_start(*p)) {
+            // the whitespace before a comment isn't part of the literal
+            e = s + self.input_slice(s..comment_start).trim_end().len();
+            self.position = e - 1;
+        }
+
+        Token {
+            kind: TokenKind::Literal,
+            text: TokenText::Slice(self.input_slice(s..e)),
+            start,
+        }
+    }
+
+    fn string(&mut self) -> Token<'source> {
+        let start = self.position;
+        let (_, e) = self.read_while(|c| *c != b'"');
+
+        if self.peek_char().is_some() {
+            // onto the closing quote
+            self.step();
+        }
+
+        Token {
+            kind: TokenKind::Str,
+            text: TokenText::Slice(self.input_slice(start + 1..e)),
+            start,
+        }
+    }
+
+    /// Read everything up to the closing paren as a literal, so that a fallback value or a
+    /// lookahead may contain characters like `-` or `:`.
+    fn fallback_value(&mut self) -> Token<'source> {
+        let start = self.position;
+        let (s, e) = self.read_while(|c| *c != b')');
         Token {
             kind: TokenKind::Literal,
             text: TokenText::Slice(self.input_slice(s..e)),
@@ -204,6 +454,111 @@ impl<'source> Lexer<'source> {
         }
     }
 
+    /// Spans of the input, in order, with what each of them is; for highlighting an expression.
+    /// Only whitespace is left out.
+    pub fn classify(mut self) -> impl Iterator<Item = (Range<usize>, SemanticKind)> {
+        let mut spans = vec![];
+        self.classify_into(false, &mut spans);
+        spans.into_iter()
+    }
+
+    fn classify_into(
+        &mut self,
+        mut replacing: bool,
+        spans: &mut Vec<(Range<usize>, SemanticKind)>,
+    ) {
+        let mut previous_end = self.position;
+        let mut rule_start = true;
+
+        loop {
+            let token = self.next_token();
+            let start = token.start;
+
+            if self.comments {
+                self.classify_comments(previous_end..start, spans);
+            }
+
+            let kind = match token.kind {
+                TokenKind::End => return,
+                TokenKind::Literal if rule_start && !replacing => {
+                    match crate::parser::type_declaration(*token.text) {
+                        Some((name, type_text, offset)) => {
+                            let name_start = start + token.text.find(name).unwrap_or_default();
+                            let equals = start + token.text.find('=').unwrap_or_default();
+                            spans.push((start..start + "type".len(), SemanticKind::Keyword));
+                            spans.push((name_start..name_start + name.len(), SemanticKind::Type));
+                            spans.push((equals..equals + 1, SemanticKind::Punctuation));
+                            spans.push((
+                                start + offset..start + offset + type_text.len(),
+                                SemanticKind::Type,
+                            ));
+                            previous_end = start + token.text.len();
+                            continue;
+                        }
+                        None => SemanticKind::Literal,
+                    }
+                }
+                TokenKind::Literal => SemanticKind::Literal,
+                TokenKind::Ident => SemanticKind::Identifier,
+                TokenKind::Type if replacing => SemanticKind::Transform,
+                TokenKind::Type => SemanticKind::Type,
+                TokenKind::Arrow => {
+                    replacing = true;
+                    SemanticKind::Operator
+                }
+                TokenKind::Semicolon => {
+                    replacing = false;
+                    SemanticKind::Operator
+                }
+                TokenKind::Str => {
+                    let inner = start + 1..start + 1 + token.text.len();
+                    spans.push((start..start + 1, SemanticKind::String));
+                    Lexer::with_range(self.input(), inner.clone()).classify_into(true, spans);
+
+                    previous_end = inner.end;
+                    if self.char_at(inner.end) == Some(&b'"') {
+                        spans.push((inner.end..inner.end + 1, SemanticKind::String));
+                        previous_end += 1;
+                    }
+
+                    rule_start = false;
+                    continue;
+                }
+                TokenKind::Lparen
+                | TokenKind::Rparen
+                | TokenKind::Colon
+                | TokenKind::Pipe
+                | TokenKind::Question
+                | TokenKind::Lookahead => SemanticKind::Punctuation,
+            };
+
+            rule_start = token.kind == TokenKind::Semicolon;
+            previous_end = start + token.text.len();
+            spans.push((start..previous_end, kind));
+        }
+    }
+
+    /// Add the comments in a range that is between tokens.
+    fn classify_comments(
+        &self,
+        range: Range<usize>,
+        spans: &mut Vec<(Range<usize>, SemanticKind)>,
+    ) {
+        let mut position = range.start;
+
+        while position < range.end {
+            if self.is_comment_start(position) {
+                let end = (position..range.end)
+                    .find(|p| self.input[*p] == b'\n')
+                    .unwrap_or(range.end);
+                spans.push((position..end, SemanticKind::Comment));
+                position = end;
+            }
+
+            position += 1;
+        }
+    }
+
     fn char_token(&self, kind: TokenKind) -> Token<'source> {
         Token {
             kind,
@@ -225,6 +580,8 @@ mod tests {
                 Lparen => "(",
                 Rparen => ")",
                 Colon => ":",
+                Pipe => "|",
+                Semicolon => ";",
                 Arrow => "->",
                 _ => unreachable!("bad test case"),
             }),
@@ -232,7 +589,7 @@ mod tests {
         }
     }
 
-    fn token_string(kind: TokenKind, text: &str, start: usize) -> Token {
+    fn token_string(kind: TokenKind, text: &str, start: usize) -> Token<'_> {
         Token {
             kind,
             text: TokenText::Slice(text),
@@ -320,4 +677,199 @@ mod tests {
         assert_eq!(l.next_token(), token(Rparen, 12));
         assert_eq!(l.next_token(), token_string(Literal, "b", 13));
     }
+
+    #[test]
+    fn slice_range_after_colon() {
+        let mut l = Lexer::new("(h:0..8)");
+        assert_eq!(l.next_token(), token(Lparen, 0));
+        assert_eq!(l.next_token(), token_string(Ident, "h", 1));
+        assert_eq!(l.next_token(), token(Colon, 2));
+        assert_eq!(l.next_token(), token_string(Type, "0..8", 3));
+        assert_eq!(l.next_token(), token(Rparen, 7));
+    }
+
+    #[test]
+    fn optional_capture_and_fallback() {
+        let mut l = Lexer::new("a|(n:int?)->(n|n-a:0)|");
+        assert_eq!(l.next_token(), token_string(Literal, "a|", 0));
+        assert_eq!(l.next_token(), token(Lparen, 2));
+        assert_eq!(l.next_token(), token_string(Ident, "n", 3));
+        assert_eq!(l.next_token(), token(Colon, 4));
+        assert_eq!(l.next_token(), token_string(Type, "int?", 5));
+        assert_eq!(l.next_token(), token(Rparen, 9));
+        assert_eq!(l.next_token(), token(Arrow, 10));
+        assert_eq!(l.next_token(), token(Lparen, 12));
+        assert_eq!(l.next_token(), token_string(Ident, "n", 13));
+        assert_eq!(l.next_token(), token(Pipe, 14));
+        assert_eq!(l.next_token(), token_string(Literal, "n-a:0", 15));
+        assert_eq!(l.next_token(), token(Rparen, 20));
+        assert_eq!(l.next_token(), token_string(Literal, "|", 21));
+    }
+
+    #[test]
+    fn variables_of_a_group() {
+        let mut l = Lexer::new("(exif.date:%Y)(n.)");
+        assert_eq!(l.next_token(), token(Lparen, 0));
+        assert_eq!(l.next_token(), token_string(Ident, "exif.date", 1));
+        assert_eq!(l.next_token(), token(Colon, 10));
+        assert_eq!(l.next_token(), token_string(Type, "%Y", 11));
+        assert_eq!(l.next_token(), token(Rparen, 13));
+        assert_eq!(l.next_token(), token(Lparen, 14));
+        assert_eq!(l.next_token(), token_string(Ident, "n", 15));
+    }
+
+    #[test]
+    fn date_format_after_colon() {
+        let mut l = Lexer::new("(now:%Y-%m-%d %H:%M)");
+        assert_eq!(l.next_token(), token(Lparen, 0));
+        assert_eq!(l.next_token(), token_string(Ident, "now", 1));
+        assert_eq!(l.next_token(), token(Colon, 4));
+        assert_eq!(l.next_token(), token_string(Type, "%Y-%m-%d %H:%M", 5));
+        assert_eq!(l.next_token(), token(Rparen, 19));
+    }
+
+    #[test]
+    fn multiple_rules() {
+        let mut l = Lexer::new("a->b;  c->d;");
+        assert_eq!(l.next_token(), token_string(Literal, "a", 0));
+        assert_eq!(l.next_token(), token(Arrow, 1));
+        assert_eq!(l.next_token(), token_string(Literal, "b", 3));
+        assert_eq!(l.next_token(), token(Semicolon, 4));
+        assert_eq!(l.next_token(), token_string(Literal, "c", 7));
+        assert_eq!(l.next_token(), token(Arrow, 8));
+        assert_eq!(l.next_token(), token_string(Literal, "d", 10));
+        assert_eq!(l.next_token(), token(Semicolon, 11));
+        assert_eq!(l.next_token().kind, End);
+    }
+
+    #[test]
+    fn conditional() {
+        let mut l = Lexer::new("(n ? \"part(n)\" : \"\")x");
+        assert_eq!(l.next_token(), token(Lparen, 0));
+        assert_eq!(l.next_token(), token_string(Ident, "n", 1));
+        assert_eq!(l.next_token(), token_string(Question, "?", 3));
+        assert_eq!(l.next_token(), token_string(Str, "part(n)", 5));
+        assert_eq!(l.next_token(), token(Colon, 15));
+        assert_eq!(l.next_token(), token_string(Str, "", 17));
+        assert_eq!(l.next_token(), token(Rparen, 19));
+        assert_eq!(l.next_token(), token_string(Literal, "x", 20));
+    }
+
+    #[test]
+    fn lexing_a_range_of_the_input() {
+        let input = "(n ? \"a(n)\" : \"\")";
+        let mut l = Lexer::with_range(input, 6..10);
+        assert_eq!(l.next_token(), token_string(Literal, "a", 6));
+        assert_eq!(l.next_token(), token(Lparen, 7));
+        assert_eq!(l.next_token(), token_string(Ident, "n", 8));
+        assert_eq!(l.next_token(), token(Rparen, 9));
+        assert_eq!(l.next_token().kind, End);
+        assert_eq!(l.input(), input);
+    }
+
+    #[test]
+    fn type_declaration() {
+        let mut l = Lexer::new("type ep = int{2}; e(e:ep)");
+        assert_eq!(l.next_token(), token_string(Literal, "type ep = int{2}", 0));
+        assert_eq!(l.next_token(), token(Semicolon, 16));
+        assert_eq!(l.next_token(), token_string(Literal, "e", 18));
+        assert_eq!(l.next_token(), token(Lparen, 19));
+        assert_eq!(l.next_token(), token_string(Ident, "e", 20));
+        assert_eq!(l.next_token(), token(Colon, 21));
+        assert_eq!(l.next_token(), token_string(Type, "ep", 22));
+        assert_eq!(l.next_token(), token(Rparen, 24));
+    }
+
+    #[test]
+    fn lookahead() {
+        let mut l = Lexer::new("(n:int)(?=.mp4)");
+        assert_eq!(l.next_token(), token(Lparen, 0));
+        assert_eq!(l.next_token(), token_string(Ident, "n", 1));
+        assert_eq!(l.next_token(), token(Colon, 2));
+        assert_eq!(l.next_token(), token_string(Type, "int", 3));
+        assert_eq!(l.next_token(), token(Rparen, 6));
+        assert_eq!(l.next_token(), token(Lparen, 7));
+        assert_eq!(l.next_token(), token_string(Lookahead, "?=", 8));
+        assert_eq!(l.next_token(), token_string(Literal, ".mp4", 10));
+        assert_eq!(l.next_token(), token(Rparen, 14));
+        assert_eq!(l.next_token().kind, End);
+    }
+
+    #[test]
+    fn function_arguments() {
+        let mut l = Lexer::new("(s:replace ')' '-' )(n:int )");
+        assert_eq!(l.next_token(), token(Lparen, 0));
+        assert_eq!(l.next_token(), token_string(Ident, "s", 1));
+        assert_eq!(l.next_token(), token(Colon, 2));
+        assert_eq!(l.next_token(), token_string(Type, "replace ')' '-'", 3));
+        assert_eq!(l.next_token(), token(Rparen, 19));
+        assert_eq!(l.next_token(), token(Lparen, 20));
+        assert_eq!(l.next_token(), token_string(Ident, "n", 21));
+        assert_eq!(l.next_token(), token(Colon, 22));
+        assert_eq!(l.next_token(), token_string(Type, "int", 23));
+        assert_eq!(l.next_token(), token(Rparen, 27));
+    }
+
+    #[test]
+    fn comments() {
+        let mut l = Lexer::new("# counting\na->(#) # why\n  ;b#1->c # done");
+        assert_eq!(l.next_token(), token_string(Literal, "a", 11));
+        assert_eq!(l.next_token(), token(Arrow, 12));
+        assert_eq!(l.next_token(), token(Lparen, 14));
+        assert_eq!(l.next_token(), token_string(Ident, "#", 15));
+        assert_eq!(l.next_token(), token(Rparen, 16));
+        assert_eq!(l.next_token(), token(Semicolon, 26));
+        assert_eq!(l.next_token(), token_string(Literal, "b#1", 27));
+        assert_eq!(l.next_token(), token(Arrow, 30));
+        assert_eq!(l.next_token(), token_string(Literal, "c", 32));
+        assert_eq!(l.next_token().kind, End);
+    }
+
+    #[test]
+    fn classifying_for_highlighting() {
+        use SemanticKind as K;
+
+        let input = "type ep = int; # eps\ns(n:ep)->(n:upper)(n ? \"x(n)\" : \"\")";
+        let classified: Vec<_> = Lexer::new(input)
+            .classify()
+            .map(|(span, kind)| (&input[span], kind))
+            .collect();
+
+        assert_eq!(
+            classified,
+            vec![
+                ("type", K::Keyword),
+                ("ep", K::Type),
+                ("=", K::Punctuation),
+                ("int", K::Type),
+                (";", K::Operator),
+                ("# eps", K::Comment),
+                ("s", K::Literal),
+                ("(", K::Punctuation),
+                ("n", K::Identifier),
+                (":", K::Punctuation),
+                ("ep", K::Type),
+                (")", K::Punctuation),
+                ("->", K::Operator),
+                ("(", K::Punctuation),
+                ("n", K::Identifier),
+                (":", K::Punctuation),
+                ("upper", K::Transform),
+                (")", K::Punctuation),
+                ("(", K::Punctuation),
+                ("n", K::Identifier),
+                ("?", K::Punctuation),
+                ("\"", K::String),
+                ("x", K::Literal),
+                ("(", K::Punctuation),
+                ("n", K::Identifier),
+                (")", K::Punctuation),
+                ("\"", K::String),
+                (":", K::Punctuation),
+                ("\"", K::String),
+                ("\"", K::String),
+                (")", K::Punctuation),
+            ]
+        );
+    }
 }
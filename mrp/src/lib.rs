@@ -1,69 +1,353 @@
+mod cache;
 mod captures;
+mod chain;
+mod compile;
 mod error;
+mod explain;
 pub mod lexer;
 mod matcher;
 pub mod parser;
-
-use std::borrow::Cow;
+mod printer;
+mod registry;
+mod set;
+mod size;
+mod stream;
+mod transform;
+mod translate;
+mod variables;
+mod warning;
+
+use std::{
+    borrow::Cow,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 pub type Array<T> = Box<[T]>;
 
-use parser::{AbstractReplaceExpression, MatchAndReplaceExpression, MatchExpression};
+pub use cache::ExpressionCache;
+pub use captures::Captures;
+pub use chain::MatchAndReplaceChain;
+pub use compile::CompiledMatchExpression;
+pub use error::{OwnedParseError, ParseError};
+pub use matcher::{CaptureMatches, Match, Matches};
+use parser::{
+    AbstractReplaceExpression, MatchAndReplaceExpression, MatchAndReplaceRules, MatchExpression,
+    ReplaceExpression,
+};
+pub use registry::{CaptureTypeRegistry, CustomCaptureType};
+pub use set::MatchSet;
+pub use stream::{MatchedLine, ReaderMatches};
+pub use translate::FromRegexError;
+pub use variables::{Variables, BUILTIN_VARIABLES, DATE_TIME_FORMAT};
+pub use warning::{ParseWarning, ParseWarningKind};
 
 /// Representing a stragety by which to match and replace on a `string` value
 pub trait MatchAndReplaceStrategy<'input> {
     /// Match and replace
     fn apply(&self, value: &'input str) -> Option<std::borrow::Cow<'input, str>>;
+
+    /// Match and replace, given values for the built-in variables like `ext` and `stem`.
+    fn apply_with(
+        &self,
+        value: &'input str,
+        _variables: &Variables,
+    ) -> Option<std::borrow::Cow<'input, str>> {
+        self.apply(value)
+    }
+
+    /// Whether the replacement refers to the built-in variable `name`, so that callers can skip
+    /// working out values that won't be used.
+    fn uses_variable(&self, _name: &str) -> bool {
+        false
+    }
 }
 
-pub struct MatchAndReplacer<'source> {
-    mex: MatchExpression<'source>,
+/// A match expression, and the replacement for what it matches.
+struct Rule<'source> {
+    mex: CompiledMatchExpression<'source>,
     exprs: Array<AbstractReplaceExpression<'source>>,
+}
+
+impl<'source> From<MatchAndReplaceExpression<'source>> for Rule<'source> {
+    fn from(mrex: MatchAndReplaceExpression<'source>) -> Self {
+        Self {
+            mex: mrex.mex.compile(),
+            exprs: mrex.rex.expressions,
+        }
+    }
+}
+
+pub struct MatchAndReplacer<'source> {
+    /// Applied in order, each on the result of the ones before it.
+    rules: Array<Rule<'source>>,
     /// When true, this strategy will replace the matching range found, and strip everything else
     /// off.
     strip: bool,
+    global: bool,
+    /// The next value of the `(#)` variable; it is only advanced when the expression uses it.
+    counter: AtomicUsize,
+    /// Whether a replacement uses the `(#)` variable, found once since the rules don't change.
+    uses_counter: bool,
+    counter_step: usize,
+    /// Minimum number of digits to write the counter with, padding with zeroes.
+    counter_width: usize,
 }
 
 impl<'source> MatchAndReplacer<'source> {
     pub fn new(mrex: MatchAndReplaceExpression<'source>) -> Self {
-        Self {
-            mex: mrex.mex,
-            exprs: mrex.rex.expressions,
+        Self::with_rules(vec![mrex.into()])
+    }
+
+    /// A strategy applying each rule in turn, on the result of the ones before it.
+    pub fn from_rules(rules: MatchAndReplaceRules<'source>) -> Self {
+        Self::with_rules(rules.rules.into_iter().map(Rule::from).collect())
+    }
+
+    /// A strategy replacing each whole value with the template, like `(stem)-(#).(ext)`; see
+    /// [`Parser::parse_template`](parser::Parser::parse_template).
+    pub fn from_template(template: ReplaceExpression<'source>) -> Self {
+        let mut replacer = Self::with_rules(vec![Rule {
+            // matches nothing at the start of every value, and the rest is stripped off
+            mex: MatchExpression::new(vec![]).compile(),
+            exprs: template.expressions,
+        }]);
+        replacer.strip = true;
+        replacer
+    }
+
+    fn with_rules(rules: Vec<Rule<'source>>) -> Self {
+        let mut replacer = Self {
+            rules: rules.into(),
             strip: false,
-        }
+            global: false,
+            counter: AtomicUsize::new(1),
+            uses_counter: false,
+            counter_step: 1,
+            counter_width: 0,
+        };
+        replacer.uses_counter =
+            replacer.any_replacement_expr(|e| matches!(e, AbstractReplaceExpression::Counter));
+        replacer
     }
 
     pub fn set_strip(&mut self, s: bool) {
         self.strip = s;
     }
-}
 
-impl<'input> MatchAndReplaceStrategy<'input> for MatchAndReplacer<'input> {
-    fn apply(&self, value: &'input str) -> Option<std::borrow::Cow<'input, str>> {
-        match self.mex.find_at_capturing(value, 0) {
-            (None, _) => None,
-            (Some(m), captures) => {
-                let mut new = Cow::from(value);
-                let replacement_str: String = self
-                    .exprs
-                    .iter()
-                    .map(|e| match e {
-                        AbstractReplaceExpression::Literal(l) => *l,
-                        AbstractReplaceExpression::Identifier(i) => captures
-                            .get(i)
-                            .unwrap_or_else(|| panic!("'{i}' should have been captured")),
-                    })
-                    .collect();
-
-                if self.strip {
-                    new = Cow::from(replacement_str);
-                } else {
-                    new.to_mut().replace_range(m.start..m.end, &replacement_str);
-                }
+    /// When true, this strategy will replace every non-overlapping match, instead of only the
+    /// first one, as [`MatchAndReplacer::replace_all`] does.
+    pub fn set_global(&mut self, g: bool) {
+        self.global = g;
+    }
 
-                Some(new)
+    /// When true, this strategy only replaces values that a rule matches as a whole.
+    pub fn set_anchored(&mut self, anchored: bool) {
+        for rule in self.rules.iter_mut() {
+            rule.mex.anchored = anchored;
+        }
+    }
+
+    /// When true, the literals of the rules match text in any case.
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        for rule in self.rules.iter_mut() {
+            rule.mex.case_insensitive = case_insensitive;
+        }
+    }
+
+    /// When true, each rule replaces the last match in a value, instead of the first one.
+    pub fn set_rightmost(&mut self, rightmost: bool) {
+        for rule in self.rules.iter_mut() {
+            rule.mex.rightmost = rightmost;
+        }
+    }
+
+    /// Limit how many positions of a value each rule tries to match at, giving up on the rest;
+    /// see [`CompiledMatchExpression::max_positions`].
+    pub fn set_max_positions(&mut self, max_positions: Option<usize>) {
+        for rule in self.rules.iter_mut() {
+            rule.mex.max_positions = max_positions;
+        }
+    }
+
+    /// Configure the `(#)` variable to start at `start`, and increase by `step` for every value
+    /// replaced; written with at least `width` digits.
+    pub fn set_counter(&mut self, start: usize, step: usize, width: usize) {
+        self.counter = AtomicUsize::new(start);
+        self.counter_step = step;
+        self.counter_width = width;
+    }
+
+    /// Whether any replacement expression, including those in the branches of conditionals,
+    /// satisfies the predicate.
+    fn any_replacement_expr<P: Fn(&AbstractReplaceExpression) -> bool>(
+        &self,
+        predicate: P,
+    ) -> bool {
+        fn any<P: Fn(&AbstractReplaceExpression) -> bool>(
+            exprs: &[AbstractReplaceExpression],
+            predicate: &P,
+        ) -> bool {
+            exprs.iter().any(|e| match e {
+                AbstractReplaceExpression::Conditional {
+                    then, otherwise, ..
+                } => predicate(e) || any(then, predicate) || any(otherwise, predicate),
+                _ => predicate(e),
+            })
+        }
+
+        self.rules.iter().any(|r| any(&r.exprs, &predicate))
+    }
+
+    /// Match and replace with one rule, on every match when `all` is true, or the first one
+    /// otherwise; `count` is taken from the counter the first time it's needed.
+    fn apply_rule(
+        &self,
+        rule: &Rule<'source>,
+        value: &str,
+        variables: &Variables,
+        count: &mut Option<usize>,
+        all: bool,
+    ) -> Option<String> {
+        let limit = if all { usize::MAX } else { 1 };
+        let mut found = rule.mex.captures_iter(value).take(limit).peekable();
+
+        found.peek()?;
+
+        let count = *count.get_or_insert_with(|| {
+            if self.uses_counter {
+                self.counter.fetch_add(self.counter_step, Ordering::Relaxed)
+            } else {
+                0
+            }
+        });
+
+        // built in one pass, with what's between the matches unless it's stripped off
+        let mut new = String::with_capacity(value.len());
+        let mut last_end = 0;
+
+        for (m, captures) in found {
+            if !self.strip {
+                new.push_str(&value[last_end..m.start]);
+            }
+            new.push_str(&self.replacement(&rule.exprs, &captures, variables, count));
+            last_end = m.end;
+        }
+
+        if !self.strip {
+            new.push_str(&value[last_end..]);
+        }
+
+        Some(new)
+    }
+
+    /// Apply each rule in turn, on the result of the ones before it.
+    fn apply_rules<'v>(
+        &self,
+        value: &'v str,
+        variables: &Variables,
+        all: bool,
+    ) -> Option<Cow<'v, str>> {
+        let mut current = Cow::Borrowed(value);
+        let mut count = None;
+        let mut replaced = false;
+
+        for rule in self.rules.iter() {
+            if let Some(new) = self.apply_rule(rule, &current, variables, &mut count, all) {
+                current = Cow::Owned(new);
+                replaced = true;
             }
         }
+
+        replaced.then_some(current)
+    }
+
+    /// Match and replace every non-overlapping match in the value, whether or not the strategy
+    /// was set to be global.
+    pub fn replace_all<'v>(&self, value: &'v str) -> Option<Cow<'v, str>> {
+        self.replace_all_with(value, &Variables::new())
+    }
+
+    /// Like [`MatchAndReplacer::replace_all`], given values for the built-in variables.
+    pub fn replace_all_with<'v>(
+        &self,
+        value: &'v str,
+        variables: &Variables,
+    ) -> Option<Cow<'v, str>> {
+        self.apply_rules(value, variables, true)
+    }
+
+    fn replacement(
+        &self,
+        exprs: &[AbstractReplaceExpression],
+        captures: &Captures,
+        variables: &Variables,
+        count: usize,
+    ) -> String {
+        // an identifier without a value comes from an optional capture that was skipped,
+        // or a built-in variable that the caller didn't provide
+        let captured = |i: &str| captures.get(i).or_else(|| variables.get(i));
+
+        let mut replacement_str = String::new();
+
+        for e in exprs.iter() {
+            match e {
+                AbstractReplaceExpression::Literal(l) => replacement_str.push_str(l),
+                AbstractReplaceExpression::Identifier(i) => {
+                    replacement_str.push_str(captured(i).unwrap_or_default())
+                }
+                AbstractReplaceExpression::Transformed {
+                    identifier,
+                    transform,
+                } => replacement_str
+                    .push_str(&transform.apply(captured(identifier).unwrap_or_default())),
+                AbstractReplaceExpression::Fallback {
+                    identifier,
+                    default,
+                } => replacement_str.push_str(captured(identifier).unwrap_or(default)),
+                AbstractReplaceExpression::Counter => replacement_str.push_str(&format!(
+                    "{:0width$}",
+                    count,
+                    width = self.counter_width
+                )),
+                AbstractReplaceExpression::Conditional {
+                    identifier,
+                    then,
+                    otherwise,
+                } => {
+                    let branch = match captured(identifier) {
+                        Some(_) => then,
+                        None => otherwise,
+                    };
+                    replacement_str.push_str(&self.replacement(branch, captures, variables, count))
+                }
+            }
+        }
+
+        replacement_str
+    }
+}
+
+impl<'input, 'source> MatchAndReplaceStrategy<'input> for MatchAndReplacer<'source> {
+    fn apply(&self, value: &'input str) -> Option<std::borrow::Cow<'input, str>> {
+        self.apply_with(value, &Variables::new())
+    }
+
+    fn uses_variable(&self, name: &str) -> bool {
+        self.any_replacement_expr(|e| match e {
+            AbstractReplaceExpression::Identifier(i) => *i == name,
+            AbstractReplaceExpression::Transformed { identifier, .. }
+            | AbstractReplaceExpression::Fallback { identifier, .. }
+            | AbstractReplaceExpression::Conditional { identifier, .. } => *identifier == name,
+            _ => false,
+        })
+    }
+
+    fn apply_with(
+        &self,
+        value: &'input str,
+        variables: &Variables,
+    ) -> Option<std::borrow::Cow<'input, str>> {
+        self.apply_rules(value, variables, self.global)
     }
 }
 
@@ -72,17 +356,18 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
 
     impl<'source> MatchAndReplacer<'source> {
-        fn apply_all(&mut self, values: Vec<&'source str>) -> Vec<String> {
+        fn apply_all(&mut self, values: &[&'source str], variables: &Variables) -> Vec<String> {
             let mut replaced = vec![];
             for value in values {
-                if let Some(v) = self.apply(value) {
+                if let Some(v) = self.apply_with(value, variables) {
                     replaced.push(v.to_string())
                 }
             }
 
-            return replaced;
+            replaced
         }
     }
 
@@ -95,48 +380,102 @@ mod tests {
         assert_eq!(strat.apply("lit12").unwrap(), "lul12");
     }
 
-    #[test]
-    fn test_mrp_application() {
-        let input = "(num:int)asdf->lul(num)";
-        let expression = MatchAndReplaceExpression::from_str(input).unwrap();
-        let mut strat = MatchAndReplacer::new(expression);
-
-        let treated = strat.apply_all(vec!["124asdf", "3asdfwery", "lk234asdfas"]);
-
-        assert_eq!(treated, vec!["lul124", "lul3wery", "lklul234as"]);
-
-        let expression = MatchAndReplaceExpression::from_str("hello(as:dig)->oh(as)hi").unwrap();
-
-        let mut strat = MatchAndReplacer::new(expression);
-
-        let treated = strat.apply_all(vec!["hello5", "ashello090", "hello345hello"]);
-
-        assert_eq!(treated, vec!["oh5hi", "asoh0hi90", "oh3hi45hello"]);
-    }
-
-    #[test]
-    fn test_mrp_application_stripping() {
-        let expression = MatchAndReplaceExpression::from_str("hello(as:dig)->oh(as)hi").unwrap();
-
-        let mut strat = MatchAndReplacer::new(expression);
-
-        strat.set_strip(true);
+    /// How a strategy is set up before it's applied.
+    type Setup = fn(&mut MatchAndReplacer);
 
-        let treated = strat.apply_all(vec!["hello5", "ashello090", "hello345hello"]);
+    /// The rules, how the strategy is set up, the variables, the values it's applied to, and what
+    /// the ones that it matches are replaced with.
+    type Case<'a> = (
+        &'a str,
+        Setup,
+        &'a [(&'a str, &'a str)],
+        &'a [&'a str],
+        &'a [&'a str],
+    );
 
-        assert_eq!(treated, vec!["oh5hi", "oh0hi", "oh3hi"]);
-    }
+    const AS_IS: Setup = |_| {};
+    const STRIPPING: Setup = |strat| strat.set_strip(true);
+    const GLOBALLY: Setup = |strat| strat.set_global(true);
 
     #[test]
-    fn test_mrp_application_with_multi_digits_and_stripping() {
-        let expression = MatchAndReplaceExpression::from_str("(n:int)->step(n)").unwrap();
-        let mut strat = MatchAndReplacer::new(expression);
-
-        strat.set_strip(true);
+    fn test_mrp_application() {
+        #[rustfmt::skip]
+        let cases: &[Case] = &[
+            ("(num:int)asdf->lul(num)", AS_IS, &[],
+                &["124asdf", "3asdfwery", "lk234asdfas"], &["lul124", "lul3wery", "lklul234as"]),
+            ("hello(as:dig)->oh(as)hi", AS_IS, &[],
+                &["hello5", "ashello090", "hello345hello"], &["oh5hi", "asoh0hi90", "oh3hi45hello"]),
+            // stripping
+            ("hello(as:dig)->oh(as)hi", STRIPPING, &[],
+                &["hello5", "ashello090", "hello345hello"], &["oh5hi", "oh0hi", "oh3hi"]),
+            ("(n:int)->step(n)", STRIPPING, &[], &["f1", "f11", "f99"], &["step1", "step11", "step99"]),
+            // case transforms
+            ("(name:alpha)-(n:int)->(n)-(name:title)", AS_IS, &[],
+                &["SHOUTING-01.txt", "quiet-2"], &["01-Shouting.txt", "2-Quiet"]),
+            ("(a:alpha)_(b:alpha)->(a:lower)_(b:upper)", AS_IS, &[], &["HELLO_world"], &["hello_WORLD"]),
+            ("(t:alpha)-(n:int)->(n)-(t:upper)", AS_IS, &[],
+                &["été-01.txt", "日本-2.txt"], &["01-ÉTÉ.txt", "2-日本.txt"]),
+            // fallbacks and conditionals
+            ("ep(n:int?)-(t:alpha)->(t)-(n|0)", AS_IS, &[], &["ep3-intro", "ep-outro"], &["intro-3", "outro-0"]),
+            ("ep(n:int?)->(n)x", AS_IS, &[], &["ep.mp4"], &["x.mp4"]),
+            ("(t:alpha)-(n:int?)->(t:lower)_(n ? \"part(n:hex)\" : \"single\")", AS_IS, &[],
+                &["Intro-12.mkv", "Outro-.mkv"], &["intro_partc.mkv", "outro_single.mkv"]),
+            // the counter
+            ("IMG_(n:int)->photo-(#)", AS_IS, &[], &["IMG_4032", "DSC_1", "IMG_4077"], &["photo-1", "photo-2"]),
+            ("(n:int)->(#)", |strat| strat.set_counter(10, 5, 3), &[],
+                &["a1", "b2", "c3"], &["a010", "b015", "c020"]),
+            // variables
+            ("(n:int)->(parent)_(n).(ext)", STRIPPING, &[("ext", "mp3"), ("parent", "album")],
+                &["track7.mp3"], &["album_7.mp3"]),
+            ("(ext:alpha)->(ext:upper)", AS_IS, &[("ext", "mp3")], &["a"], &["A"]),
+            ("(n:int)->(now:%Y%m%d)-(n)", AS_IS, &[("now", "2023-08-01T12:30:00")],
+                &["backup1"], &["backup20230801-1"]),
+            ("(n:alpha)->(hash:sha256:8)-(hash:sha256)", AS_IS, &[("hash", "9f86d081884c7d659a2feaa0c55ad015")],
+                &["cache"], &["9f86d081-9f86d081884c7d659a2feaa0c55ad015"]),
+            ("(n:int)->(stem:replace '_' ' ')-(n:lpad 3 '0')(ext:rpad 4 '~')", STRIPPING,
+                &[("ext", "mp"), ("stem", "my_track_7")], &["my_track_7.mp"], &["my track 7-007mp~~"]),
+            // capture types
+            ("frame_0x(h:hex)->frame_(h:dec)", AS_IS, &[],
+                &["frame_0x0a.png", "frame_0x1F.png"], &["frame_10.png", "frame_31.png"]),
+            ("(n:int)->0x(n:hex)", AS_IS, &[], &["addr4096"], &["addr0x1000"]),
+            ("report_(d:date)->(d:%d-%m-%Y)_report", AS_IS, &[],
+                &["report_2023-08-01.csv", "report_2023-02-30.csv"], &["01-08-2023_report.csv"]),
+            ("chapter-(n:wordnum)->chapter-(n:lpad 2 '0')", AS_IS, &[],
+                &["chapter-three.md", "chapter-twenty-one.md"], &["chapter-03.md", "chapter-21.md"]),
+            ("server_(t:timestamp)->(t:%Y%m%d_%H%M)", AS_IS, &[],
+                &["server_2023-08-01T12:30:00+02:00.log"], &["20230801_1230.log"]),
+            ("(s:size)->(s:MiB)", AS_IS, &[], &["export_1.5GiB.csv", "export_10.csv"], &["export_1536MiB.csv"]),
+            ("(n:int)(?=.mp4)->ep(n)", AS_IS, &[], &["show-1.mp4", "show-2.mkv"], &["show-ep1.mp4"]),
+            // every match, and only whole values
+            ("(n:int)-><(n)>", GLOBALLY, &[], &["a1b22c333", "none", "7"], &["a<1>b<22>c<333>", "<7>"]),
+            ("x(d:dig)->(d)", |strat| { strat.set_global(true); strat.set_strip(true) }, &[],
+                &["x1yx2—x3"], &["123"]),
+            ("(n:int?)->_", GLOBALLY, &[], &["a—"], &["_a_—_"]),
+            ("(t:alpha)_(n:int)->(n)-(t)", |strat| strat.set_anchored(true), &[],
+                &["intro_1", "intro_1.mkv", "1_intro_2"], &["1-intro"]),
+            // more than one rule, type aliases and comments
+            ("a(n:int)->b(n); b(n:int)->c(n)-(#); foo->bar", AS_IS, &[],
+                &["a1", "b2foo", "foo", "none"], &["c1-1", "c2-2bar", "bar"]),
+            ("type ep = int{2}; s(s:ep)e(e:ep)->(s)x(e); show_->tv_", AS_IS, &[],
+                &["show_s01e0203.mkv", "s1e2.mkv"], &["tv_01x0203.mkv"]),
+            ("# episodes are numbered with two digits
+              type ep = int{2}; # like 01
+              e(e:ep)->episode-(#) # the counter, not a comment
+              ; track#(n:int)->(n ? \"#(n)\" : \"\") # keeps the # inside a string", AS_IS, &[],
+                &["e01.mkv", "track#3.mp3"], &["episode-1.mkv", "#3.mp3"]),
+        ];
 
-        let treated = strat.apply_all(vec!["f1", "f11", "f99"]);
+        for (rules, setup, put, values, replaced) in cases {
+            let mut strat =
+                MatchAndReplacer::from_rules(MatchAndReplaceRules::from_str(rules).unwrap());
+            setup(&mut strat);
+            let mut variables = Variables::new();
+            for (name, value) in put.iter() {
+                variables.put(name, *value);
+            }
 
-        assert_eq!(treated, vec!["step1", "step11", "step99"]);
+            assert_eq!(strat.apply_all(values, &variables), *replaced, "{}", rules);
+        }
     }
 
     #[test]
@@ -158,4 +497,71 @@ mod tests {
             assert_eq!(strat.apply(input).unwrap(), output);
         }
     }
+
+    #[test]
+    fn test_mrp_variables_used() {
+        let expression = MatchAndReplaceExpression::from_str("(n:int)->(now:%Y%m%d)-(n)").unwrap();
+        let strat = MatchAndReplacer::new(expression);
+
+        assert!(strat.uses_variable("now"));
+        assert!(!strat.uses_variable("mtime"));
+
+        let expression = MatchAndReplaceExpression::from_str("(n:alpha)->(hash:sha256:8)").unwrap();
+        assert!(MatchAndReplacer::new(expression).uses_variable("hash"));
+    }
+
+    #[test]
+    fn test_mrp_application_of_a_template() {
+        let input = "(stem)-(#)(ext ? \".(ext)\" : \"\")";
+        let template = Parser::new(Lexer::new(input)).parse_template().unwrap();
+        let strat = MatchAndReplacer::from_template(template);
+
+        let mut variables = Variables::new();
+        variables.put("stem", "IMG_4032");
+        variables.put("ext", "jpg");
+        assert_eq!(
+            strat.apply_with("IMG_4032.jpg", &variables).unwrap(),
+            "IMG_4032-1.jpg"
+        );
+
+        let mut variables = Variables::new();
+        variables.put("stem", "notes");
+        assert_eq!(strat.apply_with("notes", &variables).unwrap(), "notes-2");
+        assert_eq!(strat.apply("").unwrap(), "-3");
+    }
+
+    #[test]
+    fn test_mrp_replacing_all_matches() {
+        let rules = MatchAndReplaceRules::from_str("(n:int)-><(n)>; a->A").unwrap();
+        let strat = MatchAndReplacer::from_rules(rules);
+
+        assert_eq!(strat.replace_all("a1b22a333").unwrap(), "A<1>b<22>A<333>");
+        assert_eq!(strat.apply("a1b22a333").unwrap(), "A<1>b22a333");
+        assert!(strat.replace_all("none").is_none());
+    }
+
+    #[test]
+    fn test_mrp_application_with_registered_types() {
+        let mut registry = CaptureTypeRegistry::new();
+        registry.register("semver", |input| {
+            let mut len = 0;
+            for part in 0..3 {
+                let digits = input[len..].bytes().take_while(u8::is_ascii_digit).count();
+                let dot = (part < 2) as usize;
+                if digits == 0 || (dot == 1 && input.as_bytes().get(len + digits) != Some(&b'.')) {
+                    return None;
+                }
+                len += digits + dot;
+            }
+            Some(len)
+        });
+
+        let mut parser = Parser::new(Lexer::new("app-(v:semver)->app_v(v)"));
+        parser.set_registry(registry);
+        let mut strat = MatchAndReplacer::new(parser.parse().unwrap());
+
+        let treated = strat.apply_all(&["app-1.2.3.tar", "app-1.2.tar"], &Variables::new());
+
+        assert_eq!(treated, vec!["app_v1.2.3.tar"]);
+    }
 }
@@ -1,178 +1,361 @@
+use std::{borrow::Cow, ops::Range};
+
 use crate::{
     captures::Captures,
-    parser::{AbstractMatchingExpression, CaptureType, MatchExpression},
+    compile::CompiledMatchExpression,
+    parser::{CaptureType, MatchExpression},
 };
 
 pub struct Match<'input> {
-    input: &'input str,
+    pub(crate) input: &'input str,
     pub start: usize,
     pub end: usize,
 }
 
 impl<'input> Match<'input> {
-    pub fn as_str(&self) -> &str {
+    pub fn as_str(&self) -> &'input str {
         &self.input[self.start..self.end]
     }
+
+    /// The range of bytes of the input that was matched.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Length in bytes of what was matched.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Whether nothing was matched, as by an expression of only optional captures.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
 }
 
 impl<'source> MatchExpression<'source> {
+    /// Find the leftmost-first match, and what it captured; the expression is compiled for each
+    /// call, so [`MatchExpression::compile`] it first to match it on many inputs.
     pub fn find_at_capturing<'input>(
         &self,
         input: &'input str,
         start: usize,
     ) -> (Option<Match<'input>>, Captures<'source, 'input>) {
-        let mut curr_position = start;
-        let mut legit_start = start;
-        let mut state = 0;
-        let mut capture_slice_start = None;
-        let mut capture_candidate_found = None;
-        let input_bytes = input.as_bytes();
-
-        let mut captures = Captures::new();
-
-        while state < self.expressions.len() && curr_position < input_bytes.len() {
-            let e = self.get_expression(state).unwrap();
-
-            match e {
-                AbstractMatchingExpression::Literal(literal) => {
-                    let slice_end = literal.len() + curr_position;
-                    let slice_range = curr_position..slice_end;
-
-                    let mut update_pointers = || {
-                        curr_position += 1;
-                        legit_start = curr_position;
-                    };
-
-                    if slice_range.end > input_bytes.len() {
-                        update_pointers();
-                        continue;
-                    }
-
-                    let slice = &input_bytes[slice_range];
-
-                    let is_match = slice == literal.as_bytes();
-
-                    if is_match {
-                        state += 1;
-                        curr_position += literal.len();
-                    } else {
-                        update_pointers();
-                        continue;
-                    }
-                }
-                AbstractMatchingExpression::Capture {
-                    identifier,
-                    identifier_type,
-                } => match identifier_type {
-                    CaptureType::Digit => {
-                        let ch = input_bytes[curr_position];
-                        let ch_str = &input_bytes[curr_position..curr_position + 1];
-
-                        if ch.is_ascii_digit() {
-                            curr_position += 1;
-                            state += 1;
-                            let captured_digit = &std::str::from_utf8(ch_str).unwrap();
-                            captures.put(identifier, captured_digit);
-                        } else {
-                            curr_position += 1;
-                            state = 0;
-                        }
-                    }
-                    CaptureType::Int => {
-                        let ch = input_bytes[curr_position] as char;
-
-                        let mut capture = |start: usize, curr_position: usize| {
-                            let captured_int =
-                                &std::str::from_utf8(&input_bytes[start..curr_position]).unwrap();
-                            captures.put(identifier, captured_int);
-                        };
-
-                        if ch.is_ascii_digit() {
-                            if capture_slice_start.is_none() {
-                                capture_slice_start = Some(curr_position);
-                                if state == 0 {
-                                    legit_start = curr_position;
-                                }
-                            }
-
-                            capture_candidate_found = Some(true);
-                            curr_position += 1;
-
-                            if curr_position == input_bytes.len() {
-                                state += 1;
-                                capture(capture_slice_start.unwrap(), curr_position);
-                                capture_slice_start = None;
-                            }
-                        } else if capture_candidate_found.is_some() {
-                            state += 1;
-                            capture(capture_slice_start.unwrap(), curr_position);
-                            capture_slice_start = None;
-                        } else {
-                            curr_position += 1;
-                            state = 0;
-                        }
-                    }
-                },
+        self.compile().find_at_capturing(input, start)
+    }
+
+    /// Find the leftmost-first match in the input starting at the given position
+    pub fn find_at<'input>(&self, input: &'input str, start: usize) -> Option<Match<'input>> {
+        self.compile().find_at(input, start)
+    }
+
+    /// Whether the expression matches anywhere in the input, without capturing anything.
+    pub fn is_match(&self, input: &str) -> bool {
+        self.compile().is_match(input)
+    }
+
+    /// Every match in the input, from the left; the expression is only borrowed, but compiled
+    /// for each call, so use [`CompiledMatchExpression::find_iter`] to search many inputs.
+    pub fn find_iter<'input>(&self, input: &'input str) -> Matches<'static, 'input, 'source> {
+        Matches::new(self, input)
+    }
+
+    /// Every match in the input, from the left, including those that overlap; each one is looked
+    /// for from the character after where the one before it starts.
+    pub fn find_overlapping_iter<'input>(
+        &self,
+        input: &'input str,
+    ) -> Matches<'static, 'input, 'source> {
+        let mut matches = Matches::new(self, input);
+        matches.inner.overlapping = true;
+        matches
+    }
+
+    /// Every match in the input, from the left, with what each of them captured.
+    pub fn captures_iter<'input>(
+        &self,
+        input: &'input str,
+    ) -> CaptureMatches<'static, 'input, 'source> {
+        CaptureMatches {
+            input,
+            compiled: Cow::Owned(self.compile()),
+            next_start: 0,
+            overlapping: false,
+        }
+    }
+}
+
+impl CaptureType {
+    /// Whether a character can be part of a capture of this type.
+    pub(crate) fn accepts(&self, c: char) -> bool {
+        match self {
+            CaptureType::Int | CaptureType::Digit => c.is_ascii_digit(),
+            CaptureType::Alpha => c.is_alphabetic(),
+            CaptureType::Hex => c.is_ascii_hexdigit(),
+            CaptureType::Date => c.is_ascii_digit() || c == '-',
+            CaptureType::Timestamp => {
+                c.is_ascii_alphanumeric() || matches!(c, '-' | ':' | '.' | '+')
             }
+            CaptureType::Size => c.is_ascii_alphanumeric() || c == '.',
+            CaptureType::WordNum => c.is_ascii_alphabetic() || c == '-',
+            CaptureType::Custom(custom) => custom.scan(c.encode_utf8(&mut [0; 4])).is_some(),
         }
+    }
+}
+
+/// The character starting at the given position, if one does.
+pub(crate) fn next_char(input: &str, position: usize) -> Option<char> {
+    input.get(position..)?.chars().next()
+}
+
+/// Length in bytes of the given number of characters at the start of the input, if they are all
+/// accepted.
+pub(crate) fn scan_exactly(
+    input: &str,
+    count: usize,
+    accepts: impl Fn(char) -> bool,
+) -> Option<usize> {
+    let mut chars = input.chars();
+    let mut len = 0;
+
+    for _ in 0..count {
+        len += chars.next().filter(|c| accepts(*c))?.len_utf8();
+    }
+
+    Some(len)
+}
 
-        if state == self.expressions.len() {
-            return (
-                Some(Match {
-                    input,
-                    start: legit_start,
-                    end: curr_position,
-                }),
-                captures,
-            );
+pub(crate) const NUMBER_WORDS: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+pub(crate) const ORDINAL_WORDS: [&str; 20] = [
+    "zeroth",
+    "first",
+    "second",
+    "third",
+    "fourth",
+    "fifth",
+    "sixth",
+    "seventh",
+    "eighth",
+    "ninth",
+    "tenth",
+    "eleventh",
+    "twelfth",
+    "thirteenth",
+    "fourteenth",
+    "fifteenth",
+    "sixteenth",
+    "seventeenth",
+    "eighteenth",
+    "nineteenth",
+];
+pub(crate) const TENS_WORDS: [&str; 8] = [
+    "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+pub(crate) const TENS_ORDINAL_WORDS: [&str; 8] = [
+    "twentieth",
+    "thirtieth",
+    "fortieth",
+    "fiftieth",
+    "sixtieth",
+    "seventieth",
+    "eightieth",
+    "ninetieth",
+];
+
+/// Index and length of the longest of the words that the input starts with, ignoring case.
+fn longest_word(input: &str, words: &[&[&str]]) -> Option<(usize, usize)> {
+    words
+        .iter()
+        .flat_map(|words| words.iter().enumerate())
+        .filter(|(_, w)| {
+            input
+                .get(..w.len())
+                .is_some_and(|s| s.eq_ignore_ascii_case(w))
+        })
+        .map(|(i, w)| (i, w.len()))
+        .max_by_key(|(_, len)| *len)
+}
+
+/// Length and value of the number written as a word, like `three`, `twelfth` or `forty-two`,
+/// at the given position; which has to be the whole of a word.
+pub(crate) fn scan_word_number(input: &str, position: usize) -> Option<(usize, usize)> {
+    let is_letter = |c: &u8| c.is_ascii_alphabetic();
+
+    if position > 0 && input.as_bytes().get(position - 1).is_some_and(is_letter) {
+        return None;
+    }
+
+    let rest = input.get(position..)?;
+
+    let (len, n) = match longest_word(rest, &[&TENS_WORDS, &TENS_ORDINAL_WORDS]) {
+        Some((tens, len)) => {
+            let n = (tens + 2) * 10;
+            let is_ordinal = len != TENS_WORDS[tens].len();
+            let after = &rest[len..];
+            let hyphen = after.starts_with('-') as usize;
+            let units = [&NUMBER_WORDS[1..10], &ORDINAL_WORDS[1..10]];
+
+            // like `forty-two`, `fortytwo` or `forty-second`
+            match longest_word(&after[hyphen..], &units) {
+                Some((unit, unit_len)) if !is_ordinal => (len + hyphen + unit_len, n + unit + 1),
+                _ => (len, n),
+            }
         }
+        None => longest_word(rest, &[&NUMBER_WORDS, &ORDINAL_WORDS]).map(|(n, len)| (len, n))?,
+    };
 
-        (None, captures)
+    if rest.as_bytes().get(len).is_some_and(is_letter) {
+        return None;
     }
 
-    /// Find the leftmost-first match in the input starting at the given position
-    pub fn find_at<'input>(&self, input: &'input str, start: usize) -> Option<Match<'input>> {
-        self.find_at_capturing(input, start).0
+    Some((len, n))
+}
+
+/// Length of the valid `YYYY-MM-DD` date at the start of the input, if there is one.
+pub(crate) fn scan_date(input: &[u8]) -> Option<usize> {
+    const LEN: usize = "YYYY-MM-DD".len();
+
+    let candidate = input.get(..LEN)?;
+    let is_shaped_like_a_date = candidate.iter().enumerate().all(|(i, c)| match i {
+        4 | 7 => *c == b'-',
+        _ => c.is_ascii_digit(),
+    });
+
+    if !is_shaped_like_a_date {
+        return None;
     }
 
-    pub fn find_iter<'input>(self, input: &'input str) -> Matches<'input, 'source> {
-        Matches::new(self, input)
+    let candidate = std::str::from_utf8(candidate).ok()?;
+    chrono::NaiveDate::parse_from_str(candidate, "%Y-%m-%d")
+        .ok()
+        .map(|_| LEN)
+}
+
+/// Length of the valid `YYYY-MM-DDTHH:MM:SS` timestamp at the start of the input, including
+/// any fractions of a second and timezone, like `.250`, `Z` or `+02:00`.
+pub(crate) fn scan_timestamp(input: &[u8]) -> Option<usize> {
+    const TIME_LEN: usize = "THH:MM:SS".len();
+
+    let date_len = scan_date(input)?;
+    let candidate = input.get(date_len..date_len + TIME_LEN)?;
+    let is_shaped_like_a_time = candidate.iter().enumerate().all(|(i, c)| match i {
+        0 => *c == b'T',
+        3 | 6 => *c == b':',
+        _ => c.is_ascii_digit(),
+    });
+
+    if !is_shaped_like_a_time {
+        return None;
     }
+
+    let time = std::str::from_utf8(&candidate[1..]).ok()?;
+    chrono::NaiveTime::parse_from_str(time, "%H:%M:%S").ok()?;
+
+    let mut len = date_len + TIME_LEN;
+    let digits_at = |position: usize| {
+        input.get(position..).map_or(0, |rest| {
+            rest.iter().take_while(|c| c.is_ascii_digit()).count()
+        })
+    };
+
+    if input.get(len) == Some(&b'.') && digits_at(len + 1) > 0 {
+        len += 1 + digits_at(len + 1);
+    }
+
+    match input.get(len) {
+        Some(b'Z') => len += 1,
+        Some(b'+' | b'-') => match (digits_at(len + 1), input.get(len + 3)) {
+            (4, _) => len += 5,
+            (2, Some(b':')) if digits_at(len + 4) == 2 => len += 6,
+            _ => {}
+        },
+        _ => {}
+    }
+
+    Some(len)
 }
 
+/// Non-overlapping matches, from the left; see [`MatchExpression::find_iter`].
 #[derive(Debug)]
-pub struct Matches<'input, 'source> {
-    pub(crate) input: &'input str,
-    pub(crate) mex: MatchExpression<'source>,
-    last_end: usize,
+pub struct Matches<'c, 'input, 'source> {
+    pub(crate) inner: CaptureMatches<'c, 'input, 'source>,
 }
 
-impl<'input, 'source> Matches<'input, 'source> {
-    pub fn new(mex: MatchExpression<'source>, input: &'input str) -> Self {
+impl<'input, 'source> Matches<'static, 'input, 'source> {
+    pub fn new(mex: &MatchExpression<'source>, input: &'input str) -> Self {
         Self {
-            input,
-            mex,
-            last_end: 0,
+            inner: CaptureMatches {
+                input,
+                compiled: Cow::Owned(mex.compile()),
+                next_start: 0,
+                overlapping: false,
+            },
         }
     }
 }
 
-impl<'input, 'source> Iterator for Matches<'input, 'source> {
+impl<'c, 'input, 'source> Iterator for Matches<'c, 'input, 'source> {
     type Item = Match<'input>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.last_end >= self.input.len() {
+        self.inner.next().map(|(m, _)| m)
+    }
+}
+
+/// Matches with their captures; see [`MatchExpression::captures_iter`].
+#[derive(Debug)]
+pub struct CaptureMatches<'c, 'input, 'source> {
+    pub(crate) input: &'input str,
+    pub(crate) compiled: Cow<'c, CompiledMatchExpression<'source>>,
+    /// Where to look for the next match, which is past the end of the input after the last one.
+    pub(crate) next_start: usize,
+    /// Whether the next match is looked for from the character after where the last one started,
+    /// instead of from where it ended.
+    pub(crate) overlapping: bool,
+}
+
+impl<'c, 'input, 'source> Iterator for CaptureMatches<'c, 'input, 'source> {
+    type Item = (Match<'input>, Captures<'source, 'input>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_start > self.input.len() {
             return None;
         }
 
-        let m = match self.mex.find_at(self.input, self.last_end) {
-            None => return None,
-            Some(m) => m,
-        };
+        let (m, captures) = self.compiled.find_at_capturing(self.input, self.next_start);
+        let m = m?;
 
-        self.last_end = m.end;
+        let from = if self.overlapping { m.start } else { m.end };
 
-        Some(m)
+        self.next_start = match self.input[from..].chars().next() {
+            _ if from == m.end && m.end > m.start => m.end,
+            // step over a character, so the same match isn't found over and over
+            Some(ch) => from + ch.len_utf8(),
+            None => self.input.len() + 1,
+        };
+
+        Some((m, captures))
     }
 }
 
@@ -190,11 +373,11 @@ mod tests {
         macro_rules! assert_match_on {
             ($pattern:literal, $input:literal) => {
                 let exp = Parser::new(Lexer::new($pattern)).parse_match_exp().unwrap();
-                assert!(Matches::new(exp, $input).count() > 0);
+                assert!(Matches::new(&exp, $input).count() > 0);
             };
             ($pattern:literal, $input:literal, $boolean:literal) => {
                 let exp = Parser::new(Lexer::new($pattern)).parse_match_exp().unwrap();
-                assert_eq!(Matches::new(exp, $input).count() > 0, $boolean);
+                assert_eq!(Matches::new(&exp, $input).count() > 0, $boolean);
             };
         }
 
@@ -262,6 +445,175 @@ mod tests {
         assert_eq!(cap.get("d").unwrap(), "8");
     }
 
+    #[test]
+    fn alpha_capture_group() {
+        let exp = MatchExpression::from_str("(name:alpha)_(n:int)").unwrap();
+        let text = "01-SHOUTING_42.txt";
+
+        assert_eq!(exp.find_at(text, 0).unwrap().as_str(), "SHOUTING_42");
+        let cap = exp.find_at_capturing(text, 0).1;
+        assert_eq!(cap.get("name").unwrap(), "SHOUTING");
+        assert_eq!(cap.get("n").unwrap(), "42");
+    }
+
+    #[test]
+    fn unicode_alpha_capture_group() {
+        let exp = MatchExpression::from_str("(name:alpha)_(n:int)").unwrap();
+        let text = "01-café_42.txt";
+
+        assert_eq!(exp.find_at(text, 0).unwrap().as_str(), "café_42");
+        let cap = exp.find_at_capturing(text, 0).1;
+        assert_eq!(cap.get("name").unwrap(), "café");
+
+        let exp = MatchExpression::from_str("(name:alpha)").unwrap();
+        assert_eq!(exp.find_at("—日本語.txt", 0).unwrap().as_str(), "日本語");
+
+        let exp = MatchExpression::from_str("(code:alpha{2})").unwrap();
+        assert_eq!(exp.find_at("1éte", 0).unwrap().as_str(), "ét");
+    }
+
+    #[test]
+    fn hex_capture_group() {
+        let exp = MatchExpression::from_str("frame_0x(h:hex)").unwrap();
+        let text = "dump-frame_0x1F.png";
+
+        assert_eq!(exp.find_at(text, 0).unwrap().as_str(), "frame_0x1F");
+        let cap = exp.find_at_capturing(text, 0).1;
+        assert_eq!(cap.get("h").unwrap(), "1F");
+    }
+
+    #[test]
+    fn date_capture_group() {
+        let exp = MatchExpression::from_str("report_(d:date)").unwrap();
+        let text = "report_2023-08-01.csv";
+
+        assert_eq!(exp.find_at(text, 0).unwrap().as_str(), "report_2023-08-01");
+        let cap = exp.find_at_capturing(text, 0).1;
+        assert_eq!(cap.get("d").unwrap(), "2023-08-01");
+
+        assert!(exp.find_at("report_2023-13-01.csv", 0).is_none());
+        assert!(exp.find_at("report_2023-08.csv", 0).is_none());
+
+        let exp = MatchExpression::from_str("(d:date)").unwrap();
+        assert_eq!(
+            exp.find_at("x2023-08-01", 0).unwrap().as_str(),
+            "2023-08-01"
+        );
+    }
+
+    #[test]
+    fn optional_capture_groups() {
+        let exp = MatchExpression::from_str("ep(n:int?)-(t:alpha)").unwrap();
+
+        assert_eq!(exp.find_at("ep12-intro", 0).unwrap().as_str(), "ep12-intro");
+        let cap = exp.find_at_capturing("ep12-intro", 0).1;
+        assert_eq!(cap.get("n").unwrap(), "12");
+
+        assert_eq!(exp.find_at("ep-intro", 0).unwrap().as_str(), "ep-intro");
+        let cap = exp.find_at_capturing("ep-intro", 0).1;
+        assert_eq!(cap.get("n"), None);
+        assert_eq!(cap.get("t").unwrap(), "intro");
+
+        let exp = MatchExpression::from_str("part(d:dig?)").unwrap();
+        assert_eq!(exp.find_at("part", 0).unwrap().as_str(), "part");
+        assert_eq!(exp.find_at("part3", 0).unwrap().as_str(), "part3");
+    }
+
+    #[test]
+    fn fixed_length_capture_groups() {
+        let exp = MatchExpression::from_str("s(s:int{2})e(e:int{2})").unwrap();
+        let text = "show_s01e0203.mkv";
+
+        assert_eq!(exp.find_at(text, 0).unwrap().as_str(), "s01e02");
+        let cap = exp.find_at_capturing(text, 0).1;
+        assert_eq!(cap.get("s").unwrap(), "01");
+        assert_eq!(cap.get("e").unwrap(), "02");
+
+        assert!(exp.find_at("s1e02", 0).is_none());
+
+        let exp = MatchExpression::from_str("(code:alpha{3})").unwrap();
+        assert_eq!(exp.find_at("12abcd", 0).unwrap().as_str(), "abc");
+    }
+
+    #[test]
+    fn lookahead() {
+        let exp = MatchExpression::from_str("(n:int)(?=.mp4)").unwrap();
+
+        assert_eq!(exp.find_at("ep12.mp4", 0).unwrap().as_str(), "12");
+        assert_eq!(exp.find_at("ep1-3.mp4", 0).unwrap().as_str(), "3");
+        assert!(exp.find_at("ep12.mkv", 0).is_none());
+
+        let exp = MatchExpression::from_str("ab(?=c)").unwrap();
+        let m = exp.find_at("ababc", 0).unwrap();
+        assert_eq!((m.start, m.end), (2, 4));
+
+        let exp = MatchExpression::from_str("(n:int?)(?=x)").unwrap();
+        assert!(exp.find_at("ab", 0).is_none());
+    }
+
+    #[test]
+    fn timestamp_capture_group() {
+        let exp = MatchExpression::from_str("log_(t:timestamp)").unwrap();
+        let timestamp = |text| exp.find_at_capturing(text, 0).1.get("t").map(String::from);
+
+        assert_eq!(
+            timestamp("log_2023-08-01T12:30:00.txt").unwrap(),
+            "2023-08-01T12:30:00"
+        );
+        assert_eq!(
+            timestamp("log_2023-08-01T12:30:00Z.txt").unwrap(),
+            "2023-08-01T12:30:00Z"
+        );
+        assert_eq!(
+            timestamp("log_2023-08-01T12:30:00.250+02:00.txt").unwrap(),
+            "2023-08-01T12:30:00.250+02:00"
+        );
+        assert_eq!(
+            timestamp("log_2023-08-01T12:30:00-0500.txt").unwrap(),
+            "2023-08-01T12:30:00-0500"
+        );
+        assert_eq!(
+            timestamp("log_2023-08-01T12:30:00-05.txt").unwrap(),
+            "2023-08-01T12:30:00"
+        );
+        assert_eq!(timestamp("log_2023-08-01T25:30:00.txt"), None);
+        assert_eq!(timestamp("log_2023-08-01.txt"), None);
+    }
+
+    #[test]
+    fn size_capture_group() {
+        let exp = MatchExpression::from_str("dump_(s:size)").unwrap();
+
+        let cap = exp.find_at_capturing("dump_1.5GiB.tar", 0);
+        assert_eq!(cap.0.unwrap().as_str(), "dump_1.5GiB");
+        assert_eq!(cap.1.get("s").unwrap(), "1.5GiB");
+
+        assert!(exp.find_at("dump_12Mbps.tar", 0).is_none());
+        assert!(exp.find_at("dump_12.tar", 0).is_none());
+    }
+
+    #[test]
+    fn word_number_capture_group() {
+        let exp = MatchExpression::from_str("chapter-(n:wordnum)").unwrap();
+
+        let cap = exp.find_at_capturing("chapter-three.md", 0);
+        assert_eq!(cap.0.unwrap().as_str(), "chapter-three");
+        assert_eq!(cap.1.get("n").unwrap(), "3");
+
+        let number = |text| exp.find_at_capturing(text, 0).1.get("n").map(String::from);
+        assert_eq!(number("chapter-Seventeen.md").unwrap(), "17");
+        assert_eq!(number("chapter-seventh.md").unwrap(), "7");
+        assert_eq!(number("chapter-twelfth.md").unwrap(), "12");
+        assert_eq!(number("chapter-forty-two.md").unwrap(), "42");
+        assert_eq!(number("chapter-twenty.md").unwrap(), "20");
+        assert_eq!(number("chapter-ninetieth.md").unwrap(), "90");
+        assert_eq!(number("chapter-twenty-first.md").unwrap(), "21");
+        assert_eq!(number("chapter-oneself.md"), None);
+
+        let exp = MatchExpression::from_str("(n:wordnum)").unwrap();
+        assert!(exp.find_at("phone", 0).is_none());
+    }
+
     #[test]
     fn special() {
         let exp = MatchExpression::from_str("hello(as:dig)->oh(as)hi").unwrap();
@@ -272,10 +624,114 @@ mod tests {
     fn muliple_matches() {
         let pattern = MatchExpression::from_str("xy(n:int)").unwrap();
         let text = "wxy10xy33asdfxy81";
-        let mut matches = Matches::new(pattern, text);
+        let mut matches = Matches::new(&pattern, text);
 
         assert_eq!(matches.next().unwrap().as_str(), "xy10");
         assert_eq!(matches.next().unwrap().as_str(), "xy33");
         assert_eq!(matches.next().unwrap().as_str(), "xy81");
     }
+
+    #[test]
+    fn captures_giving_back_to_the_text_after_them() {
+        for (pattern, input, matched, name, captured) in [
+            ("(n:int)1x", "231x", "231x", "n", "23"),
+            ("(n:int)1x", "ab231x7", "231x", "n", "23"),
+            ("(n:int)12", "9123412", "9123412", "n", "91234"),
+            ("(t:alpha)ing", "running.txt", "running", "t", "runn"),
+            ("(n:int)(?=0.mp4)", "ep100.mp4", "10", "n", "10"),
+        ] {
+            let exp = MatchExpression::from_str(pattern).unwrap();
+            let (m, captures) = exp.find_at_capturing(input, 0);
+
+            assert_eq!(m.unwrap().as_str(), matched, "{pattern} on {input}");
+            assert_eq!(captures.get(name).unwrap(), captured);
+        }
+
+        // a capture keeps at least one character
+        let exp = MatchExpression::from_str("(n:int)1").unwrap();
+        assert!(exp.find_at("1", 0).is_none());
+    }
+
+    #[test]
+    fn match_ranges_and_captures_in_order() {
+        let exp = MatchExpression::from_str("(t:alpha)_(n:int?)(d:dig?)").unwrap();
+        let (m, captures) = exp.find_at_capturing("01-intro_42.mkv", 0);
+        let m = m.unwrap();
+
+        assert_eq!(m.range(), 3..11);
+        assert_eq!(m.len(), 8);
+        assert!(!m.is_empty());
+
+        assert_eq!(captures.len(), 2);
+        assert_eq!(&captures[0], "intro");
+        assert_eq!(&captures[1], "42");
+        assert_eq!(
+            captures.iter().collect::<Vec<_>>(),
+            vec![("t", "intro"), ("n", "42")]
+        );
+
+        // nothing is kept from where matching started over
+        let exp = MatchExpression::from_str("(n:int)(t:alpha)").unwrap();
+        let (m, captures) = exp.find_at_capturing("1-2b", 0);
+        assert_eq!(m.unwrap().as_str(), "2b");
+        assert_eq!(captures.get("n"), Some("2"));
+        assert_eq!(
+            captures.iter().collect::<Vec<_>>(),
+            vec![("n", "2"), ("t", "b")]
+        );
+    }
+
+    #[test]
+    fn searching_many_inputs_with_one_expression() {
+        let compiled = MatchExpression::from_str("ep(n:int)").unwrap().compile();
+
+        let found: Vec<_> = ["ep1-ep2.mkv", "none", "ep30"]
+            .iter()
+            .flat_map(|input| compiled.find_iter(input).map(|m| m.as_str()))
+            .collect();
+
+        assert_eq!(found, vec!["ep1", "ep2", "ep30"]);
+    }
+
+    #[test]
+    fn overlapping_matches() {
+        let exp = MatchExpression::from_str("(n:int)").unwrap();
+        let found: Vec<_> = exp
+            .find_overlapping_iter("a12—3")
+            .map(|m| m.as_str())
+            .collect();
+
+        assert_eq!(found, vec!["12", "2", "3"]);
+
+        let exp = MatchExpression::from_str("aa").unwrap();
+        assert_eq!(exp.find_overlapping_iter("aaaa").count(), 3);
+        assert_eq!(exp.find_iter("aaaa").count(), 2);
+    }
+
+    #[test]
+    fn every_match_with_its_captures() {
+        let pattern = MatchExpression::from_str("(t:alpha)(n:int)").unwrap();
+        let found: Vec<_> = pattern
+            .captures_iter("a1-bc22_d")
+            .map(|(m, captures)| {
+                (
+                    m.as_str().to_string(),
+                    captures.get("t").unwrap().to_string(),
+                    captures.get("n").unwrap().to_string(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                ("a1".to_string(), "a".to_string(), "1".to_string()),
+                ("bc22".to_string(), "bc".to_string(), "22".to_string()),
+            ]
+        );
+
+        let pattern = MatchExpression::from_str("(n:int?)").unwrap();
+        let ends: Vec<_> = pattern.captures_iter("a—").map(|(m, _)| m.end).collect();
+        assert_eq!(ends, vec![0, 1, 4]);
+    }
 }
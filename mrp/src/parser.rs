@@ -1,49 +1,304 @@
-use std::str::FromStr;
+use std::{borrow::Cow, ops::Range, str::FromStr, sync::Arc};
 
 use crate::{
-    error::{ParseError, ParseErrorKind, Result},
-    lexer::{Lexer, Token, TokenKind},
-    Array,
+    error::{OwnedParseError, ParseError, ParseErrorKind, Result},
+    lexer::{Lexer, Token, TokenKind, TokenText},
+    size,
+    variables::{BUILTIN_VARIABLES, HASH_VARIABLE},
+    Array, CaptureTypeRegistry, CompiledMatchExpression, CustomCaptureType,
 };
 
+/// What a capture matches. With the `serde` feature, every type but [`CaptureType::Custom`] can be
+/// serialized; serializing an expression with a registered type in it fails at runtime, with an
+/// error from the serializer.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CaptureType {
     Int,
     Digit,
+    Alpha,
+    Hex,
+    /// A date written as `YYYY-MM-DD`
+    Date,
+    /// A timestamp written as `YYYY-MM-DDTHH:MM:SS`, possibly with fractions of a second and a
+    /// timezone
+    Timestamp,
+    /// A size like `12MB` or `1.5GiB`
+    Size,
+    /// A number written as a word, like `three` or `twelfth`, captured as its value
+    WordNum,
+    /// A type from a [`CaptureTypeRegistry`]; it can't be serialized, since how it matches is
+    /// only known to the program that registered it.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(CustomCaptureType),
 }
 
+/// A part of a match expression. Text that can be anything, like a literal, is a [`Cow`], so that
+/// it's borrowed from the source when it can be, and copied when it can't, like when it's
+/// deserialized from a string with escapes in it.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AbstractMatchingExpression<'source> {
-    Literal(&'source str),
+    Literal(#[cfg_attr(feature = "serde", serde(borrow))] Cow<'source, str>),
     Capture {
         identifier: &'source str,
         identifier_type: CaptureType,
+        /// An optional capture is skipped over when the input doesn't match its type.
+        optional: bool,
+        /// Exactly how many characters to capture, for the types that otherwise capture as many
+        /// as they can; written like `int{2}`.
+        length: Option<usize>,
     },
+    /// Text that has to follow, written like `(?=.mp4)`, but that isn't part of the match.
+    Lookahead(#[cfg_attr(feature = "serde", serde(borrow))] Cow<'source, str>),
+}
+
+/// Extend the span of the expression in parens up to the `)` that closes them.
+fn close_group(group_start: &mut Option<usize>, spans: &mut [Span], token: &Token) {
+    if token.kind != TokenKind::Rparen {
+        return;
+    }
+
+    if let (Some(_), Some(span)) = (group_start.take(), spans.last_mut()) {
+        span.end = token.start + 1;
+    }
+}
+
+/// What the type of a capture, as written after its `:`, stands for.
+#[derive(Debug, PartialEq, Clone)]
+struct CaptureSpec {
+    identifier_type: CaptureType,
+    optional: bool,
+    length: Option<usize>,
+}
+
+/// The name and type of a declaration like `type ep = int{2}`, with the offset of the type from
+/// the start of the declaration.
+pub(crate) fn type_declaration(text: &str) -> Option<(&str, &str, usize)> {
+    let rest = text.strip_prefix("type ")?;
+    let (name, type_text) = rest.split_once('=')?;
+    let (name, trimmed_type_text) = (name.trim(), type_text.trim());
+
+    if name.is_empty() || !name.bytes().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    if trimmed_type_text.is_empty() {
+        return None;
+    }
+
+    let offset = text.len() - type_text.trim_start().len();
+
+    Some((name, trimmed_type_text, offset))
+}
+
+/// A change applied to a captured value before it is written into the replacement.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Transform<'source> {
+    Upper,
+    Lower,
+    Title,
+    /// Keep only the characters from `start` up to (not including) `end`.
+    Slice {
+        start: usize,
+        end: Option<usize>,
+    },
+    /// Write a decimal number in hexadecimal.
+    Hex,
+    /// Write a hexadecimal number in decimal.
+    Dec,
+    /// Write a date or time in a different format, given in `strftime` syntax (e.g. `%Y%m%d`).
+    DateFormat(#[cfg_attr(feature = "serde", serde(borrow))] Cow<'source, str>),
+    /// Write a size like `1.5GiB` as a number of bytes.
+    Bytes,
+    /// Write a size like `1.5GiB` in another unit, like `MiB`.
+    SizeIn(&'source str),
+    /// Remove whitespace from both ends.
+    Trim,
+    /// Add `fill` to the start until there are at least `width` characters.
+    PadLeft {
+        width: usize,
+        fill: char,
+    },
+    /// Add `fill` to the end until there are at least `width` characters.
+    PadRight {
+        width: usize,
+        fill: char,
+    },
+    /// Replace every occurence of `from` with `to`.
+    Replace {
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        from: Cow<'source, str>,
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        to: Cow<'source, str>,
+    },
+}
+
+impl<'source> Transform<'source> {
+    /// Parse a range like `0..8`, `2..` or `..8`.
+    fn slice(text: &str) -> Option<Self> {
+        let (start, end) = text.split_once("..")?;
+
+        let start = match start {
+            "" => 0,
+            s => s.parse().ok()?,
+        };
+
+        let end = match end {
+            "" => None,
+            e => Some(e.parse().ok()?),
+        };
+
+        if end.is_some_and(|end| end < start) {
+            return None;
+        }
+
+        Some(Transform::Slice { start, end })
+    }
+
+    /// Parse the algorithm of the `hash` variable, like `sha256`, with the number of characters
+    /// of it to keep, like `sha256:8`; SHA-256 is the only one there is, so it's kept as a range.
+    fn digest(text: &str) -> Option<Self> {
+        let end = match text.strip_prefix("sha256")? {
+            "" => None,
+            length => Some(length.strip_prefix(':')?.parse().ok()?),
+        };
+
+        Some(Transform::Slice { start: 0, end })
+    }
+
+    /// Parse a function with arguments, like `replace '_' '-'` or `lpad 3 '0'`.
+    fn function(text: &'source str) -> Option<Self> {
+        let (name, arguments) = text.split_once(' ')?;
+        let arguments = arguments_of(arguments)?;
+
+        let fill = |argument: Option<&&str>| match argument {
+            Some(fill) => {
+                let mut chars = fill.chars();
+                let fill = chars.next()?;
+                chars.next().is_none().then_some(fill)
+            }
+            None => Some(' '),
+        };
+
+        match (name, arguments.as_slice()) {
+            ("lpad", [width, rest @ ..]) if rest.len() <= 1 => Some(Transform::PadLeft {
+                width: width.parse().ok()?,
+                fill: fill(rest.first())?,
+            }),
+            ("rpad", [width, rest @ ..]) if rest.len() <= 1 => Some(Transform::PadRight {
+                width: width.parse().ok()?,
+                fill: fill(rest.first())?,
+            }),
+            ("replace", [from, to]) if !from.is_empty() => Some(Transform::Replace {
+                from: Cow::Borrowed(from),
+                to: Cow::Borrowed(to),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Split the arguments of a function on whitespace, where an argument in single quotes may
+/// contain whitespace.
+fn arguments_of(text: &str) -> Option<Vec<&str>> {
+    let mut arguments = vec![];
+    let mut rest = text.trim_start();
+
+    while !rest.is_empty() {
+        let end = match rest.strip_prefix('\'') {
+            Some(quoted) => {
+                let len = quoted.find('\'')?;
+                arguments.push(&quoted[..len]);
+                len + 2
+            }
+            None => {
+                let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                arguments.push(&rest[..len]);
+                len
+            }
+        };
+
+        rest = rest[end..].trim_start();
+    }
+
+    Some(arguments)
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AbstractReplaceExpression<'source> {
-    Literal(&'source str),
+    Literal(#[cfg_attr(feature = "serde", serde(borrow))] Cow<'source, str>),
     Identifier(&'source str),
+    Transformed {
+        identifier: &'source str,
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        transform: Transform<'source>,
+    },
+    /// An identifier with a value to write instead when it wasn't captured.
+    Fallback {
+        identifier: &'source str,
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        default: Cow<'source, str>,
+    },
+    /// The reserved `(#)` variable, counting up with every value replaced.
+    Counter,
+    /// Either of two replacements, depending on whether an identifier has a value; written as
+    /// `(n ? "then" : "otherwise")`.
+    Conditional {
+        identifier: &'source str,
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        then: Array<AbstractReplaceExpression<'source>>,
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        otherwise: Array<AbstractReplaceExpression<'source>>,
+    },
 }
 
-#[derive(Debug, PartialEq)]
+/// A range of bytes in the source of an expression.
+pub type Span = Range<usize>;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MatchExpression<'source> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub expressions: Vec<AbstractMatchingExpression<'source>>,
+    /// Where each of the expressions was written, if they were parsed.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub spans: Vec<Span>,
 }
 
+/// Match expressions are equal when they match in the same way, wherever they were written.
+impl<'source> PartialEq for MatchExpression<'source> {
+    fn eq(&self, other: &Self) -> bool {
+        self.expressions == other.expressions
+    }
+}
+
+/// Parses by leaking the input, to give an expression that can be kept for as long as the program
+/// runs. Use [`MatchExpressionOwned`] to parse many expressions without leaking each of them.
 impl FromStr for MatchExpression<'static> {
     type Err = ParseError<'static>;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         let input = Box::leak(s.into());
-        Parser::new(Lexer::new(input)).parse_match_exp()
+        let mut parser = Parser::new(Lexer::new(input));
+        let expression = parser.parse_match_exp();
+        parser.first_error(expression)
     }
 }
 
 impl<'source> MatchExpression<'source> {
     pub fn new(expressions: Vec<AbstractMatchingExpression<'source>>) -> Self {
-        Self { expressions }
+        Self {
+            expressions,
+            spans: vec![],
+        }
+    }
+
+    /// Where the expression at `idx` was written, if it was parsed.
+    pub fn span(&self, idx: usize) -> Option<Span> {
+        self.spans.get(idx).cloned()
     }
 
     pub fn get_expression(&self, idx: usize) -> Option<AbstractMatchingExpression<'source>> {
@@ -51,29 +306,323 @@ impl<'source> MatchExpression<'source> {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReplaceExpression<'source> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub expressions: Array<AbstractReplaceExpression<'source>>,
+    /// Where each of the expressions was written, if they were parsed.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub spans: Array<Span>,
+}
+
+/// Replace expressions are equal when they replace in the same way, wherever they were written.
+impl<'source> PartialEq for ReplaceExpression<'source> {
+    fn eq(&self, other: &Self) -> bool {
+        self.expressions == other.expressions
+    }
+}
+
+impl<'source> ReplaceExpression<'source> {
+    pub fn new(expressions: Array<AbstractReplaceExpression<'source>>) -> Self {
+        Self {
+            expressions,
+            spans: Box::new([]),
+        }
+    }
+
+    /// Where the expression at `idx` was written, if it was parsed.
+    pub fn span(&self, idx: usize) -> Option<Span> {
+        self.spans.get(idx).cloned()
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MatchAndReplaceExpression<'source> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub mex: MatchExpression<'source>,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub rex: ReplaceExpression<'source>,
 }
 
+/// Parses by leaking the input, to give an expression that can be kept for as long as the program
+/// runs. Use [`MatchAndReplaceExpressionOwned`] to parse many expressions without leaking each of
+/// them.
 impl FromStr for MatchAndReplaceExpression<'static> {
     type Err = ParseError<'static>;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         let input = Box::leak(s.into());
-        Parser::new(Lexer::new(input)).parse()
+        let mut parser = Parser::new(Lexer::new(input));
+        let expression = parser.parse()?;
+        parser.expect(TokenKind::End)?;
+        Ok(expression)
     }
 }
 
+/// Match and replace expressions, separated by `;`, to be applied one after the other.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchAndReplaceRules<'source> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub rules: Vec<MatchAndReplaceExpression<'source>>,
+}
+
+/// Parses by leaking the input, to give rules that can be kept for as long as the program runs.
+/// Use [`MatchAndReplaceRulesOwned`] to parse many sets of rules without leaking each of them.
+impl FromStr for MatchAndReplaceRules<'static> {
+    type Err = ParseError<'static>;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let input = Box::leak(s.into());
+        Parser::new(Lexer::new(input)).parse_rules()
+    }
+}
+
+/// A match expression that owns its source, so that parsing it doesn't leak anything.
+///
+/// The expression is parsed once, and kept next to the source that it borrows from, which is
+/// shared by the clones of it, and never changed; it's only handed out for as long as it's
+/// borrowed, so the `'static` it's stored with is never seen, and the source outlives it. The
+/// other owned types are kept the same way.
+#[derive(Debug, Clone)]
+pub struct MatchExpressionOwned {
+    /// Borrows from `source`.
+    expression: MatchExpression<'static>,
+    /// Borrows from `source`, like the expression it was compiled from.
+    compiled: CompiledMatchExpression<'static>,
+    source: Arc<str>,
+    registry: CaptureTypeRegistry,
+}
+
+impl MatchExpressionOwned {
+    /// Parse the source, accepting the types in the registry on top of the built-in ones.
+    pub fn with_registry(
+        source: &str,
+        registry: CaptureTypeRegistry,
+    ) -> std::result::Result<Self, OwnedParseError> {
+        let source: Arc<str> = source.into();
+        let mut parser = Parser::new(Lexer::new(&source));
+        parser.set_registry(registry.clone());
+        let expression = parser.parse_match_exp();
+        let expression = parser.first_error(expression)?;
+        let compiled = expression.compile();
+
+        Ok(Self {
+            // SAFETY: they're only changed in lifetime, and kept next to the source, which lives
+            // for as long as they do; see `MatchExpressionOwned`
+            expression: unsafe {
+                std::mem::transmute::<MatchExpression<'_>, MatchExpression<'static>>(expression)
+            },
+            compiled: unsafe {
+                std::mem::transmute::<CompiledMatchExpression<'_>, CompiledMatchExpression<'static>>(
+                    compiled,
+                )
+            },
+            source,
+            registry,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The expression, borrowing from the source.
+    pub fn expression(&self) -> &MatchExpression<'_> {
+        &self.expression
+    }
+
+    /// The expression as a program, compiled once, when it was parsed; see
+    /// [`MatchExpression::compile`].
+    pub fn compiled(&self) -> &CompiledMatchExpression<'_> {
+        &self.compiled
+    }
+
+    /// The types the expression was parsed with, on top of the built-in ones.
+    pub fn registry(&self) -> &CaptureTypeRegistry {
+        &self.registry
+    }
+}
+
+impl PartialEq for MatchExpressionOwned {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl FromStr for MatchExpressionOwned {
+    type Err = OwnedParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::with_registry(s, CaptureTypeRegistry::new())
+    }
+}
+
+/// A match and replace expression that owns its source, so that parsing it doesn't leak anything.
+#[derive(Debug, Clone)]
+pub struct MatchAndReplaceExpressionOwned {
+    /// Borrows from `source`; see [`MatchExpressionOwned`].
+    expression: MatchAndReplaceExpression<'static>,
+    source: Arc<str>,
+    registry: CaptureTypeRegistry,
+}
+
+impl MatchAndReplaceExpressionOwned {
+    /// Parse the source, accepting the types in the registry on top of the built-in ones.
+    pub fn with_registry(
+        source: &str,
+        registry: CaptureTypeRegistry,
+    ) -> std::result::Result<Self, OwnedParseError> {
+        let source: Arc<str> = source.into();
+        let mut parser = Parser::new(Lexer::new(&source));
+        parser.set_registry(registry.clone());
+        let expression = parser.parse()?;
+        parser.expect(TokenKind::End)?;
+
+        Ok(Self {
+            // SAFETY: it's only changed in lifetime, and kept next to the source, which lives
+            // for as long as it does; see `MatchExpressionOwned`
+            expression: unsafe {
+                std::mem::transmute::<
+                    MatchAndReplaceExpression<'_>,
+                    MatchAndReplaceExpression<'static>,
+                >(expression)
+            },
+            source,
+            registry,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The expression, borrowing from the source.
+    pub fn expression(&self) -> &MatchAndReplaceExpression<'_> {
+        &self.expression
+    }
+
+    /// The types the expression was parsed with, on top of the built-in ones.
+    pub fn registry(&self) -> &CaptureTypeRegistry {
+        &self.registry
+    }
+}
+
+impl PartialEq for MatchAndReplaceExpressionOwned {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl FromStr for MatchAndReplaceExpressionOwned {
+    type Err = OwnedParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::with_registry(s, CaptureTypeRegistry::new())
+    }
+}
+
+/// Match and replace rules that own their source, so that parsing them doesn't leak anything.
+#[derive(Debug, Clone)]
+pub struct MatchAndReplaceRulesOwned {
+    /// Borrows from `source`; see [`MatchExpressionOwned`].
+    rules: MatchAndReplaceRules<'static>,
+    source: Arc<str>,
+    registry: CaptureTypeRegistry,
+}
+
+impl MatchAndReplaceRulesOwned {
+    /// Parse the source, accepting the types in the registry on top of the built-in ones.
+    pub fn with_registry(
+        source: &str,
+        registry: CaptureTypeRegistry,
+    ) -> std::result::Result<Self, OwnedParseError> {
+        let source: Arc<str> = source.into();
+        let mut parser = Parser::new(Lexer::new(&source));
+        parser.set_registry(registry.clone());
+        let rules = parser.parse_rules_recovering()?;
+
+        Ok(Self {
+            // SAFETY: they're only changed in lifetime, and kept next to the source, which lives
+            // for as long as it does; see `MatchExpressionOwned`
+            rules: unsafe {
+                std::mem::transmute::<MatchAndReplaceRules<'_>, MatchAndReplaceRules<'static>>(
+                    rules,
+                )
+            },
+            source,
+            registry,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The rules, borrowing from the source.
+    pub fn rules(&self) -> &MatchAndReplaceRules<'_> {
+        &self.rules
+    }
+
+    /// The types the rules were parsed with, on top of the built-in ones.
+    pub fn registry(&self) -> &CaptureTypeRegistry {
+        &self.registry
+    }
+}
+
+impl PartialEq for MatchAndReplaceRulesOwned {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl FromStr for MatchAndReplaceRulesOwned {
+    type Err = OwnedParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::with_registry(s, CaptureTypeRegistry::new())
+    }
+}
+
+/// Parse rules written one per line, as in a file. Blank lines and comments are skipped, and a
+/// type declared like `type ep = int{2};` can be used on the lines after it. Errors give the line
+/// and column they are on with [`ParseError::line_and_column`].
+pub fn parse_rules(source: &str) -> Result<'_, Vec<MatchAndReplaceExpression<'_>>> {
+    let mut rules = vec![];
+    let mut aliases = vec![];
+    let mut line_start = 0;
+
+    for line in source.split_inclusive('\n') {
+        // without the indentation and the whitespace at the end
+        let start = line_start + line.len() - line.trim_start().len();
+        let range = start..start + line.trim().len();
+        line_start += line.len();
+
+        let mut parser = Parser::new(Lexer::with_range(source, range).with_comments());
+        parser.aliases = aliases;
+        parser.parse_type_declarations()?;
+
+        // a line may only have declarations or a comment
+        if parser.peek_token().kind != TokenKind::End {
+            rules.extend(parser.parse_rules()?.rules);
+        }
+
+        aliases = parser.aliases;
+    }
+
+    Ok(rules)
+}
+
 pub struct Parser<'source> {
     lexer: Lexer<'source>,
     peeked: Option<Token<'source>>,
+    /// Types declared with `type name = type;`
+    aliases: Vec<(&'source str, CaptureSpec)>,
+    registry: CaptureTypeRegistry,
+    /// Errors that were recovered from, to be reported along with any that weren't.
+    errors: Vec<ParseError<'source>>,
 }
 
 impl<'source> Parser<'source> {
@@ -81,9 +630,17 @@ impl<'source> Parser<'source> {
         Self {
             lexer,
             peeked: None,
+            aliases: vec![],
+            registry: CaptureTypeRegistry::new(),
+            errors: vec![],
         }
     }
 
+    /// Accept the types in the registry, on top of the built-in ones.
+    pub fn set_registry(&mut self, registry: CaptureTypeRegistry) {
+        self.registry = registry;
+    }
+
     fn token(&mut self) -> Token<'source> {
         match self.peeked.take() {
             Some(t) => t,
@@ -99,41 +656,106 @@ impl<'source> Parser<'source> {
         self.token();
     }
 
+    /// Skip the rest of a rule that has an error, up to the `;` or end after it.
+    fn skip_rule(&mut self) {
+        while !matches!(
+            self.peek_token().kind,
+            TokenKind::End | TokenKind::Semicolon
+        ) {
+            self.eat_token();
+        }
+    }
+
+    /// The first error, of those that were recovered from and the one in `result`.
+    fn first_error<T>(&mut self, result: Result<'source, T>) -> Result<'source, T> {
+        match self.errors.drain(..).next() {
+            Some(e) => Err(e),
+            None => result,
+        }
+    }
+
     pub(crate) fn parse_match_exp(&mut self) -> Result<'source, MatchExpression<'source>> {
         let mut expressions = vec![];
+        let mut spans = vec![];
+        // where the `(` of a capture is, until its `)` is found
+        let mut group_start = None;
 
         let mut token = self.token();
 
         use TokenKind::*;
 
-        while token.kind != End {
+        while !matches!(token.kind, End | Semicolon) {
             if let Lparen = token.kind {
+                if let Lookahead = self.peek_token().kind {
+                    let (exp, end) = self.parse_lookahead()?;
+                    expressions.push(exp);
+                    spans.push(token.start..end);
+                    token = self.token();
+                    continue;
+                }
+
+                group_start = Some(token.start);
                 self.expect(Ident)?;
             }
 
             let exp = match token.kind {
-                Literal => AbstractMatchingExpression::Literal(&token.text),
+                Literal => AbstractMatchingExpression::Literal(Cow::Borrowed(*token.text)),
                 Ident => {
                     let exp = self.parse_capture(&token.text)?;
-                    self.expect(Rparen)?;
+                    if let Err(e) = self.expect(Rparen) {
+                        // carry on as if the `)` was there
+                        self.errors.push(e);
+                    }
                     exp
                 }
                 Arrow => {
+                    if expressions.is_empty() {
+                        // carry on, to check the replacement too
+                        self.errors.push(ParseError {
+                            source: self.lexer.input(),
+                            kind: ParseErrorKind::EmptyMatchExpression {
+                                position: token.start,
+                            },
+                        });
+                    }
                     self.expect_not(End, Arrow)?;
-                    break;
+                    self.expect_not(Semicolon, Arrow)?;
+                    return Ok(MatchExpression { expressions, spans });
                 }
                 _ => {
+                    close_group(&mut group_start, &mut spans, &token);
                     token = self.token();
                     continue;
                 }
             };
 
             expressions.push(exp);
+            spans.push(group_start.unwrap_or(token.start)..token.start + token.text.len());
 
             token = self.token();
         }
 
-        Ok(MatchExpression::new(expressions))
+        // the end of the rule is left for the caller to see
+        self.peeked = Some(token);
+
+        Ok(MatchExpression { expressions, spans })
+    }
+
+    /// Parse what follows the `(` of a lookahead like `(?=.mp4)`, up to and including the `)`,
+    /// and give where it ends.
+    fn parse_lookahead(&mut self) -> Result<'source, (AbstractMatchingExpression<'source>, usize)> {
+        self.eat_token();
+
+        self.expect(TokenKind::Literal)?;
+        let text = self.token().text;
+
+        self.expect(TokenKind::Rparen)?;
+        let end = self.token().start + 1;
+
+        Ok((
+            AbstractMatchingExpression::Lookahead(Cow::Borrowed(*text)),
+            end,
+        ))
     }
 
     fn parse_capture(
@@ -144,22 +766,141 @@ impl<'source> Parser<'source> {
 
         self.expect(TokenKind::Type)?;
 
+        let t = self.token();
+
+        let spec = match self.resolve_type(&t.text) {
+            Some(spec) => spec,
+            None => {
+                self.errors.push(ParseError {
+                    source: self.lexer.input(),
+                    kind: ParseErrorKind::UnsupportedToken(t),
+                });
+                // the capture is still declared, so that its uses aren't reported too
+                CaptureSpec {
+                    identifier_type: CaptureType::Int,
+                    optional: false,
+                    length: None,
+                }
+            }
+        };
+
         Ok(AbstractMatchingExpression::Capture {
             identifier,
-            identifier_type: match self.token() {
-                t if t.kind == TokenKind::Type => match *t.text {
-                    "int" => CaptureType::Int,
-                    "dig" => CaptureType::Digit,
-                    _ => {
-                        return Err(ParseError {
-                            source: self.lexer.input(),
-                            kind: ParseErrorKind::UnsupportedToken(t),
-                        })
-                    }
+            identifier_type: spec.identifier_type,
+            optional: spec.optional,
+            length: spec.length,
+        })
+    }
+
+    /// Work out a type like `int`, `int?`, `alpha{3}`, or one that was declared.
+    fn resolve_type(&self, text: &str) -> Option<CaptureSpec> {
+        let (text, optional) = match text.strip_suffix('?') {
+            Some(text) => (text, true),
+            None => (text, false),
+        };
+
+        let (name, length) = match text.strip_suffix('}').and_then(|t| t.split_once('{')) {
+            Some((name, length)) => (name, Some(length.parse().ok().filter(|l| *l > 0)?)),
+            None => (text, None),
+        };
+
+        let mut spec = match name {
+            "int" => CaptureSpec {
+                identifier_type: CaptureType::Int,
+                optional: false,
+                length: None,
+            },
+            "dig" => CaptureSpec {
+                identifier_type: CaptureType::Digit,
+                optional: false,
+                length: None,
+            },
+            "alpha" => CaptureSpec {
+                identifier_type: CaptureType::Alpha,
+                optional: false,
+                length: None,
+            },
+            "hex" => CaptureSpec {
+                identifier_type: CaptureType::Hex,
+                optional: false,
+                length: None,
+            },
+            "date" => CaptureSpec {
+                identifier_type: CaptureType::Date,
+                optional: false,
+                length: None,
+            },
+            "timestamp" => CaptureSpec {
+                identifier_type: CaptureType::Timestamp,
+                optional: false,
+                length: None,
+            },
+            "size" => CaptureSpec {
+                identifier_type: CaptureType::Size,
+                optional: false,
+                length: None,
+            },
+            "wordnum" => CaptureSpec {
+                identifier_type: CaptureType::WordNum,
+                optional: false,
+                length: None,
+            },
+            name => match self.aliases.iter().find(|(n, _)| *n == name) {
+                Some((_, spec)) => spec.clone(),
+                None => CaptureSpec {
+                    identifier_type: CaptureType::Custom(self.registry.get(name)?.clone()),
+                    optional: false,
+                    length: None,
                 },
-                _ => unreachable!("we expected a type token"),
             },
-        })
+        };
+
+        spec.optional |= optional;
+
+        if length.is_some() {
+            let takes_length = matches!(
+                spec.identifier_type,
+                CaptureType::Int | CaptureType::Alpha | CaptureType::Hex
+            );
+
+            if !takes_length || spec.length.is_some() {
+                return None;
+            }
+
+            spec.length = length;
+        }
+
+        Some(spec)
+    }
+
+    /// Parse any declarations like `type ep = int{2};`, so that the types can be used in the
+    /// captures that follow.
+    fn parse_type_declarations(&mut self) -> Result<'source, ()> {
+        loop {
+            let (name, type_text, start) = match self.peek_token() {
+                t if t.kind == TokenKind::Literal => match type_declaration(*t.text) {
+                    Some((name, type_text, offset)) => (name, type_text, t.start + offset),
+                    None => return Ok(()),
+                },
+                _ => return Ok(()),
+            };
+
+            self.eat_token();
+            self.expect(TokenKind::Semicolon)?;
+            self.eat_token();
+
+            match self.resolve_type(type_text) {
+                Some(spec) => self.aliases.push((name, spec)),
+                None => self.errors.push(ParseError {
+                    source: self.lexer.input(),
+                    kind: ParseErrorKind::UnsupportedToken(Token {
+                        kind: TokenKind::Type,
+                        text: TokenText::Slice(type_text),
+                        start,
+                    }),
+                }),
+            }
+        }
     }
 
     fn expect(&mut self, token_kind: TokenKind) -> Result<'source, ()> {
@@ -200,54 +941,207 @@ impl<'source> Parser<'source> {
         declared_idents: Vec<&'source str>,
     ) -> Result<'source, ReplaceExpression<'source>> {
         let mut expressions = vec![];
+        let mut spans = vec![];
+        // where the `(` of an identifier is, until its `)` is found
+        let mut group_start = None;
 
         let mut token = self.token();
 
         use TokenKind::*;
-        while token.kind != End {
+        while !matches!(token.kind, End | Semicolon) {
             if let Lparen = token.kind {
+                group_start = Some(token.start);
                 self.expect(Ident)?;
             }
 
             let exp = match &token.kind {
-                Literal => AbstractReplaceExpression::Literal(&token.text),
+                Literal => AbstractReplaceExpression::Literal(Cow::Borrowed(*token.text)),
+                Ident if *token.text == "#" => AbstractReplaceExpression::Counter,
                 Ident => {
-                    if !declared_idents.contains(&token.text) {
-                        return Err(ParseError {
+                    if !declared_idents.contains(&token.text)
+                        && !BUILTIN_VARIABLES.contains(&token.text)
+                    {
+                        self.errors.push(ParseError {
                             source: self.lexer.input(),
                             kind: ParseErrorKind::UndeclaredIdentifier {
                                 ident: &token.text,
-                                declared: declared_idents,
+                                declared: declared_idents.clone(),
                                 position: token.start,
                             },
                         });
                     }
 
-                    AbstractReplaceExpression::Identifier(&token.text)
+                    match self.peek_token().kind {
+                        Colon => self.parse_transform(&token.text)?,
+                        Pipe => self.parse_fallback(&token.text)?,
+                        Question => self.parse_conditional(&token.text, &declared_idents)?,
+                        _ => AbstractReplaceExpression::Identifier(&token.text),
+                    }
                 }
                 _ => {
+                    close_group(&mut group_start, &mut spans, &token);
                     token = self.token();
                     continue;
                 }
             };
 
             expressions.push(exp);
+            spans.push(group_start.unwrap_or(token.start)..token.start + token.text.len());
 
             token = self.token();
         }
 
+        if token.kind == Semicolon {
+            // whitespace before a `;` is only there to separate the rules
+            if let Some(AbstractReplaceExpression::Literal(Cow::Borrowed(l))) =
+                expressions.last_mut()
+            {
+                *l = l.trim_end();
+                if l.is_empty() {
+                    expressions.pop();
+                    spans.pop();
+                } else if let Some(span) = spans.last_mut() {
+                    span.end = span.start + l.len();
+                }
+            }
+        }
+
+        // the end of the rule is left for the caller to see
+        self.peeked = Some(token);
+
         Ok(ReplaceExpression {
             expressions: expressions.into(),
+            spans: spans.into(),
         })
     }
 
+    fn parse_transform(
+        &mut self,
+        identifier: &'source str,
+    ) -> Result<'source, AbstractReplaceExpression<'source>> {
+        self.eat_token();
+
+        self.expect(TokenKind::Type)?;
+
+        let transform = match self.token() {
+            t if t.kind == TokenKind::Type => match *t.text {
+                "upper" => Transform::Upper,
+                "lower" => Transform::Lower,
+                "title" => Transform::Title,
+                "hex" => Transform::Hex,
+                "dec" => Transform::Dec,
+                "trim" => Transform::Trim,
+                "bytes" => Transform::Bytes,
+                unit if size::unit_factor(unit).is_some() => Transform::SizeIn(unit),
+                digest if identifier == HASH_VARIABLE && Transform::digest(digest).is_some() => {
+                    Transform::digest(digest).expect("it's a digest")
+                }
+                format if format.starts_with('%') && Transform::is_date_format(format) => {
+                    Transform::DateFormat(Cow::Borrowed(format))
+                }
+                text => match Transform::slice(text).or_else(|| Transform::function(text)) {
+                    Some(slice) => slice,
+                    None => {
+                        self.errors.push(ParseError {
+                            source: self.lexer.input(),
+                            kind: ParseErrorKind::UnsupportedTransform(t),
+                        });
+                        return Ok(AbstractReplaceExpression::Identifier(identifier));
+                    }
+                },
+            },
+            _ => unreachable!("we expected a type token"),
+        };
+
+        Ok(AbstractReplaceExpression::Transformed {
+            identifier,
+            transform,
+        })
+    }
+
+    fn parse_fallback(
+        &mut self,
+        identifier: &'source str,
+    ) -> Result<'source, AbstractReplaceExpression<'source>> {
+        self.eat_token();
+
+        let default = match self.peek_token() {
+            t if t.kind == TokenKind::Literal => *t.text,
+            _ => "",
+        };
+
+        if !default.is_empty() {
+            self.eat_token();
+        }
+
+        self.expect(TokenKind::Rparen)?;
+
+        Ok(AbstractReplaceExpression::Fallback {
+            identifier,
+            default: Cow::Borrowed(default),
+        })
+    }
+
+    fn parse_conditional(
+        &mut self,
+        identifier: &'source str,
+        declared_idents: &[&'source str],
+    ) -> Result<'source, AbstractReplaceExpression<'source>> {
+        self.eat_token();
+
+        self.expect(TokenKind::Str)?;
+        let then = self.token();
+
+        self.expect(TokenKind::Colon)?;
+        self.eat_token();
+
+        self.expect(TokenKind::Str)?;
+        let otherwise = self.token();
+
+        self.expect(TokenKind::Rparen)?;
+
+        Ok(AbstractReplaceExpression::Conditional {
+            identifier,
+            then: self.parse_branch(&then, declared_idents)?,
+            otherwise: self.parse_branch(&otherwise, declared_idents)?,
+        })
+    }
+
+    /// Parse the replacement expression inside of a string token.
+    fn parse_branch(
+        &mut self,
+        string: &Token<'source>,
+        declared_idents: &[&'source str],
+    ) -> Result<'source, Array<AbstractReplaceExpression<'source>>> {
+        // past the opening quote
+        let start = string.start + 1;
+        let lexer = Lexer::with_range(self.lexer.input(), start..start + string.text.len());
+
+        let mut parser = Parser::new(lexer);
+        let rex = parser.parse_replacement_exp(declared_idents.to_vec());
+        self.errors.append(&mut parser.errors);
+
+        rex.map(|rex| rex.expressions)
+    }
+
+    /// Parse a match and replace expression, giving the first error in it.
     pub fn parse(&mut self) -> Result<'source, MatchAndReplaceExpression<'source>> {
+        let expression = self.parse_rule();
+        self.first_error(expression)
+    }
+
+    /// Parse a match and replace expression, keeping any errors that can be recovered from in
+    /// `self.errors`.
+    fn parse_rule(&mut self) -> Result<'source, MatchAndReplaceExpression<'source>> {
+        self.parse_type_declarations()?;
+
         let mex = self.parse_match_exp()?;
         let declared_idents = mex
             .expressions
             .iter()
             .filter_map(|e| match e {
                 AbstractMatchingExpression::Literal(_) => None,
+                AbstractMatchingExpression::Lookahead(_) => None,
                 AbstractMatchingExpression::Capture { identifier, .. } => Some(*identifier),
             })
             .collect();
@@ -258,13 +1152,55 @@ impl<'source> Parser<'source> {
 
         Ok(expression)
     }
+
+    /// Parse a replacement on its own, like `(stem)-(#).(ext)`, to write a whole new value with;
+    /// since nothing is captured, it can only use the built-in variables and the counter.
+    pub fn parse_template(&mut self) -> Result<'source, ReplaceExpression<'source>> {
+        let template = self.parse_replacement_exp(vec![]);
+        let template = self.first_error(template)?;
+        self.expect(TokenKind::End)?;
+        Ok(template)
+    }
+
+    /// Parse one or more match and replace expressions, separated by `;`, giving the first error
+    /// in them.
+    pub fn parse_rules(&mut self) -> Result<'source, MatchAndReplaceRules<'source>> {
+        self.parse_rules_recovering()
+            .map_err(|errors| errors.into_iter().next().expect("there to be an error"))
+    }
+
+    /// Parse one or more match and replace expressions, separated by `;`, giving every error
+    /// in them. A rule with an error that can't be recovered from is skipped, so that the rules
+    /// after it can still be checked.
+    pub fn parse_rules_recovering(
+        &mut self,
+    ) -> std::result::Result<MatchAndReplaceRules<'source>, Vec<ParseError<'source>>> {
+        let mut rules = vec![];
+
+        loop {
+            match self.parse_rule() {
+                Ok(rule) => rules.push(rule),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.skip_rule();
+                }
+            }
+
+            if self.token().kind == TokenKind::End || self.peek_token().kind == TokenKind::End {
+                break;
+            }
+        }
+
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
+
+        Ok(MatchAndReplaceRules { rules })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-
-    use std::sync::Arc;
-
     use super::*;
 
     #[test]
@@ -274,7 +1210,7 @@ mod tests {
 
         assert_eq!(
             p.parse_match_exp().unwrap(),
-            MatchExpression::new(vec![AbstractMatchingExpression::Literal("abc")])
+            MatchExpression::new(vec![AbstractMatchingExpression::Literal("abc".into())])
         );
 
         let input = "1234";
@@ -282,7 +1218,7 @@ mod tests {
 
         assert_eq!(
             p.parse_match_exp().unwrap(),
-            MatchExpression::new(vec![AbstractMatchingExpression::Literal("1234")],)
+            MatchExpression::new(vec![AbstractMatchingExpression::Literal("1234".into())],)
         )
     }
 
@@ -295,7 +1231,9 @@ mod tests {
             p.parse_match_exp().unwrap(),
             MatchExpression::new(vec![AbstractMatchingExpression::Capture {
                 identifier: "num",
-                identifier_type: CaptureType::Int
+                identifier_type: CaptureType::Int,
+                optional: false,
+                length: None
             }])
         );
     }
@@ -308,10 +1246,12 @@ mod tests {
         assert_eq!(
             p.parse_match_exp().unwrap(),
             MatchExpression::new(vec![
-                AbstractMatchingExpression::Literal("abc"),
+                AbstractMatchingExpression::Literal("abc".into()),
                 AbstractMatchingExpression::Capture {
                     identifier: "d",
-                    identifier_type: CaptureType::Digit
+                    identifier_type: CaptureType::Digit,
+                    optional: false,
+                    length: None
                 }
             ])
         )
@@ -325,22 +1265,28 @@ mod tests {
         assert_eq!(
             p.parse_match_exp().unwrap(),
             MatchExpression::new(vec![
-                AbstractMatchingExpression::Literal("abc235"),
+                AbstractMatchingExpression::Literal("abc235".into()),
                 AbstractMatchingExpression::Capture {
                     identifier: "d",
 
-                    identifier_type: CaptureType::Digit
+                    identifier_type: CaptureType::Digit,
+                    optional: false,
+                    length: None
                 },
-                AbstractMatchingExpression::Literal("zap"),
+                AbstractMatchingExpression::Literal("zap".into()),
                 AbstractMatchingExpression::Capture {
                     identifier: "num",
 
-                    identifier_type: CaptureType::Int
+                    identifier_type: CaptureType::Int,
+                    optional: false,
+                    length: None
                 },
                 AbstractMatchingExpression::Capture {
                     identifier: "d",
 
-                    identifier_type: CaptureType::Int
+                    identifier_type: CaptureType::Int,
+                    optional: false,
+                    length: None
                 },
             ])
         )
@@ -374,20 +1320,496 @@ mod tests {
             MatchExpression::new(vec![
                 AbstractMatchingExpression::Capture {
                     identifier: "num",
-                    identifier_type: CaptureType::Int
+                    identifier_type: CaptureType::Int,
+                    optional: false,
+                    length: None
                 },
-                AbstractMatchingExpression::Literal("asdf"),
+                AbstractMatchingExpression::Literal("asdf".into()),
             ])
         );
 
         assert_eq!(
             p.parse_replacement_exp(vec!["num"]).unwrap(),
-            ReplaceExpression {
-                expressions: Box::new([
-                    AbstractReplaceExpression::Literal("lul"),
-                    AbstractReplaceExpression::Identifier("num")
-                ])
+            ReplaceExpression::new(Box::new([
+                AbstractReplaceExpression::Literal("lul".into()),
+                AbstractReplaceExpression::Identifier("num")
+            ]))
+        )
+    }
+
+    #[test]
+    fn test_transformed_identifiers_in_replace_expression() {
+        let input = "(a:int)(b:int)->(a:upper)-(b:lower)(a:title)";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse().unwrap().rex,
+            ReplaceExpression::new(Box::new([
+                AbstractReplaceExpression::Transformed {
+                    identifier: "a",
+                    transform: Transform::Upper
+                },
+                AbstractReplaceExpression::Literal("-".into()),
+                AbstractReplaceExpression::Transformed {
+                    identifier: "b",
+                    transform: Transform::Lower
+                },
+                AbstractReplaceExpression::Transformed {
+                    identifier: "a",
+                    transform: Transform::Title
+                },
+            ]))
+        )
+    }
+
+    #[test]
+    fn test_slices_in_replace_expression() {
+        let input = "(h:int)->(h:0..8)(h:2..)(h:..3)";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse().unwrap().rex,
+            ReplaceExpression::new(Box::new([
+                AbstractReplaceExpression::Transformed {
+                    identifier: "h",
+                    transform: Transform::Slice {
+                        start: 0,
+                        end: Some(8)
+                    }
+                },
+                AbstractReplaceExpression::Transformed {
+                    identifier: "h",
+                    transform: Transform::Slice {
+                        start: 2,
+                        end: None
+                    }
+                },
+                AbstractReplaceExpression::Transformed {
+                    identifier: "h",
+                    transform: Transform::Slice {
+                        start: 0,
+                        end: Some(3)
+                    }
+                },
+            ]))
+        )
+    }
+
+    #[test]
+    fn test_optional_captures_and_fallbacks() {
+        let input = "ep(n:int?)(d:dig)->(n|none)(d|)";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse().unwrap(),
+            MatchAndReplaceExpression {
+                mex: MatchExpression::new(vec![
+                    AbstractMatchingExpression::Literal("ep".into()),
+                    AbstractMatchingExpression::Capture {
+                        identifier: "n",
+                        identifier_type: CaptureType::Int,
+                        optional: true,
+                        length: None
+                    },
+                    AbstractMatchingExpression::Capture {
+                        identifier: "d",
+                        identifier_type: CaptureType::Digit,
+                        optional: false,
+                        length: None
+                    },
+                ]),
+                rex: ReplaceExpression::new(Box::new([
+                    AbstractReplaceExpression::Fallback {
+                        identifier: "n",
+                        default: "none".into()
+                    },
+                    AbstractReplaceExpression::Fallback {
+                        identifier: "d",
+                        default: "".into()
+                    },
+                ]))
+            }
+        )
+    }
+
+    #[test]
+    fn test_counter_in_replace_expression() {
+        let input = "(n:int)->(#)-(n)";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse().unwrap().rex,
+            ReplaceExpression::new(Box::new([
+                AbstractReplaceExpression::Counter,
+                AbstractReplaceExpression::Literal("-".into()),
+                AbstractReplaceExpression::Identifier("n"),
+            ]))
+        )
+    }
+
+    #[test]
+    fn test_templates() {
+        let mut p = Parser::new(Lexer::new("(stem)-(#).(ext)"));
+
+        assert_eq!(
+            p.parse_template().unwrap(),
+            ReplaceExpression::new(Box::new([
+                AbstractReplaceExpression::Identifier("stem"),
+                AbstractReplaceExpression::Literal("-".into()),
+                AbstractReplaceExpression::Counter,
+                AbstractReplaceExpression::Literal(".".into()),
+                AbstractReplaceExpression::Identifier("ext"),
+            ]))
+        );
+
+        // nothing is captured, so there's nothing but the built-in variables to use
+        let mut p = Parser::new(Lexer::new("(n)-(#)"));
+        assert!(matches!(
+            p.parse_template().unwrap_err().kind,
+            ParseErrorKind::UndeclaredIdentifier { ident: "n", .. }
+        ));
+
+        let mut p = Parser::new(Lexer::new("(#); a->b"));
+        assert!(p.parse_template().is_err());
+    }
+
+    #[test]
+    fn test_builtin_variables_in_replace_expression() {
+        let input = "(n:int)->(parent)_(n).(ext:lower)";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse().unwrap().rex,
+            ReplaceExpression::new(Box::new([
+                AbstractReplaceExpression::Identifier("parent"),
+                AbstractReplaceExpression::Literal("_".into()),
+                AbstractReplaceExpression::Identifier("n"),
+                AbstractReplaceExpression::Literal(".".into()),
+                AbstractReplaceExpression::Transformed {
+                    identifier: "ext",
+                    transform: Transform::Lower
+                },
+            ]))
+        )
+    }
+
+    #[test]
+    fn test_digest_of_the_hash_variable() {
+        let input = "(h:hex)->(hash:sha256:8)(hash:sha256)";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse().unwrap().rex,
+            ReplaceExpression::new(Box::new([
+                AbstractReplaceExpression::Transformed {
+                    identifier: "hash",
+                    transform: Transform::Slice {
+                        start: 0,
+                        end: Some(8)
+                    }
+                },
+                AbstractReplaceExpression::Transformed {
+                    identifier: "hash",
+                    transform: Transform::Slice {
+                        start: 0,
+                        end: None
+                    }
+                },
+            ]))
+        );
+
+        // only the `hash` variable has a digest
+        let mut p = Parser::new(Lexer::new("(h:hex)->(h:sha256:8)"));
+        assert!(p.parse().is_err());
+    }
+
+    #[test]
+    fn test_multiple_rules() {
+        let input = "a(n:int)->b(n) ; foo->bar;";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse_rules().unwrap(),
+            MatchAndReplaceRules {
+                rules: vec![
+                    MatchAndReplaceExpression {
+                        mex: MatchExpression::new(vec![
+                            AbstractMatchingExpression::Literal("a".into()),
+                            AbstractMatchingExpression::Capture {
+                                identifier: "n",
+                                identifier_type: CaptureType::Int,
+                                optional: false,
+                                length: None
+                            },
+                        ]),
+                        rex: ReplaceExpression::new(Box::new([
+                            AbstractReplaceExpression::Literal("b".into()),
+                            AbstractReplaceExpression::Identifier("n"),
+                        ]))
+                    },
+                    MatchAndReplaceExpression {
+                        mex: MatchExpression::new(vec![AbstractMatchingExpression::Literal(
+                            "foo".into()
+                        )]),
+                        rex: ReplaceExpression::new(Box::new([
+                            AbstractReplaceExpression::Literal("bar".into())
+                        ]))
+                    }
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn test_conditional_in_replace_expression() {
+        let input = "ep(n:int?)->(n ? \"part(n)\" : \"single\")";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse().unwrap().rex,
+            ReplaceExpression::new(Box::new([AbstractReplaceExpression::Conditional {
+                identifier: "n",
+                then: Box::new([
+                    AbstractReplaceExpression::Literal("part".into()),
+                    AbstractReplaceExpression::Identifier("n"),
+                ]),
+                otherwise: Box::new([AbstractReplaceExpression::Literal("single".into())]),
+            }]))
+        )
+    }
+
+    #[test]
+    fn test_type_declarations_and_lengths() {
+        let input =
+            "type ep = int{2}; type maybe = alpha?; s(s:int)e(e:ep)(t:maybe)(x:hex{4})->(e)";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse().unwrap().mex,
+            MatchExpression::new(vec![
+                AbstractMatchingExpression::Literal("s".into()),
+                AbstractMatchingExpression::Capture {
+                    identifier: "s",
+                    identifier_type: CaptureType::Int,
+                    optional: false,
+                    length: None
+                },
+                AbstractMatchingExpression::Literal("e".into()),
+                AbstractMatchingExpression::Capture {
+                    identifier: "e",
+                    identifier_type: CaptureType::Int,
+                    optional: false,
+                    length: Some(2)
+                },
+                AbstractMatchingExpression::Capture {
+                    identifier: "t",
+                    identifier_type: CaptureType::Alpha,
+                    optional: true,
+                    length: None
+                },
+                AbstractMatchingExpression::Capture {
+                    identifier: "x",
+                    identifier_type: CaptureType::Hex,
+                    optional: false,
+                    length: Some(4)
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_lookahead() {
+        let input = "(n:int)(?=.mp4)->ep(n)";
+        let mut p = Parser::new(Lexer::new(input));
+
+        assert_eq!(
+            p.parse().unwrap().mex,
+            MatchExpression::new(vec![
+                AbstractMatchingExpression::Capture {
+                    identifier: "n",
+                    identifier_type: CaptureType::Int,
+                    optional: false,
+                    length: None
+                },
+                AbstractMatchingExpression::Lookahead(".mp4".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_owned_expressions() {
+        let owned = MatchAndReplaceExpressionOwned::from_str("a(n:int)->b(n)").unwrap();
+        assert_eq!(
+            *owned.expression(),
+            MatchAndReplaceExpression::from_str("a(n:int)->b(n)").unwrap()
+        );
+        assert_eq!(owned.source(), "a(n:int)->b(n)");
+
+        let owned = MatchExpressionOwned::from_str("a(n:int)").unwrap();
+        assert_eq!(
+            *owned.expression(),
+            MatchExpression::from_str("a(n:int)").unwrap()
+        );
+
+        let owned = MatchAndReplaceRulesOwned::from_str("a->b; c->d").unwrap();
+        assert_eq!(owned.rules().rules.len(), 2);
+
+        assert!(MatchAndReplaceExpressionOwned::from_str("a(n:int)->b(m)").is_err());
+        assert!(MatchAndReplaceExpressionOwned::from_str("a->b; c->d").is_err());
+        assert!(MatchAndReplaceRulesOwned::from_str("a->").is_err());
+    }
+
+    #[test]
+    fn test_owned_expressions_with_registered_types() {
+        let mut registry = CaptureTypeRegistry::new();
+        registry.register("upperword", |input| {
+            Some(input.chars().take_while(char::is_ascii_uppercase).count())
+        });
+
+        assert!(MatchAndReplaceRulesOwned::from_str("(w:upperword)->(w)").is_err());
+        let owned =
+            MatchAndReplaceRulesOwned::with_registry("(w:upperword)->(w)", registry).unwrap();
+        assert!(owned.registry().get("upperword").is_some());
+
+        // a clone shares the source, so it outlives the expression it was cloned from
+        let clone = owned.clone();
+        drop(owned);
+        assert!(matches!(
+            clone.rules().rules[0].mex.expressions[0],
+            AbstractMatchingExpression::Capture {
+                identifier: "w",
+                identifier_type: CaptureType::Custom(_),
+                ..
             }
+        ));
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = "ab(n:int)(?=.x)->(n:upper)-(#) ; c->d";
+        let rules = Parser::new(Lexer::new(input)).parse_rules().unwrap();
+        let rule = &rules.rules[0];
+
+        let spans: Vec<_> = rule.mex.spans.iter().map(|s| &input[s.clone()]).collect();
+        assert_eq!(spans, vec!["ab", "(n:int)", "(?=.x)"]);
+
+        let spans: Vec<_> = rule.rex.spans.iter().map(|s| &input[s.clone()]).collect();
+        assert_eq!(spans, vec!["(n:upper)", "-", "(#)"]);
+
+        assert_eq!(rules.rules[1].mex.span(0), Some(33..34));
+        assert_eq!(rules.rules[1].rex.span(0), Some(36..37));
+    }
+
+    #[test]
+    fn test_recovering_from_errors() {
+        let input = "a(n:int->(m)";
+        let errors = Parser::new(Lexer::new(input))
+            .parse_rules_recovering()
+            .unwrap_err();
+        let kinds: Vec<_> = errors.into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ParseErrorKind::ExpectedToken {
+                    expected: TokenKind::Rparen,
+                    found: TokenKind::Arrow,
+                    text: "->",
+                    position: 7
+                },
+                ParseErrorKind::UndeclaredIdentifier {
+                    ident: "m",
+                    declared: vec!["n"],
+                    position: 10
+                },
+            ]
+        );
+
+        let input = "(n:in)->(n:shout); a->; (x:int)->(y)";
+        let errors = Parser::new(Lexer::new(input))
+            .parse_rules_recovering()
+            .unwrap_err();
+        let positions: Vec<_> = errors.iter().map(|e| e.error_span().start).collect();
+        assert_eq!(positions, vec![3, 11, 22, 34]);
+
+        // the first of them is the one that's given when not recovering
+        assert_eq!(
+            Parser::new(Lexer::new(input))
+                .parse_rules()
+                .unwrap_err()
+                .kind,
+            ParseErrorKind::UnsupportedToken(Token {
+                kind: TokenKind::Type,
+                text: TokenText::Slice("in"),
+                start: 3
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let input =
+            "type ep = int{2}; s(s:ep)(?=e)e(e:int?)->(s:lpad 3 '0')x(e|1)(e ? \"!\" : \"\")";
+        let expression = Parser::new(Lexer::new(input)).parse().unwrap();
+
+        let json = serde_json::to_string(&expression).unwrap();
+        let deserialized: MatchAndReplaceExpression = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, expression);
+        assert_eq!(deserialized.mex.spans, expression.mex.spans);
+
+        let rules: MatchAndReplaceRules = serde_json::from_str(
+            r#"{"rules":[{"mex":{"expressions":[{"Literal":"a"}]},"rex":{"expressions":[{"Literal":"b"}]}}]}"#,
         )
+        .unwrap();
+        assert_eq!(rules, MatchAndReplaceRules::from_str("a->b").unwrap());
+
+        // text that is escaped in JSON can't be borrowed from it, so it's copied
+        let input = r"a\b(n:int)(?=\)->x\(n:replace '\' '/')(n|\)";
+        let expression = Parser::new(Lexer::new(input)).parse().unwrap();
+        let json = serde_json::to_string(&expression).unwrap();
+        let deserialized: MatchAndReplaceExpression = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, expression);
+        assert!(matches!(
+            &deserialized.mex.expressions[0],
+            AbstractMatchingExpression::Literal(Cow::Owned(text)) if text == r"a\b"
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serializing_a_registered_type() {
+        let mut registry = CaptureTypeRegistry::new();
+        registry.register("upperword", |input| {
+            Some(input.chars().take_while(char::is_ascii_uppercase).count())
+        });
+        let mut parser = Parser::new(Lexer::new("(w:upperword)->(w)"));
+        parser.set_registry(registry);
+        let expression = parser.parse().unwrap();
+
+        assert!(serde_json::to_string(&expression).is_err());
+    }
+
+    #[test]
+    fn test_parsing_rule_files() {
+        let source = "# episodes\ntype ep = int{2};\n\ns(s:ep)e(e:ep)->(s)x(e)  \n.jpeg->.jpg; IMG_->img_ # short\n";
+        let rules = parse_rules(source).unwrap();
+
+        assert_eq!(rules.len(), 3);
+        assert_eq!(
+            rules[0],
+            MatchAndReplaceExpression::from_str("type ep = int{2}; s(s:ep)e(e:ep)->(s)x(e)")
+                .unwrap()
+        );
+        assert_eq!(
+            rules[1],
+            MatchAndReplaceExpression::from_str(".jpeg->.jpg").unwrap()
+        );
+        assert_eq!(
+            rules[2],
+            MatchAndReplaceExpression::from_str("IMG_->img_").unwrap()
+        );
+
+        let err = parse_rules("a->b\n\n  c(n:in)->d\n").unwrap_err();
+        assert_eq!(err.line_and_column(), (3, 6));
     }
 }
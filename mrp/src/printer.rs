@@ -0,0 +1,221 @@
+//! Writing expressions back as MRP source, so that ones that were built or changed by a program
+//! can be shown to users, or stored to be parsed again later.
+
+use std::fmt::{Display, Formatter, Result};
+
+use crate::parser::{
+    AbstractMatchingExpression, AbstractReplaceExpression, CaptureType, MatchAndReplaceExpression,
+    MatchAndReplaceRules, MatchExpression, ReplaceExpression, Transform,
+};
+
+impl Display for CaptureType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str(match self {
+            CaptureType::Int => "int",
+            CaptureType::Digit => "dig",
+            CaptureType::Alpha => "alpha",
+            CaptureType::Hex => "hex",
+            CaptureType::Date => "date",
+            CaptureType::Timestamp => "timestamp",
+            CaptureType::Size => "size",
+            CaptureType::WordNum => "wordnum",
+            CaptureType::Custom(custom) => custom.name(),
+        })
+    }
+}
+
+/// Declared types are written as the types they stand for, e.g. `(s:int{2})` for `(s:ep)`
+/// after `type ep = int{2};`.
+impl<'source> Display for AbstractMatchingExpression<'source> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            AbstractMatchingExpression::Literal(text) => f.write_str(text),
+            AbstractMatchingExpression::Capture {
+                identifier,
+                identifier_type,
+                optional,
+                length,
+            } => {
+                write!(f, "({identifier}:{identifier_type}")?;
+                if let Some(length) = length {
+                    write!(f, "{{{length}}}")?;
+                }
+                if *optional {
+                    f.write_str("?")?;
+                }
+                f.write_str(")")
+            }
+            AbstractMatchingExpression::Lookahead(text) => write!(f, "(?={text})"),
+        }
+    }
+}
+
+impl<'source> Display for Transform<'source> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Transform::Upper => f.write_str("upper"),
+            Transform::Lower => f.write_str("lower"),
+            Transform::Title => f.write_str("title"),
+            Transform::Slice { start, end } => {
+                write!(f, "{start}..")?;
+                match end {
+                    Some(end) => write!(f, "{end}"),
+                    None => Ok(()),
+                }
+            }
+            Transform::Hex => f.write_str("hex"),
+            Transform::Dec => f.write_str("dec"),
+            Transform::DateFormat(format) => f.write_str(format),
+            Transform::Bytes => f.write_str("bytes"),
+            Transform::SizeIn(unit) => f.write_str(unit),
+            Transform::Trim => f.write_str("trim"),
+            Transform::PadLeft { width, fill } => write!(f, "lpad {width} '{fill}'"),
+            Transform::PadRight { width, fill } => write!(f, "rpad {width} '{fill}'"),
+            Transform::Replace { from, to } => write!(f, "replace '{from}' '{to}'"),
+        }
+    }
+}
+
+impl<'source> Display for AbstractReplaceExpression<'source> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            AbstractReplaceExpression::Literal(text) => f.write_str(text),
+            AbstractReplaceExpression::Identifier(identifier) => write!(f, "({identifier})"),
+            AbstractReplaceExpression::Transformed {
+                identifier,
+                transform,
+            } => write!(f, "({identifier}:{transform})"),
+            AbstractReplaceExpression::Fallback {
+                identifier,
+                default,
+            } => write!(f, "({identifier}|{default})"),
+            AbstractReplaceExpression::Counter => f.write_str("(#)"),
+            AbstractReplaceExpression::Conditional {
+                identifier,
+                then,
+                otherwise,
+            } => {
+                write!(f, "({identifier} ? \"")?;
+                write_all(f, then)?;
+                f.write_str("\" : \"")?;
+                write_all(f, otherwise)?;
+                f.write_str("\")")
+            }
+        }
+    }
+}
+
+fn write_all(f: &mut Formatter<'_>, expressions: &[impl Display]) -> Result {
+    expressions.iter().try_for_each(|e| e.fmt(f))
+}
+
+impl<'source> Display for MatchExpression<'source> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write_all(f, &self.expressions)
+    }
+}
+
+impl<'source> Display for ReplaceExpression<'source> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write_all(f, &self.expressions)
+    }
+}
+
+impl<'source> Display for MatchAndReplaceExpression<'source> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}->{}", self.mex, self.rex)
+    }
+}
+
+impl<'source> Display for MatchAndReplaceRules<'source> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for (i, rule) in self.rules.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            rule.fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{
+        lexer::Lexer,
+        parser::{Parser, ReplaceExpression},
+    };
+
+    fn round_trip(source: &str) -> String {
+        let rules = Parser::new(Lexer::new(source)).parse_rules().unwrap();
+        let printed = rules.to_string();
+        assert_eq!(
+            Parser::new(Lexer::new(&printed)).parse_rules().unwrap(),
+            rules
+        );
+        printed
+    }
+
+    #[test]
+    fn printing_what_was_parsed() {
+        for source in [
+            "file(n:int)->(n)renamed.txt",
+            "(n:int?)(t:alpha{3})(?=.mp4)->(t:upper)-(n|0)",
+            "(d:date)_(h:hex)_(w:wordnum)->(d:%d.%m.%Y)_(h:dec)_(w:lpad 3 '0')",
+            "(s:size)(t:timestamp)->(s:MiB)(s:bytes)(t:%Y)(#)",
+            "(a:alpha)(x:dig)->(a:0..2)(a:3..)(a:trim)(a:rpad 5 ' ')(a:replace '_' '-')",
+            "(n:int?)->(n ? \"_part(n)\" : \"(stem)\")",
+            "a->b; c->d",
+        ] {
+            assert_eq!(round_trip(source), source);
+        }
+    }
+
+    #[test]
+    fn printing_declared_types_as_what_they_stand_for() {
+        assert_eq!(
+            round_trip("type ep = int{2}; s(s:ep)e(e:ep?)->(s)x(e)"),
+            "s(s:int{2})e(e:int{2}?)->(s)x(e)"
+        );
+    }
+
+    #[test]
+    fn printing_built_expressions() {
+        let expression = MatchAndReplaceExpression {
+            mex: MatchExpression::new(vec![
+                AbstractMatchingExpression::Literal("IMG_".into()),
+                AbstractMatchingExpression::Capture {
+                    identifier: "n",
+                    identifier_type: CaptureType::Int,
+                    optional: false,
+                    length: None,
+                },
+            ]),
+            rex: ReplaceExpression::new(
+                vec![
+                    AbstractReplaceExpression::Literal("photo-".into()),
+                    AbstractReplaceExpression::Transformed {
+                        identifier: "n",
+                        transform: Transform::PadLeft {
+                            width: 4,
+                            fill: '0',
+                        },
+                    },
+                ]
+                .into(),
+            ),
+        };
+
+        assert_eq!(expression.to_string(), "IMG_(n:int)->photo-(n:lpad 4 '0')");
+        assert_eq!(
+            MatchAndReplaceExpression::from_str(&expression.to_string())
+                .unwrap()
+                .to_string(),
+            expression.to_string()
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::{fmt::Debug, sync::Arc};
+
+/// Finds how long a value of a custom capture type is, at the start of the input.
+type Scanner = dyn Fn(&str) -> Option<usize> + Send + Sync;
+
+/// A capture type registered by a user of the library, like `(v:semver)`.
+#[derive(Clone)]
+pub struct CustomCaptureType {
+    name: Arc<str>,
+    scanner: Arc<Scanner>,
+}
+
+impl CustomCaptureType {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Length of the value at the start of the input, if there is one. A length that is zero, or
+    /// that doesn't end on a character boundary in the input, doesn't count.
+    pub(crate) fn scan(&self, input: &str) -> Option<usize> {
+        (self.scanner)(input).filter(|len| *len > 0 && input.is_char_boundary(*len))
+    }
+}
+
+impl Debug for CustomCaptureType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CustomCaptureType")
+            .field(&self.name)
+            .finish()
+    }
+}
+
+impl PartialEq for CustomCaptureType {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && Arc::ptr_eq(&self.scanner, &other.scanner)
+    }
+}
+
+/// Capture types that the parser accepts on top of the built-in ones, once it's given them with
+/// [`Parser::set_registry`](crate::parser::Parser::set_registry).
+#[derive(Debug, Clone, Default)]
+pub struct CaptureTypeRegistry {
+    types: Vec<CustomCaptureType>,
+}
+
+impl CaptureTypeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a type, backed by a scanner that gives the length in bytes of the value at the
+    /// start of its input, or `None` if the input doesn't start with one. Registering a name again
+    /// replaces the type, and built-in types take precedence over ones registered with their name.
+    pub fn register<F>(&mut self, name: &str, scanner: F)
+    where
+        F: Fn(&str) -> Option<usize> + Send + Sync + 'static,
+    {
+        self.types.retain(|t| t.name() != name);
+        self.types.push(CustomCaptureType {
+            name: name.into(),
+            scanner: Arc::new(scanner),
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CustomCaptureType> {
+        self.types.iter().find(|t| t.name() == name)
+    }
+}
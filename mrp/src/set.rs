@@ -0,0 +1,160 @@
+//! Matching many expressions on an input at once, like the rules of a large rule file.
+
+use aho_corasick::AhoCorasick;
+
+use crate::{
+    captures::Captures, compile::CompiledMatchExpression, matcher::Match, parser::MatchExpression,
+};
+
+/// Expressions that are matched together, in the order they were given, which is also their
+/// priority. The literals that each of them needs are searched for in one pass over an input, so
+/// that only the expressions that can match it are run.
+#[derive(Debug, Clone)]
+pub struct MatchSet<'source> {
+    expressions: Vec<CompiledMatchExpression<'source>>,
+    literals: AhoCorasick,
+    /// For each of the literals, the expressions that need it.
+    needed_by: Vec<Vec<usize>>,
+    /// The expressions without a literal to look for, which are run on every input.
+    unfiltered: Vec<usize>,
+}
+
+impl<'source> MatchSet<'source> {
+    pub fn new<'e>(expressions: impl IntoIterator<Item = &'e MatchExpression<'source>>) -> Self
+    where
+        'source: 'e,
+    {
+        Self::from_compiled(expressions.into_iter().map(|mex| mex.compile()))
+    }
+
+    pub fn from_compiled(
+        expressions: impl IntoIterator<Item = CompiledMatchExpression<'source>>,
+    ) -> Self {
+        let expressions: Vec<_> = expressions.into_iter().collect();
+        let mut patterns: Vec<&str> = vec![];
+        let mut needed_by: Vec<Vec<usize>> = vec![];
+        let mut unfiltered = vec![];
+
+        for (idx, compiled) in expressions.iter().enumerate() {
+            let Some(literal) = compiled.required_literal() else {
+                unfiltered.push(idx);
+                continue;
+            };
+
+            match patterns.iter().position(|p| *p == literal) {
+                Some(pattern) => needed_by[pattern].push(idx),
+                None => {
+                    patterns.push(literal);
+                    needed_by.push(vec![idx]);
+                }
+            }
+        }
+
+        Self {
+            literals: AhoCorasick::new(&patterns),
+            expressions,
+            needed_by,
+            unfiltered,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.expressions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expressions.is_empty()
+    }
+
+    /// Whether each expression could match the input, as far as the literals it needs tell.
+    fn candidates(&self, input: &str) -> Vec<bool> {
+        let mut candidates = vec![false; self.expressions.len()];
+
+        for idx in &self.unfiltered {
+            candidates[*idx] = true;
+        }
+
+        for found in self.literals.find_overlapping_iter(input) {
+            for idx in &self.needed_by[found.pattern()] {
+                candidates[*idx] = true;
+            }
+        }
+
+        candidates
+    }
+
+    /// The indexes of the expressions that match the input, in order.
+    pub fn matches(&self, input: &str) -> Vec<usize> {
+        self.candidates(input)
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, candidate)| {
+                *candidate && self.expressions[*idx].run_program(input, 0, None).is_some()
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// The first of the expressions that matches the input, with its index, match and captures.
+    pub fn find<'input>(
+        &self,
+        input: &'input str,
+    ) -> Option<(usize, Match<'input>, Captures<'source, 'input>)> {
+        self.candidates(input)
+            .into_iter()
+            .enumerate()
+            .filter(|(_, candidate)| *candidate)
+            .find_map(|(idx, _)| {
+                let mut captures = Captures::new();
+                let m = self.expressions[idx].run_program(input, 0, Some(&mut captures))?;
+                Some((idx, m, captures))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn set(sources: &[&str]) -> MatchSet<'static> {
+        let expressions: Vec<_> = sources
+            .iter()
+            .map(|source| MatchExpression::from_str(source).unwrap())
+            .collect();
+
+        MatchSet::new(&expressions)
+    }
+
+    #[test]
+    fn matching_many_expressions() {
+        let set = set(&["IMG_(n:int)", "DSC_(n:int)", "(d:date)", "IMG_(n:int).png"]);
+
+        assert_eq!(set.matches("IMG_12.png"), vec![0, 3]);
+        assert_eq!(set.matches("DSC_3 2023-08-01"), vec![1, 2]);
+        assert!(set.matches("notes.txt").is_empty());
+
+        let (idx, m, captures) = set.find("trip/IMG_12.png").unwrap();
+        assert_eq!(idx, 0);
+        assert_eq!(m.as_str(), "IMG_12");
+        assert_eq!(captures.get("n"), Some("12"));
+
+        assert!(set.find("notes.txt").is_none());
+    }
+
+    #[test]
+    fn matching_expressions_without_literals_to_look_for() {
+        let expressions = [
+            MatchExpression::from_str("img_(n:int)")
+                .unwrap()
+                .compile()
+                .case_insensitive(true),
+            MatchExpression::from_str("(t:alpha)").unwrap().compile(),
+        ];
+        let set = MatchSet::from_compiled(expressions);
+
+        assert_eq!(set.matches("IMG_1"), vec![0, 1]);
+        assert_eq!(set.find("12").map(|(idx, ..)| idx), None);
+    }
+}
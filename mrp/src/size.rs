@@ -0,0 +1,138 @@
+/// The units a size can be converted to, with the number of bytes in each.
+const UNITS: [(&str, u64); 10] = [
+    ("KB", 1000),
+    ("MB", 1000_u64.pow(2)),
+    ("GB", 1000_u64.pow(3)),
+    ("TB", 1000_u64.pow(4)),
+    ("PB", 1000_u64.pow(5)),
+    ("KiB", 1024),
+    ("MiB", 1024_u64.pow(2)),
+    ("GiB", 1024_u64.pow(3)),
+    ("TiB", 1024_u64.pow(4)),
+    ("PiB", 1024_u64.pow(5)),
+];
+
+/// The number of bytes in a unit like `MB` or `GiB`.
+pub(crate) fn unit_factor(unit: &str) -> Option<u64> {
+    UNITS.iter().find(|(u, _)| *u == unit).map(|(_, f)| *f)
+}
+
+/// Length of the size, like `12MB`, `1.5GiB`, `300k` or `512B`, at the start of the input.
+pub(crate) fn scan_size(input: &str) -> Option<usize> {
+    let (number_len, unit_len, _) = split_size(input)?;
+    Some(number_len + unit_len)
+}
+
+/// Number of bytes in a size like `12MB` or `1.5GiB`; a number without a unit, like the `size`
+/// variable, is already one.
+pub(crate) fn parse_size(text: &str) -> Option<f64> {
+    if let Ok(bytes) = text.parse::<u64>() {
+        return Some(bytes as f64);
+    }
+
+    let (number_len, unit_len, factor) = split_size(text)?;
+
+    if number_len + unit_len != text.len() {
+        return None;
+    }
+
+    let number: f64 = text[..number_len].parse().ok()?;
+    Some(number * factor as f64)
+}
+
+/// Lengths of the number and the unit of the size at the start of the input, and the number of
+/// bytes in the unit. Units are read ignoring case, and without an `i` they are powers of 1000.
+fn split_size(input: &str) -> Option<(usize, usize, u64)> {
+    let bytes = input.as_bytes();
+    let digits_at = |position: usize| {
+        bytes[position..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count()
+    };
+
+    let mut number_len = digits_at(0);
+
+    if number_len == 0 {
+        return None;
+    }
+
+    if bytes.get(number_len) == Some(&b'.') && digits_at(number_len + 1) > 0 {
+        number_len += 1 + digits_at(number_len + 1);
+    }
+
+    let unit = &bytes[number_len..];
+    let is = |position: usize, c: u8| {
+        unit.get(position)
+            .is_some_and(|u| u.eq_ignore_ascii_case(&c))
+    };
+
+    let (unit_len, factor) = match unit.first().map(u8::to_ascii_uppercase) {
+        Some(b'B') => (1, 1),
+        Some(prefix @ (b'K' | b'M' | b'G' | b'T' | b'P')) => {
+            let power = match prefix {
+                b'K' => 1,
+                b'M' => 2,
+                b'G' => 3,
+                b'T' => 4,
+                _ => 5,
+            };
+
+            let binary = is(1, b'i');
+            let base: u64 = if binary { 1024 } else { 1000 };
+            let len = 1 + binary as usize;
+            let len = len + is(len, b'b') as usize;
+
+            (len, base.pow(power))
+        }
+        _ => return None,
+    };
+
+    // a unit has to be the whole of a word, so `12Mbps` isn't a size
+    if unit.get(unit_len).is_some_and(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    Some((number_len, unit_len, factor))
+}
+
+/// Write a number of bytes in the given unit, with at most two decimal places.
+pub(crate) fn format_size(bytes: f64, unit: &str, factor: u64) -> String {
+    let value = format!("{:.2}", bytes / factor as f64);
+    let value = value.trim_end_matches('0').trim_end_matches('.');
+
+    format!("{value}{unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scanning_sizes() {
+        assert_eq!(scan_size("12MB.csv"), Some(4));
+        assert_eq!(scan_size("1.5GiB"), Some(6));
+        assert_eq!(scan_size("300k_rows"), Some(4));
+        assert_eq!(scan_size("512B"), Some(4));
+        assert_eq!(scan_size("2.mb"), None);
+        assert_eq!(scan_size("12Mbps"), None);
+        assert_eq!(scan_size("12"), None);
+        assert_eq!(scan_size("MB"), None);
+    }
+
+    #[test]
+    fn parsing_and_formatting_sizes() {
+        assert_eq!(parse_size("1.5GiB"), Some(1610612736.0));
+        assert_eq!(parse_size("12MB"), Some(12_000_000.0));
+        assert_eq!(parse_size("3k"), Some(3000.0));
+        assert_eq!(parse_size("3kx"), None);
+        assert_eq!(parse_size("1536"), Some(1536.0));
+        assert_eq!(parse_size("-1536"), None);
+
+        assert_eq!(format_size(1610612736.0, "MiB", 1024 * 1024), "1536MiB");
+        assert_eq!(format_size(1_500_000.0, "MB", 1_000_000), "1.5MB");
+        assert_eq!(format_size(1000.0, "KiB", 1024), "0.98KiB");
+        assert_eq!(unit_factor("GB"), Some(1_000_000_000));
+        assert_eq!(unit_factor("gb"), None);
+    }
+}
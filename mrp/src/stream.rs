@@ -0,0 +1,110 @@
+//! Matching the lines of a reader one at a time, so that a large input doesn't have to be read
+//! into memory all at once.
+
+use std::{
+    io::{self, BufRead},
+    ops::Range,
+};
+
+use crate::compile::CompiledMatchExpression;
+
+/// A line that the expression matched, with where each of its matches is.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchedLine {
+    /// Which line of the input it is, counting from 1.
+    pub number: usize,
+    /// The line, without its line ending.
+    pub line: String,
+    /// The ranges of bytes of the line that were matched, from the left.
+    pub ranges: Vec<Range<usize>>,
+}
+
+impl MatchedLine {
+    /// The text of each match.
+    pub fn matches(&self) -> impl Iterator<Item = &str> {
+        self.ranges.iter().map(|range| &self.line[range.clone()])
+    }
+}
+
+/// The lines of a reader that an expression matches; see
+/// [`CompiledMatchExpression::find_lines`].
+#[derive(Debug)]
+pub struct ReaderMatches<'c, 'source, R> {
+    compiled: &'c CompiledMatchExpression<'source>,
+    reader: R,
+    /// Lines read so far.
+    number: usize,
+}
+
+impl<'c, 'source, R: BufRead> Iterator for ReaderMatches<'c, 'source, R> {
+    type Item = io::Result<MatchedLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            self.number += 1;
+
+            let ending = match line.ends_with("\r\n") {
+                true => 2,
+                false => usize::from(line.ends_with('\n')),
+            };
+            line.truncate(line.len() - ending);
+
+            let ranges: Vec<_> = self.compiled.find_iter(&line).map(|m| m.range()).collect();
+
+            if !ranges.is_empty() {
+                return Some(Ok(MatchedLine {
+                    number: self.number,
+                    line,
+                    ranges,
+                }));
+            }
+        }
+    }
+}
+
+impl<'source> CompiledMatchExpression<'source> {
+    /// Every line of the reader that the expression matches, read one at a time.
+    pub fn find_lines<R: BufRead>(&self, reader: R) -> ReaderMatches<'_, 'source, R> {
+        ReaderMatches {
+            compiled: self,
+            reader,
+            number: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::parser::MatchExpression;
+
+    use super::*;
+
+    #[test]
+    fn matching_the_lines_of_a_reader() {
+        let compiled = MatchExpression::from_str("ep(n:int)").unwrap().compile();
+        let input = "show-ep1\r\nnothing\nep2 and ep3\n\nep4";
+
+        let lines: Vec<_> = compiled
+            .find_lines(input.as_bytes())
+            .collect::<io::Result<_>>()
+            .unwrap();
+
+        assert_eq!(
+            lines.iter().map(|l| l.number).collect::<Vec<_>>(),
+            vec![1, 3, 5]
+        );
+        assert_eq!(lines[0].line, "show-ep1");
+        assert_eq!(lines[0].ranges, vec![5..8]);
+        assert_eq!(lines[1].matches().collect::<Vec<_>>(), vec!["ep2", "ep3"]);
+        assert_eq!(lines[2].matches().collect::<Vec<_>>(), vec!["ep4"]);
+    }
+}
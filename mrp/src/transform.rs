@@ -0,0 +1,237 @@
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, NaiveDate, NaiveDateTime,
+};
+
+use crate::{parser::Transform, size};
+
+/// The formats in which a value can be read as a date or time, to be written in another format.
+const DATE_TIME_FORMATS: [&str; 3] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y%m%dT%H%M%S",
+];
+const DATE_FORMATS: [&str; 2] = ["%Y-%m-%d", "%Y%m%d"];
+const ZONED_DATE_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f%z";
+
+impl<'source> Transform<'source> {
+    pub(crate) fn is_date_format(format: &str) -> bool {
+        !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+    }
+
+    /// Produce the transformed version of a captured value.
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Transform::Upper => value.to_uppercase(),
+            Transform::Lower => value.to_lowercase(),
+            Transform::Title => title_case(value),
+            Transform::Slice { start, end } => {
+                let chars = value.chars().skip(*start);
+                match end {
+                    Some(end) => chars.take(end - start).collect(),
+                    None => chars.collect(),
+                }
+            }
+            Transform::Hex => match value.parse::<u128>() {
+                Ok(n) => format!("{n:x}"),
+                Err(_) => value.to_string(),
+            },
+            Transform::Dec => match u128::from_str_radix(value, 16) {
+                Ok(n) => n.to_string(),
+                Err(_) => value.to_string(),
+            },
+            Transform::DateFormat(format) => match parse_date_time(value) {
+                Some(date_time) => date_time.format(format).to_string(),
+                None => value.to_string(),
+            },
+            Transform::Bytes => match size::parse_size(value) {
+                Some(bytes) => format!("{}", bytes.round()),
+                None => value.to_string(),
+            },
+            Transform::SizeIn(unit) => match (size::parse_size(value), size::unit_factor(unit)) {
+                (Some(bytes), Some(factor)) => size::format_size(bytes, unit, factor),
+                _ => value.to_string(),
+            },
+            Transform::Trim => value.trim().to_string(),
+            Transform::PadLeft { width, fill } => {
+                let padding = width.saturating_sub(value.chars().count());
+                std::iter::repeat_n(*fill, padding)
+                    .chain(value.chars())
+                    .collect()
+            }
+            Transform::PadRight { width, fill } => {
+                let padding = width.saturating_sub(value.chars().count());
+                value
+                    .chars()
+                    .chain(std::iter::repeat_n(*fill, padding))
+                    .collect()
+            }
+            Transform::Replace { from, to } => value.replace(from.as_ref(), to),
+        }
+    }
+}
+
+/// Read a date or time, where a time with a timezone is kept as it was written in that timezone.
+fn parse_date_time(value: &str) -> Option<NaiveDateTime> {
+    DATE_TIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())
+        .or_else(|| {
+            DateTime::parse_from_rfc3339(value)
+                .or_else(|_| DateTime::parse_from_str(value, ZONED_DATE_TIME_FORMAT))
+                .ok()
+                .map(|d| d.naive_local())
+        })
+        .or_else(|| {
+            DATE_FORMATS
+                .iter()
+                .find_map(|f| NaiveDate::parse_from_str(value, f).ok())
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// Uppercase the first letter of every word and lowercase the rest, where words are
+/// runs of alphanumeric characters.
+fn title_case(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut at_word_start = true;
+
+    for ch in value.chars() {
+        if ch.is_alphanumeric() {
+            if at_word_start {
+                result.extend(ch.to_uppercase());
+            } else {
+                result.extend(ch.to_lowercase());
+            }
+            at_word_start = false;
+        } else {
+            result.push(ch);
+            at_word_start = true;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_transforms() {
+        assert_eq!(Transform::Upper.apply("shout me"), "SHOUT ME");
+        assert_eq!(Transform::Lower.apply("QUIET_PLEASE"), "quiet_please");
+        assert_eq!(
+            Transform::Title.apply("THE_GREAT gatsby"),
+            "The_Great Gatsby"
+        );
+        assert_eq!(Transform::Title.apply("éLAN-vital"), "Élan-Vital");
+    }
+
+    #[test]
+    fn slices_on_char_boundaries() {
+        let slice = |start, end| Transform::Slice { start, end };
+
+        assert_eq!(slice(0, Some(8)).apply("a3f9c2d17be04"), "a3f9c2d1");
+        assert_eq!(slice(2, None).apply("a3f9"), "f9");
+        assert_eq!(slice(0, Some(3)).apply("日本語の"), "日本語");
+        assert_eq!(slice(3, Some(10)).apply("abcd"), "d");
+        assert_eq!(slice(5, Some(10)).apply("abcd"), "");
+    }
+
+    #[test]
+    fn date_formats() {
+        assert_eq!(
+            Transform::DateFormat("%Y%m%d_%H%M".into()).apply("2023-08-01T12:30:00"),
+            "20230801_1230"
+        );
+        assert_eq!(
+            Transform::DateFormat("%d.%m.%Y".into()).apply("2023-08-01"),
+            "01.08.2023"
+        );
+        assert_eq!(
+            Transform::DateFormat("%Y".into()).apply("not a date"),
+            "not a date"
+        );
+
+        assert_eq!(
+            Transform::DateFormat("%Y%m%d_%H%M".into()).apply("2023-08-01T12:30:00Z"),
+            "20230801_1230"
+        );
+        assert_eq!(
+            Transform::DateFormat("%H%M%S".into()).apply("2023-08-01T12:30:05.5+02:00"),
+            "123005"
+        );
+        assert_eq!(
+            Transform::DateFormat("%H%M".into()).apply("2023-08-01T12:30:00-0500"),
+            "1230"
+        );
+
+        assert!(Transform::is_date_format("%Y-%m-%d"));
+        assert!(!Transform::is_date_format("%Q"));
+    }
+
+    #[test]
+    fn base_conversions() {
+        assert_eq!(Transform::Hex.apply("255"), "ff");
+        assert_eq!(Transform::Hex.apply("0010"), "a");
+        assert_eq!(Transform::Dec.apply("1F"), "31");
+        assert_eq!(Transform::Dec.apply("00ff"), "255");
+        assert_eq!(Transform::Dec.apply("xyz"), "xyz");
+        assert_eq!(Transform::Hex.apply(""), "");
+    }
+
+    #[test]
+    fn size_conversions() {
+        assert_eq!(Transform::Bytes.apply("1.5GiB"), "1610612736");
+        assert_eq!(Transform::Bytes.apply("12MB"), "12000000");
+        assert_eq!(Transform::SizeIn("MiB").apply("1.5GiB"), "1536MiB");
+        assert_eq!(Transform::SizeIn("GB").apply("1500MB"), "1.5GB");
+        assert_eq!(Transform::SizeIn("GB").apply("big"), "big");
+    }
+
+    #[test]
+    fn string_functions() {
+        assert_eq!(Transform::Trim.apply("  the end "), "the end");
+        assert_eq!(
+            Transform::PadLeft {
+                width: 3,
+                fill: '0'
+            }
+            .apply("7"),
+            "007"
+        );
+        assert_eq!(
+            Transform::PadLeft {
+                width: 2,
+                fill: '0'
+            }
+            .apply("123"),
+            "123"
+        );
+        assert_eq!(
+            Transform::PadRight {
+                width: 4,
+                fill: '_'
+            }
+            .apply("日本"),
+            "日本__"
+        );
+        assert_eq!(
+            Transform::Replace {
+                from: "_".into(),
+                to: "-".into(),
+            }
+            .apply("a_b_c"),
+            "a-b-c"
+        );
+        assert_eq!(
+            Transform::Replace {
+                from: " ".into(),
+                to: "".into(),
+            }
+            .apply("no spaces"),
+            "nospaces"
+        );
+    }
+}
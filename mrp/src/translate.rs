@@ -0,0 +1,437 @@
+//! Translating between MRP expressions and regular expressions.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    matcher::{NUMBER_WORDS, ORDINAL_WORDS, TENS_ORDINAL_WORDS, TENS_WORDS},
+    parser::{
+        AbstractMatchingExpression, CaptureType, MatchAndReplaceExpressionOwned, MatchExpression,
+    },
+    OwnedParseError,
+};
+
+impl<'source> MatchExpression<'source> {
+    /// A pattern for the `regex` crate that matches what this expression does, with a named group
+    /// for each capture. There is none when the expression has a part that such a pattern can't
+    /// express, like a lookahead or a custom capture type.
+    ///
+    /// Dates, sizes and word numbers are checked more loosely than by the expression; e.g. the
+    /// pattern doesn't check that a date is a real one.
+    pub fn to_regex(&self) -> Option<String> {
+        let mut pattern = String::new();
+
+        for e in &self.expressions {
+            match e {
+                AbstractMatchingExpression::Literal(text) => pattern.push_str(&regex::escape(text)),
+                AbstractMatchingExpression::Capture {
+                    identifier,
+                    identifier_type,
+                    optional,
+                    length,
+                } => {
+                    let value = match (identifier_type, length) {
+                        (_, Some(length)) => format!("{}{{{length}}}", class_of(identifier_type)?),
+                        (CaptureType::Int | CaptureType::Alpha | CaptureType::Hex, None) => {
+                            format!("{}+", class_of(identifier_type)?)
+                        }
+                        _ => class_of(identifier_type)?,
+                    };
+
+                    pattern.push_str(&format!("(?P<{identifier}>{value})"));
+                    if *optional {
+                        pattern.push('?');
+                    }
+                }
+                AbstractMatchingExpression::Lookahead(_) => return None,
+            }
+        }
+
+        Some(pattern)
+    }
+}
+
+/// A pattern for one value of the type, or for a single character of it when the type captures
+/// as many as it can.
+fn class_of(identifier_type: &CaptureType) -> Option<String> {
+    const DATE: &str = "[0-9]{4}-[0-9]{2}-[0-9]{2}";
+
+    let class = match identifier_type {
+        CaptureType::Int | CaptureType::Digit => "[0-9]".to_string(),
+        CaptureType::Alpha => r"\p{Alphabetic}".to_string(),
+        CaptureType::Hex => "[0-9a-fA-F]".to_string(),
+        CaptureType::Date => DATE.to_string(),
+        CaptureType::Timestamp => format!(
+            r"{DATE}T[0-9]{{2}}:[0-9]{{2}}:[0-9]{{2}}(?:\.[0-9]+)?(?:Z|[+-][0-9]{{2}}:?[0-9]{{2}})?"
+        ),
+        CaptureType::Size => r"[0-9]+(?:\.[0-9]+)?(?i:b|[kmgtp]i?b?)".to_string(),
+        CaptureType::WordNum => {
+            let units = alternatives(&[&NUMBER_WORDS[1..10], &ORDINAL_WORDS[1..10]]);
+            let tens = alternatives(&[&TENS_WORDS]);
+            let others = alternatives(&[&TENS_ORDINAL_WORDS, &NUMBER_WORDS, &ORDINAL_WORDS]);
+
+            format!(r"(?i:\b(?:(?:{tens})(?:-?(?:{units}))?|{others})\b)")
+        }
+        CaptureType::Custom(_) => return None,
+    };
+
+    Some(class)
+}
+
+/// The words as alternatives, longest first so that a word isn't cut short by another that
+/// starts it, like `seven` in `seventeen`.
+fn alternatives(words: &[&[&str]]) -> String {
+    let mut words: Vec<_> = words.iter().flat_map(|words| words.iter()).collect();
+    words.sort_by_key(|w| std::cmp::Reverse(w.len()));
+
+    words.into_iter().copied().collect::<Vec<_>>().join("|")
+}
+
+/// Why a regex couldn't be written as a match and replace expression.
+#[derive(Debug, PartialEq, Clone)]
+pub enum FromRegexError {
+    /// The pattern isn't a valid regex.
+    Regex(regex::Error),
+    /// A part of the pattern or the replacement, like `[a-z]` or `$0`, that has no equivalent.
+    Unsupported { text: String, position: usize },
+    /// The expression written for the regex didn't parse.
+    Parse(OwnedParseError),
+}
+
+impl std::error::Error for FromRegexError {}
+
+impl Display for FromRegexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FromRegexError::Regex(e) => e.fmt(f),
+            FromRegexError::Unsupported { text, position } => write!(
+                f,
+                "{text:?} at {position} can't be written as a match and replace expression; \
+                only literals, \\d, \\d+, \\d{{n}}, and groups of them, may be used"
+            ),
+            FromRegexError::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl MatchAndReplaceExpressionOwned {
+    /// Write a regex, made of literals, `\d`, `\d+` or `\d{n}`, and groups of them, and a
+    /// replacement, with references like `$1` or `${name}`, as a match and replace expression.
+    pub fn from_regex(pattern: &str, replacement: &str) -> Result<Self, FromRegexError> {
+        let regex = regex::Regex::new(pattern).map_err(FromRegexError::Regex)?;
+        let names: Vec<String> = regex.capture_names().flatten().map(String::from).collect();
+        let mut taken = names.clone();
+        // the names of the groups, by their number
+        let mut groups = vec![];
+        let mut source = String::new();
+        let mut position = 0;
+
+        while position < pattern.len() {
+            let rest = &pattern[position..];
+            let unsupported = |len: usize| FromRegexError::Unsupported {
+                text: rest[..len].to_string(),
+                position,
+            };
+
+            if let Some((type_text, len)) = digits_of(rest) {
+                // some digits that can't be referred to, but still have to be captured
+                let name = unused_name(&mut taken);
+                source.push_str(&format!("({name}:{type_text})"));
+                position += len;
+                continue;
+            }
+
+            if let Some(group) = rest.strip_prefix('(') {
+                let group_len = group.find(')').map_or(rest.len(), |end| end + 2);
+                let (name, body) = match group.strip_prefix("?P<") {
+                    Some(named) => match named.split_once('>') {
+                        Some((name, body)) => (name.to_string(), body),
+                        None => return Err(unsupported(group_len)),
+                    },
+                    None if group.starts_with('?') => return Err(unsupported(group_len)),
+                    None => (unused_name(&mut taken), group),
+                };
+
+                let (type_text, len) = match digits_of(body) {
+                    Some((type_text, len)) if body[len..].starts_with(')') => (type_text, len),
+                    _ => return Err(unsupported(group_len)),
+                };
+
+                if !name.bytes().all(|c| c.is_ascii_alphabetic()) {
+                    return Err(unsupported(group_len));
+                }
+
+                source.push_str(&format!("({name}:{type_text})"));
+                groups.push(name);
+                position += rest.len() - body.len() + len + 1;
+                continue;
+            }
+
+            let (c, len) = match rest.chars().next() {
+                Some('\\') => match rest[1..].chars().next() {
+                    Some(c) if c.is_ascii_punctuation() => (c, 2),
+                    _ => return Err(unsupported(rest.len().min(2))),
+                },
+                Some(c) if "^$.*+?|()[]{}".contains(c) => return Err(unsupported(1)),
+                Some(c) => (c, c.len_utf8()),
+                None => unreachable!("the position is before the end"),
+            };
+
+            push_literal(&mut source, c).ok_or_else(|| unsupported(len))?;
+            position += len;
+        }
+
+        source.push_str("->");
+        write_replacement(&mut source, replacement, &groups, &names)?;
+
+        Self::from_str(&source).map_err(FromRegexError::Parse)
+    }
+}
+
+/// The type of the capture for the digits, like `\d+`, at the start of a regex, and how long
+/// they are written.
+fn digits_of(pattern: &str) -> Option<(String, usize)> {
+    let rest = pattern.strip_prefix(r"\d")?;
+
+    if rest.starts_with('+') {
+        return Some(("int".to_string(), 3));
+    }
+
+    if let Some((length, _)) = rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+        if length.parse::<usize>().is_ok_and(|l| l > 0) {
+            return Some((format!("int{{{length}}}"), 4 + length.len()));
+        }
+    }
+
+    Some(("dig".to_string(), 2))
+}
+
+/// A name for a capture, like `a` or `ab`, that isn't taken yet; which then is.
+fn unused_name(taken: &mut Vec<String>) -> String {
+    let name = (0..)
+        .map(|mut n: usize| {
+            let mut name = String::new();
+            loop {
+                name.insert(0, (b'a' + (n % 26) as u8) as char);
+                n /= 26;
+                if n == 0 {
+                    break name;
+                }
+                n -= 1;
+            }
+        })
+        .find(|name| !taken.contains(name))
+        .expect("there to be a name left");
+
+    taken.push(name.clone());
+    name
+}
+
+/// Add a character to a literal, unless it would be read as part of the syntax around it.
+fn push_literal(source: &mut String, c: char) -> Option<()> {
+    let is_syntax = match c {
+        '(' | ')' | ':' | ';' => true,
+        '>' => source.ends_with('-'),
+        '#' => source.chars().last().is_none_or(char::is_whitespace),
+        _ => false,
+    };
+
+    if is_syntax {
+        return None;
+    }
+
+    source.push(c);
+    Some(())
+}
+
+/// Write a regex replacement, with references like `$1`, `$name` or `${name}`, as the replace
+/// side of an expression.
+fn write_replacement(
+    source: &mut String,
+    replacement: &str,
+    groups: &[String],
+    names: &[String],
+) -> Result<(), FromRegexError> {
+    let mut position = 0;
+
+    while position < replacement.len() {
+        let rest = &replacement[position..];
+        let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+        let reference = match rest.strip_prefix('$') {
+            Some(r) if r.starts_with('{') => r[1..].find('}').map(|end| (&r[1..end + 1], end + 3)),
+            Some(r) => {
+                let len = r.find(|c| !is_name(c)).unwrap_or(r.len());
+                (len > 0).then(|| (&r[..len], len + 1))
+            }
+            None => None,
+        };
+
+        match reference {
+            Some((reference, len)) => {
+                let name = match reference.parse::<usize>() {
+                    Ok(n) if n > 0 => groups.get(n - 1),
+                    Ok(_) => None,
+                    Err(_) => names.iter().find(|name| *name == reference),
+                };
+
+                match name {
+                    Some(name) => source.push_str(&format!("({name})")),
+                    None => {
+                        return Err(FromRegexError::Unsupported {
+                            text: rest[..len].to_string(),
+                            position,
+                        })
+                    }
+                }
+
+                position += len;
+            }
+            None => {
+                // `$$` is a `$`, as is one that doesn't start a reference
+                let c = rest.chars().next().expect("the position is before the end");
+                let len = if rest.starts_with("$$") {
+                    2
+                } else {
+                    c.len_utf8()
+                };
+
+                push_literal(source, c).ok_or_else(|| FromRegexError::Unsupported {
+                    text: rest[..len].to_string(),
+                    position,
+                })?;
+
+                position += len;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use regex::Regex;
+
+    use super::*;
+
+    fn to_regex(expression: &str) -> Option<String> {
+        MatchExpression::from_str(expression).unwrap().to_regex()
+    }
+
+    #[test]
+    fn writing_expressions_as_regex() {
+        assert_eq!(
+            to_regex("file(n:int).txt").unwrap(),
+            r"file(?P<n>[0-9]+)\.txt"
+        );
+        assert_eq!(
+            to_regex("(t:alpha{3})(x:hex?)(d:dig)").unwrap(),
+            r"(?P<t>\p{Alphabetic}{3})(?P<x>[0-9a-fA-F]+)?(?P<d>[0-9])"
+        );
+        assert_eq!(to_regex("(n:int)(?=.mp4)"), None);
+    }
+
+    #[test]
+    fn matching_like_the_expression() {
+        let cases = [
+            ("IMG_(n:int)_(t:alpha)", "x IMG_0042_café.jpg", "0042"),
+            ("(d:date)", "log-2023-02-21.txt", "2023-02-21"),
+            (
+                "(t:timestamp)",
+                "at 2023-02-21T14:05:09+02:00!",
+                "2023-02-21T14:05:09+02:00",
+            ),
+            ("(s:size)", "dump-1.5GiB.tar", "1.5GiB"),
+            ("(w:wordnum)", "chapter-forty-second", "forty-second"),
+            ("(w:wordnum)", "the seventeenth", "seventeenth"),
+        ];
+
+        for (expression, input, captured) in cases {
+            let mex = MatchExpression::from_str(expression).unwrap();
+            let regex = Regex::new(&mex.to_regex().unwrap()).unwrap();
+            let found = regex.find(input).unwrap();
+            let first = mex.expressions.iter().find_map(|e| match e {
+                AbstractMatchingExpression::Capture { identifier, .. } => Some(*identifier),
+                _ => None,
+            });
+
+            assert_eq!(
+                found.as_str(),
+                mex.find_at(input, 0).unwrap().as_str(),
+                "{expression}"
+            );
+            assert_eq!(
+                &regex.captures(input).unwrap()[first.unwrap()],
+                captured,
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn reading_regexes_as_expressions() {
+        let from_regex = |pattern, replacement| {
+            MatchAndReplaceExpressionOwned::from_regex(pattern, replacement)
+                .map(|e| e.source().to_string())
+        };
+
+        assert_eq!(
+            from_regex(r"file(\d+)\.txt", "${1}renamed.txt").unwrap(),
+            "file(a:int).txt->(a)renamed.txt"
+        );
+        assert_eq!(
+            from_regex(r"(?P<a>\d{2})x(\d)_\d+", "$a-$2 $$5").unwrap(),
+            "(a:int{2})x(b:dig)_(c:int)->(a)-(b) $5"
+        );
+
+        assert_eq!(
+            from_regex(r"file[0-9]+", "x"),
+            Err(FromRegexError::Unsupported {
+                text: "[".to_string(),
+                position: 4
+            })
+        );
+        assert_eq!(
+            from_regex(r"a(\w+)", "x"),
+            Err(FromRegexError::Unsupported {
+                text: r"(\w+)".to_string(),
+                position: 1
+            })
+        );
+        assert_eq!(
+            from_regex(r"a(\d+)", "$0"),
+            Err(FromRegexError::Unsupported {
+                text: "$0".to_string(),
+                position: 0
+            })
+        );
+        assert_eq!(
+            from_regex(r"a\(\d+", "x"),
+            Err(FromRegexError::Unsupported {
+                text: r"\(".to_string(),
+                position: 1
+            })
+        );
+        assert!(matches!(
+            from_regex(r"a(\d+", "x"),
+            Err(FromRegexError::Regex(_))
+        ));
+    }
+
+    #[test]
+    fn replacing_like_the_regex() {
+        let (pattern, replacement) = (r"IMG_(?P<n>\d+)-(\d)", "photo-$n.$2");
+        let expression = MatchAndReplaceExpressionOwned::from_regex(pattern, replacement).unwrap();
+        let replacer = crate::MatchAndReplacer::new(expression.expression().clone());
+
+        let input = "IMG_0042-7.jpg";
+        assert_eq!(
+            crate::MatchAndReplaceStrategy::apply(&replacer, input).unwrap(),
+            regex::Regex::new(pattern)
+                .unwrap()
+                .replace(input, replacement)
+        );
+    }
+}
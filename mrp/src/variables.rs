@@ -0,0 +1,49 @@
+use std::borrow::Cow;
+
+/// Names of the variables that may be used in a replacement expression without being captured.
+pub const BUILTIN_VARIABLES: [&str; 15] = [
+    "ext",
+    "stem",
+    "parent",
+    "now",
+    "mtime",
+    "ctime",
+    "size",
+    "exif.date",
+    "exif.make",
+    "exif.model",
+    "tag.artist",
+    "tag.album",
+    "tag.title",
+    "tag.track",
+    HASH_VARIABLE,
+];
+
+/// The SHA-256 digest of the contents of a file, in hexadecimal, which a transform like
+/// `(hash:sha256:8)` can shorten.
+pub(crate) const HASH_VARIABLE: &str = "hash";
+
+/// The format of date and time variables, like `now`, `mtime` and `ctime`; they can be written in any
+/// other format with a transform, e.g. `(now:%Y%m%d)`.
+pub const DATE_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Values for the built-in variables, provided by the caller for each input.
+#[derive(Debug, Default, PartialEq)]
+pub struct Variables<'v> {
+    inner: Vec<(&'v str, Cow<'v, str>)>,
+}
+
+impl<'v> Variables<'v> {
+    pub fn new() -> Self {
+        Self { inner: vec![] }
+    }
+    pub fn put(&mut self, name: &'v str, value: impl Into<Cow<'v, str>>) {
+        self.inner.push((name, value.into()));
+    }
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.inner
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_ref())
+    }
+}
@@ -0,0 +1,173 @@
+//! Parts of expressions that parse, but probably don't do what was meant.
+
+use std::fmt::Display;
+
+use crate::parser::{
+    AbstractMatchingExpression, AbstractReplaceExpression, CaptureType, MatchAndReplaceExpression,
+    MatchAndReplaceRules, Span,
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseWarningKind<'source> {
+    /// A capture that isn't written anywhere in the replacement.
+    UnusedCapture(&'source str),
+    /// A capture right after one that takes every character it could, so it never matches
+    /// anything; like `n` in `(m:int)(n:int)`.
+    UnreachableCapture {
+        identifier: &'source str,
+        previous: &'source str,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseWarning<'source> {
+    pub kind: ParseWarningKind<'source>,
+    /// Where the expression that the warning is about was written, if it was parsed.
+    pub span: Option<Span>,
+}
+
+impl<'source> Display for ParseWarning<'source> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ParseWarningKind::UnusedCapture(identifier) => {
+                write!(
+                    f,
+                    "{identifier} is captured, but never used in the replacement"
+                )
+            }
+            ParseWarningKind::UnreachableCapture {
+                identifier,
+                previous,
+            } => write!(
+                f,
+                "{identifier} never captures anything, since {previous} takes all of it"
+            ),
+        }
+    }
+}
+
+impl<'source> MatchAndReplaceExpression<'source> {
+    /// Warnings about the parts of the expression that probably don't do what was meant.
+    pub fn warnings(&self) -> Vec<ParseWarning<'source>> {
+        let mut warnings = vec![];
+        let mut previous: Option<(&str, &CaptureType)> = None;
+
+        for (idx, e) in self.mex.expressions.iter().enumerate() {
+            let (identifier, identifier_type, length) = match e {
+                AbstractMatchingExpression::Capture {
+                    identifier,
+                    identifier_type,
+                    length,
+                    ..
+                } => (*identifier, identifier_type, length),
+                _ => {
+                    previous = None;
+                    continue;
+                }
+            };
+
+            if !uses(&self.rex.expressions, identifier) {
+                warnings.push(ParseWarning {
+                    kind: ParseWarningKind::UnusedCapture(identifier),
+                    span: self.mex.span(idx),
+                });
+            }
+
+            if let Some((previous, previous_type)) = previous {
+                if swallows(previous_type, identifier_type) {
+                    warnings.push(ParseWarning {
+                        kind: ParseWarningKind::UnreachableCapture {
+                            identifier,
+                            previous,
+                        },
+                        span: self.mex.span(idx),
+                    });
+                }
+            }
+
+            // only a capture without a length takes as many characters as it can
+            previous = match length {
+                Some(_) => None,
+                None => Some((identifier, identifier_type)),
+            };
+        }
+
+        warnings
+    }
+}
+
+impl<'source> MatchAndReplaceRules<'source> {
+    /// Warnings about each of the rules.
+    pub fn warnings(&self) -> Vec<ParseWarning<'source>> {
+        self.rules.iter().flat_map(|rule| rule.warnings()).collect()
+    }
+}
+
+/// Whether the replacement writes the identifier anywhere, including in a conditional.
+fn uses(expressions: &[AbstractReplaceExpression], identifier: &str) -> bool {
+    expressions.iter().any(|e| match e {
+        AbstractReplaceExpression::Literal(_) | AbstractReplaceExpression::Counter => false,
+        AbstractReplaceExpression::Identifier(i)
+        | AbstractReplaceExpression::Transformed { identifier: i, .. }
+        | AbstractReplaceExpression::Fallback { identifier: i, .. } => *i == identifier,
+        AbstractReplaceExpression::Conditional {
+            identifier: i,
+            then,
+            otherwise,
+        } => *i == identifier || uses(then, identifier) || uses(otherwise, identifier),
+    })
+}
+
+/// Whether a capture of the first type takes every character that one of the second type needs
+/// to start with.
+fn swallows(first: &CaptureType, second: &CaptureType) -> bool {
+    use CaptureType::*;
+
+    matches!(
+        (first, second),
+        (Int | Hex, Int | Digit | Date | Timestamp | Size) | (Hex, Hex) | (Alpha, Alpha)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn warnings(source: &str) -> Vec<ParseWarningKind<'_>> {
+        MatchAndReplaceRules::from_str(source)
+            .unwrap()
+            .warnings()
+            .into_iter()
+            .map(|w| w.kind)
+            .collect()
+    }
+
+    #[test]
+    fn warning_about_unused_captures() {
+        assert_eq!(
+            warnings("(n:int)_(t:alpha)->(t)"),
+            vec![ParseWarningKind::UnusedCapture("n")]
+        );
+        assert_eq!(warnings("(n:int?)->(n ? \"x\" : \"\")"), vec![]);
+        assert_eq!(warnings("(n:int)->(n:hex); (m:int)->(m|0)"), vec![]);
+    }
+
+    #[test]
+    fn warning_about_unreachable_captures() {
+        assert_eq!(
+            warnings("(m:int)(n:int)->(m)(n)"),
+            vec![ParseWarningKind::UnreachableCapture {
+                identifier: "n",
+                previous: "m"
+            }]
+        );
+        assert_eq!(warnings("(m:int{2})(n:int)->(m)(n)"), vec![]);
+        assert_eq!(warnings("(m:int)(t:alpha)->(m)(t)"), vec![]);
+        assert_eq!(warnings("(m:int)-(n:int)->(m)(n)"), vec![]);
+
+        let rules = MatchAndReplaceRules::from_str("ab(m:hex)(n:dig)->(m)(n)").unwrap();
+        assert_eq!(rules.warnings()[0].span, Some(9..16));
+    }
+}
@@ -0,0 +1,101 @@
+//! Renaming with tokio, so that a service renaming many paths doesn't block the threads of its
+//! runtime while it waits on the file system.
+
+use std::path::PathBuf;
+
+use log::*;
+use mrp::MatchAndReplaceStrategy;
+use tokio::task::JoinSet;
+
+use crate::{
+    chain, plan_with, BulkRenameOptions, ErrorPolicy, RenameObserver, RenamePlan, RenameReport,
+};
+
+/// Like [`crate::in_bulk`], without blocking; it has to be awaited on a tokio runtime. Working
+/// out the new names still happens on the calling task, since it's quick next to the renames.
+pub async fn in_bulk_async<R: for<'a> MatchAndReplaceStrategy<'a>>(
+    paths: &[PathBuf],
+    rename: &R,
+    options: &BulkRenameOptions,
+) -> RenameReport {
+    plan_with(paths, rename, options, &())
+        .execute_async(options, &())
+        .await
+}
+
+impl RenamePlan {
+    /// Like [`RenamePlan::execute_with`], with the renames made on the blocking threads of tokio,
+    /// like `tokio::fs::rename` does. At most as many renames as there are `threads` in the
+    /// options are underway at once, or one at a time when `serial`.
+    pub async fn execute_async(
+        self,
+        options: &BulkRenameOptions,
+        observer: &impl RenameObserver,
+    ) -> RenameReport {
+        let (mut report, renames) = self.resolve_conflicts(options, observer);
+
+        if options.no_rename {
+            report.renamed = crate::preview(renames, options, observer);
+            return report;
+        }
+
+        let renames = crate::backup::back_up(renames, options, observer, &mut report.failed);
+
+        let limit = match options.serial {
+            true => 1,
+            false => options.threads.unwrap_or(usize::MAX).max(1),
+        };
+
+        let mut waves = chain::waves(&renames, options).into_iter();
+        let mut groups = waves.next().unwrap_or_default().into_iter();
+        let mut tasks = JoinSet::new();
+        let mut stopped = false;
+
+        loop {
+            while tasks.len() < limit {
+                let Some(group) = groups.next() else {
+                    break;
+                };
+
+                let options = options.clone();
+                tasks.spawn_blocking(move || {
+                    let interruption = || options.interruption(stopped);
+                    chain::make(group, interruption, |from, to| {
+                        crate::rename_path(from, to, &options)
+                    })
+                });
+            }
+
+            let Some(joined) = tasks.join_next().await else {
+                // the next wave is only started once the last one is done
+                match waves.next() {
+                    Some(wave) => groups = wave.into_iter(),
+                    None => break,
+                }
+                continue;
+            };
+
+            for ((from, to), result) in joined.expect("renaming shouldn't panic") {
+                match result {
+                    Ok(()) => {
+                        if options.verbose && !options.silent {
+                            println!("{:?} -> {:?}", from, to);
+                        }
+                        observer.on_renamed(&from, &to);
+                        report.renamed.push((from, to));
+                    }
+                    Err(err) => {
+                        if !chain::is_not_tried(&err) {
+                            error!("{:?}: {}", from, err);
+                            stopped |= options.on_error == ErrorPolicy::Stop;
+                        }
+                        observer.on_error(&from, &err);
+                        report.failed.push((from, err));
+                    }
+                }
+            }
+        }
+
+        report
+    }
+}
@@ -0,0 +1,83 @@
+//! Copying files before they're renamed, so that the copies are left whatever happens to them.
+
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+
+use crate::{conflict, BulkRenameOptions, RenameObserver};
+
+/// Copy the file of each rename, if the options say to, and give back the renames whose files
+/// were copied; those that couldn't be are put in `failed`, and left as they are. Directories
+/// aren't copied.
+pub(crate) fn back_up(
+    renames: Vec<(PathBuf, PathBuf)>,
+    options: &BulkRenameOptions,
+    observer: &impl RenameObserver,
+    failed: &mut Vec<(PathBuf, io::Error)>,
+) -> Vec<(PathBuf, PathBuf)> {
+    if options.backup_suffix.is_none() && options.backup_dir.is_none() {
+        return renames;
+    }
+
+    let suffix = options.backup_suffix.as_deref().unwrap_or_default();
+    if let Some(dir) = &options.backup_dir {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            error!("{:?}: {}", dir, err);
+        }
+    }
+
+    renames
+        .into_iter()
+        .filter(|(from, _)| {
+            if from.is_dir() {
+                warn!("{:?} isn't backed up, since it's a directory", from);
+                return true;
+            }
+
+            let result = backup_path(from, suffix, options.backup_dir.as_deref())
+                .and_then(|backup| std::fs::copy(from, &backup).map(|_| backup));
+            match result {
+                Ok(backup) => {
+                    info!("{:?} is backed up at {:?}", from, backup);
+                    true
+                }
+                Err(err) => {
+                    error!("{:?}: can't back it up, so it isn't renamed: {}", from, err);
+                    observer.on_error(from, &err);
+                    failed.push((from.clone(), err));
+                    false
+                }
+            }
+        })
+        .collect()
+}
+
+/// Where to copy the file to: next to it, or in `dir`, with the suffix added to its name, and a
+/// number too if a file of that name is there already, like one from an earlier backup.
+fn backup_path(file: &Path, suffix: &str, dir: Option<&Path>) -> io::Result<PathBuf> {
+    let name = file.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "there's no file name to back up",
+        )
+    })?;
+    let mut backup_name = OsString::from(name);
+    backup_name.push(suffix);
+
+    let backup = match dir {
+        Some(dir) => dir.join(backup_name),
+        None => file.with_file_name(backup_name),
+    };
+
+    match backup.symlink_metadata() {
+        Err(_) => Ok(backup),
+        Ok(_) => Ok((1..)
+            .map(|n| conflict::numbered(&backup, n))
+            .find(|p| p.symlink_metadata().is_err())
+            .expect("there's always a free number")),
+    }
+}
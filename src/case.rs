@@ -0,0 +1,128 @@
+//! Writing names in another case, like `My File.txt` as `my_file.txt`, for `case`.
+
+use std::borrow::Cow;
+
+use heck::{ToKebabCase, ToLowerCamelCase, ToSnakeCase, ToTitleCase, ToUpperCamelCase};
+use mrp::MatchAndReplaceStrategy;
+
+/// A way of writing the words of a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    /// `my_file`
+    Snake,
+    /// `my-file`
+    Kebab,
+    /// `myFile`
+    Camel,
+    /// `MyFile`
+    Pascal,
+    /// `My File`
+    Title,
+    /// `my file`, with the words left as they are.
+    Lower,
+    /// `MY FILE`, with the words left as they are.
+    Upper,
+}
+
+/// Writes the name of each path in the case, leaving the extension, the directories, and any
+/// dots at the start as they are. Words are told apart by the spaces, dashes, underscores and
+/// other punctuation between them, and by where a lowercase letter is followed by an uppercase
+/// one, like in `myFile`; the punctuation between them is dropped. A name with no letters or
+/// digits in it isn't matched.
+impl<'s> MatchAndReplaceStrategy<'s> for Case {
+    fn apply(&self, value: &'s str) -> Option<Cow<'s, str>> {
+        let (dir, dots, stem, ext) = split_name(value);
+        let stem = match self {
+            Case::Snake => stem.to_snake_case(),
+            Case::Kebab => stem.to_kebab_case(),
+            Case::Camel => stem.to_lower_camel_case(),
+            Case::Pascal => stem.to_upper_camel_case(),
+            Case::Title => stem.to_title_case(),
+            Case::Lower => stem.to_lowercase(),
+            Case::Upper => stem.to_uppercase(),
+        };
+        if stem.is_empty() {
+            return None;
+        }
+
+        Some(Cow::Owned(format!("{dir}{dots}{stem}{ext}")))
+    }
+}
+
+/// The directories of a path, the dots at the start of its name, its name without them or the
+/// extension, and the extension with its dot, like `("photos/", ".", "my file", ".txt")`.
+pub(crate) fn split_name(value: &str) -> (&str, &str, &str, &str) {
+    let (dir, name) = match value.rfind('/') {
+        Some(idx) => value.split_at(idx + 1),
+        None => ("", value),
+    };
+    let dots = &name[..name.len() - name.trim_start_matches('.').len()];
+    let name = &name[dots.len()..];
+    let (stem, ext) = match name.rfind('.') {
+        Some(idx) if idx > 0 => name.split_at(idx),
+        _ => (name, ""),
+    };
+
+    (dir, dots, stem, ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(case: Case, name: &str) -> Option<String> {
+        case.apply(name).map(Cow::into_owned)
+    }
+
+    #[test]
+    fn writing_names_in_each_case() {
+        for (case, written) in [
+            (Case::Snake, "my_file_name.txt"),
+            (Case::Kebab, "my-file-name.txt"),
+            (Case::Camel, "myFileName.txt"),
+            (Case::Pascal, "MyFileName.txt"),
+            (Case::Title, "My File Name.txt"),
+            (Case::Lower, "my file-name.txt"),
+            (Case::Upper, "MY FILE-NAME.txt"),
+        ] {
+            assert_eq!(
+                write(case, "My file-Name.txt").unwrap(),
+                written,
+                "{:?}",
+                case
+            );
+        }
+
+        assert_eq!(write(Case::Snake, "myFile").unwrap(), "my_file");
+        assert_eq!(write(Case::Snake, "--.txt"), None);
+    }
+
+    #[test]
+    fn leaving_the_dots_at_the_start_and_the_directories() {
+        assert_eq!(write(Case::Upper, ".bashrc").unwrap(), ".BASHRC");
+        assert_eq!(
+            write(Case::Snake, ".My Config.json").unwrap(),
+            ".my_config.json"
+        );
+        assert_eq!(
+            write(Case::Kebab, "My Photos/Summer Trip.JPG").unwrap(),
+            "My Photos/summer-trip.JPG"
+        );
+    }
+
+    #[test]
+    fn splitting_names() {
+        for (value, split) in [
+            ("notes.txt", ("", "", "notes", ".txt")),
+            ("a/b/file.tar.gz", ("a/b/", "", "file.tar", ".gz")),
+            ("..hidden.txt", ("", "..", "hidden", ".txt")),
+            (".bashrc", ("", ".", "bashrc", "")),
+            ("dir.d/README", ("dir.d/", "", "README", "")),
+        ] {
+            assert_eq!(split_name(value), split, "{}", value);
+        }
+
+        // only the last extension is left as it is
+        assert_eq!(write(Case::Upper, "file.tar.gz").unwrap(), "FILE.TAR.gz");
+    }
+}
@@ -0,0 +1,391 @@
+//! Renames to names that other renames of the same plan free up, like `b -> c` before `a -> b`,
+//! or swaps and rotations, where each path takes the name of another.
+
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::BulkRenameOptions;
+
+/// Why a rename wasn't tried, like after a rename before it failed, or the run was cancelled.
+/// It's its own type, instead of an [`io::ErrorKind`], so that it isn't taken for an error of the
+/// file system, like an [`io::ErrorKind::Interrupted`] one that's tried again; see [`not_tried`].
+#[derive(Debug)]
+struct NotTried(&'static str);
+
+impl fmt::Display for NotTried {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for NotTried {}
+
+/// The error of a rename that wasn't tried, for the reason.
+pub(crate) fn not_tried(reason: &'static str) -> io::Error {
+    io::Error::other(NotTried(reason))
+}
+
+/// Whether the error is of a rename that wasn't tried, which isn't worth telling about by itself.
+pub(crate) fn is_not_tried(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|err| err.is::<NotTried>())
+}
+
+/// One step of making a rename of the plan.
+#[derive(Debug)]
+pub(crate) struct Move {
+    pub(crate) from: PathBuf,
+    pub(crate) to: PathBuf,
+    /// The rename of the plan that this is a step of.
+    pub(crate) rename: (PathBuf, PathBuf),
+    /// Whether the rename is made once this is, instead of the path only being moved out of the
+    /// way, to a temporary name.
+    pub(crate) completes: bool,
+}
+
+impl Move {
+    fn new(from: &Path, to: &Path) -> Self {
+        Move {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            rename: (from.to_path_buf(), to.to_path_buf()),
+            completes: true,
+        }
+    }
+}
+
+/// The moves that make the renames, in waves that are made one after the other, of groups that
+/// can be made at the same time as each other. What's in a directory is renamed before the
+/// directory is, while the paths of the plan still lead to it, and after the directory that it's
+/// in is renamed to the name the plan has it in, like when a rename of a directory is undone.
+pub(crate) fn waves(
+    renames: &[(PathBuf, PathBuf)],
+    options: &BulkRenameOptions,
+) -> Vec<Vec<Vec<Move>>> {
+    // links leave the paths where they are, so they never free up a name for each other
+    let groups = match options.link {
+        None => group(renames),
+        Some(_) => renames
+            .iter()
+            .map(|(from, to)| vec![Move::new(from, to)])
+            .collect(),
+    };
+
+    let mut by_source = HashMap::new();
+    let mut by_target = HashMap::new();
+    for (idx, group) in groups.iter().enumerate() {
+        for step in group {
+            by_source.insert(step.rename.0.as_path(), idx);
+            by_target.insert(step.rename.1.as_path(), idx);
+        }
+    }
+
+    // the groups that wait for each one, and how many each one waits for
+    let mut waiting = vec![vec![]; groups.len()];
+    let mut waits_for = vec![0; groups.len()];
+    for (idx, group) in groups.iter().enumerate() {
+        for dir in group
+            .iter()
+            .flat_map(|step| step.rename.0.ancestors().skip(1))
+        {
+            if let Some(&renamed) = by_source.get(dir).filter(|&&other| other != idx) {
+                waiting[idx].push(renamed);
+                waits_for[renamed] += 1;
+            }
+            if let Some(&renamed) = by_target.get(dir).filter(|&&other| other != idx) {
+                waiting[renamed].push(idx);
+                waits_for[idx] += 1;
+            }
+        }
+    }
+
+    let mut wave: Vec<_> = (0..groups.len())
+        .filter(|&idx| waits_for[idx] == 0)
+        .collect();
+    let mut groups: Vec<_> = groups.into_iter().map(Some).collect();
+    let mut waves = vec![];
+
+    while !wave.is_empty() {
+        let mut next = vec![];
+        for &idx in &wave {
+            for &other in &waiting[idx] {
+                waits_for[other] -= 1;
+                if waits_for[other] == 0 {
+                    next.push(other);
+                }
+            }
+        }
+
+        waves.push(wave.iter().filter_map(|&idx| groups[idx].take()).collect());
+        wave = next;
+    }
+
+    // groups that wait for each other can't be put in order, so they're made last
+    let rest: Vec<_> = groups.into_iter().flatten().collect();
+    if !rest.is_empty() {
+        waves.push(rest);
+    }
+
+    waves
+}
+
+/// Split the renames into groups that can be made at the same time as each other. The moves of
+/// a group are made one after the other, since each one frees up the name that the next one
+/// takes; a cycle starts by moving one of its paths to a temporary name, out of the way of the
+/// rest. The new names have to be different from each other.
+fn group(renames: &[(PathBuf, PathBuf)]) -> Vec<Vec<Move>> {
+    let by_source: HashMap<&Path, usize> = renames
+        .iter()
+        .enumerate()
+        .map(|(idx, (from, _))| (from.as_path(), idx))
+        .collect();
+    let by_target: HashMap<&Path, usize> = renames
+        .iter()
+        .enumerate()
+        .map(|(idx, (_, to))| (to.as_path(), idx))
+        .collect();
+
+    let mut grouped = vec![false; renames.len()];
+    let mut groups = vec![];
+
+    // a chain ends with a rename to a name that no other one frees up, which is made first, and
+    // then the one that takes the name it freed up, and so on
+    for (end, (_, to)) in renames.iter().enumerate() {
+        if by_source.contains_key(to.as_path()) {
+            continue;
+        }
+
+        let mut group = vec![];
+        let mut next = Some(end);
+        while let Some(idx) = next {
+            grouped[idx] = true;
+            let (from, to) = &renames[idx];
+            group.push(Move::new(from, to));
+            next = by_target.get(from.as_path()).copied();
+        }
+        groups.push(group);
+    }
+
+    // the rest go around in cycles
+    for start in 0..renames.len() {
+        if grouped[start] {
+            continue;
+        }
+        grouped[start] = true;
+
+        let (from, to) = &renames[start];
+        let temporary = temporary_name(to);
+        let mut group = vec![Move {
+            from: from.clone(),
+            to: temporary.clone(),
+            rename: (from.clone(), to.clone()),
+            completes: false,
+        }];
+
+        let mut next = by_target.get(from.as_path()).copied();
+        while let Some(idx) = next.filter(|&idx| idx != start) {
+            grouped[idx] = true;
+            let (from, to) = &renames[idx];
+            group.push(Move::new(from, to));
+            next = by_target.get(from.as_path()).copied();
+        }
+
+        group.push(Move {
+            from: temporary,
+            to: to.clone(),
+            rename: (from.clone(), to.clone()),
+            completes: true,
+        });
+        groups.push(group);
+    }
+
+    groups
+}
+
+/// A name next to the path, that nothing has, for a path to wait under until the path is free.
+fn temporary_name(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    (0..)
+        .map(|n| path.with_file_name(format!(".{}.rename-{}-{}", name, std::process::id(), n)))
+        .find(|p| p.symlink_metadata().is_err())
+        .expect("there's always a free name")
+}
+
+/// Make the moves of a group in order, with `rename`, giving how each of the renames of the plan
+/// went. After one fails, the rest aren't made, since their new names weren't freed up; the run
+/// is only interrupted between moves when no path is waiting under a temporary name.
+pub(crate) fn make(
+    group: Vec<Move>,
+    interruption: impl Fn() -> Option<io::Error>,
+    rename: impl Fn(&Path, &Path) -> io::Result<()>,
+) -> Vec<((PathBuf, PathBuf), io::Result<()>)> {
+    let mut made = vec![];
+    let mut failed = false;
+    let mut waiting = false;
+    // why a path couldn't be moved to its temporary name, which is why it wasn't renamed
+    let mut cause = None;
+
+    for step in group {
+        let from_temporary = step.from != step.rename.0;
+        let result = match () {
+            _ if failed && from_temporary => Err(cause.take().unwrap_or_else(|| {
+                io::Error::other(format!(
+                    "not renamed, since a rename before it failed; it's at {:?} instead",
+                    step.from
+                ))
+            })),
+            _ if failed => Err(not_tried("not renamed, since a rename before it failed")),
+            _ => match (!waiting).then(&interruption).flatten() {
+                Some(err) => Err(err),
+                None => rename(&step.from, &step.to),
+            },
+        };
+
+        match (result, step.completes) {
+            (Ok(()), true) => made.push((step.rename, Ok(()))),
+            (Ok(()), false) => waiting = true,
+            (Err(err), true) => {
+                failed = true;
+                made.push((step.rename, Err(err)));
+            }
+            (Err(err), false) => {
+                failed = true;
+                cause = Some(err);
+            }
+        }
+    }
+
+    made
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::RenamePlan;
+
+    fn renames(pairs: &[(&str, &str)]) -> Vec<(PathBuf, PathBuf)> {
+        pairs
+            .iter()
+            .map(|(from, to)| (PathBuf::from(from), PathBuf::from(to)))
+            .collect()
+    }
+
+    fn moves<'a>(steps: impl IntoIterator<Item = &'a Move>) -> Vec<(&'a Path, &'a Path)> {
+        steps
+            .into_iter()
+            .map(|step| (step.from.as_path(), step.to.as_path()))
+            .collect()
+    }
+
+    #[test]
+    fn grouping_a_chain_from_its_end() {
+        let groups = group(&renames(&[("a", "b"), ("b", "c"), ("x", "y")]));
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            moves(&groups[0]),
+            [
+                (Path::new("b"), Path::new("c")),
+                (Path::new("a"), Path::new("b"))
+            ]
+        );
+        assert_eq!(moves(&groups[1]), [(Path::new("x"), Path::new("y"))]);
+        assert!(groups.iter().flatten().all(|step| step.completes));
+    }
+
+    #[test]
+    fn grouping_a_cycle_through_a_temporary_name() {
+        let groups = group(&renames(&[("a", "b"), ("b", "c"), ("c", "a")]));
+
+        assert_eq!(groups.len(), 1);
+        let group = &groups[0];
+        assert_eq!(group.len(), 4);
+
+        // `a` waits under a temporary name while `c` and then `b` are renamed
+        let temporary = &group[0].to;
+        assert_eq!(group[0].from, Path::new("a"));
+        assert!(!group[0].completes);
+        assert_eq!(
+            moves(&group[1..3]),
+            [
+                (Path::new("c"), Path::new("a")),
+                (Path::new("b"), Path::new("c"))
+            ]
+        );
+        assert_eq!(
+            (&group[3].from, group[3].to.as_path()),
+            (temporary, Path::new("b"))
+        );
+        assert_eq!(group[3].rename, (PathBuf::from("a"), PathBuf::from("b")));
+        assert!(group[1..].iter().all(|step| step.completes));
+    }
+
+    #[test]
+    fn renaming_what_is_in_a_directory_before_the_directory() {
+        let renames = renames(&[("dir", "folder"), ("dir/a", "dir/b")]);
+        let waves = waves(&renames, &BulkRenameOptions::default());
+
+        let order: Vec<_> = waves
+            .iter()
+            .map(|wave| moves(wave.iter().flatten()))
+            .collect();
+        assert_eq!(order.len(), 2);
+        assert_eq!(order[0], [(Path::new("dir/a"), Path::new("dir/b"))]);
+        assert_eq!(order[1], [(Path::new("dir"), Path::new("folder"))]);
+    }
+
+    #[test]
+    fn swapping_and_rotating_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a", "b", "c", "x", "y"] {
+            fs::write(path(name), name).unwrap();
+        }
+
+        let plan = RenamePlan {
+            renames: vec![
+                (path("a"), path("b")),
+                (path("b"), path("c")),
+                (path("c"), path("a")),
+                (path("x"), path("y")),
+                (path("y"), path("x")),
+            ],
+            ..Default::default()
+        };
+        assert!(plan.conflicts().is_empty());
+        let report = plan.execute(&BulkRenameOptions::default());
+
+        assert!(report.is_success());
+        for (name, was) in [("a", "c"), ("b", "a"), ("c", "b"), ("x", "y"), ("y", "x")] {
+            assert_eq!(fs::read_to_string(path(name)).unwrap(), was);
+        }
+        // nothing is left under a temporary name
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 5);
+    }
+
+    #[test]
+    fn not_making_the_rest_of_a_chain_after_a_failure() {
+        let group = group(&renames(&[("a", "b"), ("b", "c"), ("c", "d")]));
+        let made = make(
+            group.into_iter().next().unwrap(),
+            || None,
+            |from, _| match from == Path::new("b") {
+                true => Err(io::Error::other("busy")),
+                false => Ok(()),
+            },
+        );
+
+        let results: Vec<_> = made
+            .iter()
+            .map(|((from, _), result)| (from.to_str().unwrap(), result.is_ok()))
+            .collect();
+        assert_eq!(results, [("c", true), ("b", false), ("a", false)]);
+        // only the one that failed is an error of its own
+        assert!(!is_not_tried(made[1].1.as_ref().unwrap_err()));
+        assert!(is_not_tried(made[2].1.as_ref().unwrap_err()));
+    }
+}
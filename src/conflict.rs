@@ -0,0 +1,403 @@
+//! Checking a plan for renames that would overwrite a file, before any of them is made.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fmt::Display,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use log::*;
+
+use crate::{BulkRenameOptions, OverwritePolicy, RenamePlan};
+
+/// A rename that would overwrite a file.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Conflict {
+    /// More than one path would be renamed to the same name.
+    SameTarget {
+        target: PathBuf,
+        sources: Vec<PathBuf>,
+    },
+    /// A path would be renamed to the name of a file that is already there.
+    TargetExists { source: PathBuf, target: PathBuf },
+}
+
+impl Conflict {
+    /// The name that the paths would be renamed to.
+    pub fn target(&self) -> &Path {
+        match self {
+            Conflict::SameTarget { target, .. } | Conflict::TargetExists { target, .. } => target,
+        }
+    }
+
+    /// The paths that would be renamed.
+    pub fn sources(&self) -> &[PathBuf] {
+        match self {
+            Conflict::SameTarget { sources, .. } => sources,
+            Conflict::TargetExists { source, .. } => std::slice::from_ref(source),
+        }
+    }
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Conflict::SameTarget { target, sources } => {
+                write!(
+                    f,
+                    "{} paths would be renamed to {:?}: ",
+                    sources.len(),
+                    target
+                )?;
+                for (idx, source) in sources.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", source)?;
+                }
+                Ok(())
+            }
+            Conflict::TargetExists { source, target } => {
+                write!(
+                    f,
+                    "{:?} would overwrite {:?}, which already exists",
+                    source, target
+                )
+            }
+        }
+    }
+}
+
+impl RenamePlan {
+    /// Every rename that would overwrite a file, either one that is already there or one that
+    /// another rename of the plan makes, in the order of the plan. A file that another rename
+    /// moves out of the way isn't in the way, so paths can swap names.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = vec![];
+        let mut sources_by_target: HashMap<&Path, Vec<&Path>> = HashMap::new();
+        let sources: HashSet<&Path> = self
+            .renames
+            .iter()
+            .map(|(from, _)| from.as_path())
+            .collect();
+
+        for (from, to) in &self.renames {
+            sources_by_target.entry(to).or_default().push(from);
+        }
+
+        for (from, to) in &self.renames {
+            match sources_by_target.get_mut(to.as_path()) {
+                Some(sources) if sources.len() > 1 => {
+                    conflicts.push(Conflict::SameTarget {
+                        target: to.clone(),
+                        sources: sources.iter().map(|s| s.to_path_buf()).collect(),
+                    });
+                    // only reported once, for the first of the paths
+                    sources.clear();
+                }
+                Some(sources) if sources.is_empty() => {}
+                _ if to.exists() && !is_same_file(from, to) && !sources.contains(to.as_path()) => {
+                    conflicts.push(Conflict::TargetExists {
+                        source: from.clone(),
+                        target: to.clone(),
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        conflicts
+    }
+
+    /// Give each rename that would overwrite a file a name that is free instead, by adding a
+    /// number to it, like `notes (1).txt`; the first of the paths renamed to a free name keeps it.
+    pub(crate) fn number_conflicting(&mut self) {
+        let planned: HashSet<PathBuf> = self.renames.iter().map(|(_, to)| to.clone()).collect();
+        let sources: HashSet<PathBuf> = self.renames.iter().map(|(from, _)| from.clone()).collect();
+        let mut taken = HashSet::new();
+
+        for (from, to) in &mut self.renames {
+            let in_the_way = to.exists() && !is_same_file(from, to) && !sources.contains(to);
+            if taken.contains(to) || in_the_way {
+                let numbered = (1..)
+                    .map(|n| numbered(to, n))
+                    .find(|p| !p.exists() && !planned.contains(p) && !taken.contains(p))
+                    .expect("there's always a free number");
+                warn!(
+                    "{:?} is taken; renaming {:?} to {:?} instead",
+                    to, from, numbered
+                );
+                *to = numbered;
+            }
+            taken.insert(to.clone());
+        }
+    }
+}
+
+/// The path with a number added to the end of its name, before the extension.
+pub(crate) fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.file_stem().unwrap_or_default());
+    name.push(format!(" ({})", n));
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+/// Ask on the terminal whether to rename over a file that is already there; anything but `y` or
+/// `yes` is taken as no.
+pub(crate) fn confirm_overwrite(source: &Path, target: &Path) -> bool {
+    eprint!("overwrite {:?} with {:?}? [y/N] ", target, source);
+    let _ = std::io::stderr().flush();
+
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Check, right before a path is renamed, that it won't overwrite a file the options don't allow
+/// it to, like one that was made after the plan was checked for conflicts; a file that it's
+/// allowed to overwrite is moved to the trash first, if the options say to. Gives whether the
+/// path can be renamed over what's at its new name; when it can't, a file can still show up
+/// there before the rename is made, so it has to be made with [`rename_no_replace`].
+pub(crate) fn make_way(
+    from: &Path,
+    to: &Path,
+    options: &BulkRenameOptions,
+) -> std::io::Result<bool> {
+    let may_overwrite = matches!(
+        options.overwrite,
+        OverwritePolicy::Always | OverwritePolicy::Prompt
+    );
+
+    // a file that shows up from now on wasn't asked about, and can't go to the trash
+    if options.link.is_some() || to.symlink_metadata().is_err() {
+        return Ok(matches!(options.overwrite, OverwritePolicy::Always) && !options.trash);
+    }
+    // like `a.txt` to `A.txt`, on a file system that ignores case
+    if is_same_file(from, to) {
+        return Ok(true);
+    }
+
+    match may_overwrite {
+        true if options.trash => trash::delete(to)
+            .map(|()| false)
+            .map_err(|err| std::io::Error::other(err.to_string())),
+        true => Ok(true),
+        false => Err(already_there(to)),
+    }
+}
+
+/// Rename the path, failing instead of renaming over a file that is at the new name, even one
+/// that shows up right before the rename is made. On Linux, it's made with `renameat2`, which
+/// checks for the file as it renames; elsewhere, or on a file system that doesn't support it, a
+/// file is linked to its new name and then removed from its old one, since making a link never
+/// replaces a file. A directory can't be linked, so that's only checked for before it's renamed.
+pub(crate) fn rename_no_replace(from: &Path, to: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    match renameat2(from, to) {
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(already_there(to))
+        }
+        result => return result,
+    }
+
+    let free = |to: &Path| match to.symlink_metadata() {
+        Ok(_) => Err(already_there(to)),
+        Err(_) => std::fs::rename(from, to),
+    };
+
+    match from.symlink_metadata()?.is_dir() {
+        true => free(to),
+        false => match std::fs::hard_link(from, to) {
+            Ok(()) => std::fs::remove_file(from),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Err(already_there(to)),
+            // like on a file system without hard links
+            Err(_) => free(to),
+        },
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn renameat2(from: &Path, to: &Path) -> std::io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let from = CString::new(from.as_os_str().as_bytes())?;
+    let to = CString::new(to.as_os_str().as_bytes())?;
+
+    // SAFETY: both paths are valid C strings, and relative paths are taken from the working
+    // directory, as `AT_FDCWD` says
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            from.as_ptr(),
+            libc::AT_FDCWD,
+            to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+fn already_there(to: &Path) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        format!("{:?} would be overwritten, since it's already there", to),
+    )
+}
+
+/// Whether both paths name the same file, as `a.txt` and `A.txt` do on a file system that
+/// ignores case.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn finding_renames_to_the_same_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let plan = RenamePlan {
+            renames: vec![
+                (path("a"), path("x")),
+                (path("b"), path("x")),
+                (path("c"), path("y")),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            plan.conflicts(),
+            [Conflict::SameTarget {
+                target: path("x"),
+                sources: vec![path("a"), path("b")],
+            }]
+        );
+    }
+
+    #[test]
+    fn finding_renames_over_files_that_are_there() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a", "b", "taken"] {
+            fs::write(path(name), name).unwrap();
+        }
+
+        // `b` is moved out of the way, so `a` can have its name
+        let plan = RenamePlan {
+            renames: vec![(path("a"), path("b")), (path("b"), path("taken"))],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            plan.conflicts(),
+            [Conflict::TargetExists {
+                source: path("b"),
+                target: path("taken"),
+            }]
+        );
+    }
+
+    #[test]
+    fn numbering_conflicting_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a", "b", "c", "notes.txt", "notes (1).txt"] {
+            fs::write(path(name), name).unwrap();
+        }
+
+        let mut plan = RenamePlan {
+            renames: vec![
+                (path("a"), path("notes.txt")),
+                (path("b"), path("new.txt")),
+                (path("c"), path("new.txt")),
+            ],
+            ..Default::default()
+        };
+        plan.number_conflicting();
+
+        assert_eq!(
+            plan.renames,
+            [
+                (path("a"), path("notes (2).txt")),
+                (path("b"), path("new.txt")),
+                (path("c"), path("new (1).txt")),
+            ]
+        );
+        assert!(plan.conflicts().is_empty());
+    }
+
+    #[test]
+    fn numbering_names_with_and_without_extensions() {
+        assert_eq!(
+            numbered(Path::new("dir/notes"), 3),
+            Path::new("dir/notes (3)")
+        );
+        assert_eq!(
+            numbered(Path::new("dir/notes.tar.gz"), 1),
+            Path::new("dir/notes.tar (1).gz")
+        );
+    }
+
+    #[test]
+    fn not_making_way_unless_overwriting_is_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("a"), dir.path().join("b"));
+        fs::write(&from, "a").unwrap();
+
+        let never = BulkRenameOptions::builder()
+            .overwrite(OverwritePolicy::Never)
+            .build();
+        assert!(make_way(&from, &to, &never).is_ok());
+
+        fs::write(&to, "b").unwrap();
+        let err = make_way(&from, &to, &never).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+        let always = BulkRenameOptions::builder()
+            .overwrite(OverwritePolicy::Always)
+            .build();
+        assert!(make_way(&from, &to, &always).unwrap());
+        assert!(to.exists());
+    }
+
+    #[test]
+    fn renaming_without_replacing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("a"), "a").unwrap();
+        fs::write(path("b"), "b").unwrap();
+        fs::create_dir(path("dir")).unwrap();
+
+        let err = rename_no_replace(&path("a"), &path("b")).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(path("a")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(path("b")).unwrap(), "b");
+
+        rename_no_replace(&path("a"), &path("c")).unwrap();
+        assert!(!path("a").exists());
+        assert_eq!(fs::read_to_string(path("c")).unwrap(), "a");
+
+        let err = rename_no_replace(&path("dir"), &path("b")).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        rename_no_replace(&path("dir"), &path("folder")).unwrap();
+        assert!(path("folder").is_dir());
+    }
+}
@@ -0,0 +1,58 @@
+//! Showing renames like a diff, with the part of each name that changes picked out.
+
+use std::{fmt::Write, path::Path};
+
+use colored::Colorize;
+
+use crate::RenamePlan;
+
+impl RenamePlan {
+    /// The renames of the plan, each as a `-` line with the old path and a `+` line with the new
+    /// one, where the part that is taken out is red, and the part that is put in is green.
+    pub fn render_diff(&self) -> String {
+        render(&self.renames)
+    }
+}
+
+pub(crate) fn render<P: AsRef<Path>>(renames: &[(P, P)]) -> String {
+    let mut diff = String::new();
+
+    for (from, to) in renames {
+        let (from, to) = (
+            from.as_ref().to_string_lossy(),
+            to.as_ref().to_string_lossy(),
+        );
+        let (prefix, old, new, suffix) = changed(&from, &to);
+
+        // writing to a string can't fail
+        let _ = writeln!(diff, "- {}{}{}", prefix, old.red().bold(), suffix);
+        let _ = writeln!(diff, "+ {}{}{}", prefix, new.green().bold(), suffix);
+    }
+
+    diff
+}
+
+/// The two names split into the start and end they have in common, and what differs between
+/// them; the start and end never overlap, so `aa -> aaa` only adds an `a`.
+fn changed<'a>(from: &'a str, to: &'a str) -> (&'a str, &'a str, &'a str, &'a str) {
+    let prefix = from
+        .char_indices()
+        .zip(to.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(from.len().min(to.len()), |((idx, _), _)| idx);
+
+    let suffix = from[prefix..]
+        .chars()
+        .rev()
+        .zip(to[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum::<usize>();
+
+    (
+        &from[..prefix],
+        &from[prefix..from.len() - suffix],
+        &to[prefix..to.len() - suffix],
+        &from[from.len() - suffix..],
+    )
+}
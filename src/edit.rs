@@ -0,0 +1,175 @@
+//! Renaming paths by editing a list of them in a text editor, for renames that no expression
+//! fits.
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::PathBuf,
+    process::Command,
+};
+
+use crate::{os_str, sanitize, BulkRenameOptions, RenamePlan};
+
+/// Write the paths to a file, one on each line, open it in the editor that `VISUAL` or `EDITOR`
+/// names, and plan to rename each path to what its line says once the editor is closed. Lines
+/// can only be changed; deleting, adding or repeating one is an error, so that every path still
+/// lines up with its new name.
+pub fn plan_edited(paths: &[PathBuf], options: &BulkRenameOptions) -> io::Result<RenamePlan> {
+    if paths.is_empty() {
+        return Ok(RenamePlan::default());
+    }
+
+    let mut list = String::new();
+    for path in paths {
+        let line = os_str::decode(path.as_os_str()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?}: invalid unicode", path),
+            )
+        })?;
+        if line.contains('\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{:?} can't be edited, since there's a line break in it",
+                    path
+                ),
+            ));
+        }
+        list.push_str(&line);
+        list.push('\n');
+    }
+
+    // a name no one else can know ahead of time, made only if nothing is there yet, so that a
+    // link put in its place can't make the list overwrite another file; it's removed when dropped
+    let mut file = tempfile::Builder::new()
+        .prefix("rename-")
+        .suffix(".txt")
+        .tempfile()?;
+    file.write_all(list.as_bytes())?;
+    file.flush()?;
+
+    open_editor(file.path())?;
+    let edited = std::fs::read_to_string(file.path())?;
+    let lines: Vec<&str> = edited.trim_end_matches('\n').split('\n').collect();
+    validate(paths, &lines)?;
+
+    let mut plan = RenamePlan::default();
+    for (from, line) in paths.iter().zip(lines) {
+        let to = PathBuf::from(os_str::encode(line.trim_end_matches('\r')));
+        let to = match options.sanitize {
+            Some(fs) => sanitize::sanitize(from, to, fs),
+            None => to,
+        };
+
+        match to == *from {
+            true => plan.skipped.push(from.clone()),
+            false => plan.renames.push((from.clone(), to)),
+        }
+    }
+
+    Ok(plan)
+}
+
+fn open_editor(file: &std::path::Path) -> io::Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    // like `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "EDITOR is empty"))?;
+
+    let status = Command::new(program).args(words).arg(file).status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "{} exited with {}, so nothing is renamed",
+            program, status
+        ))),
+    }
+}
+
+/// Check that there's still a line for every path, and that no two lines are the same.
+fn validate(paths: &[PathBuf], lines: &[&str]) -> io::Result<()> {
+    let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+
+    if lines.len() < paths.len() {
+        return invalid(format!(
+            "{} of the lines were deleted; each path needs a line with its new name",
+            paths.len() - lines.len()
+        ));
+    }
+    if lines.len() > paths.len() {
+        return invalid(format!(
+            "{} lines were added; each line is the new name of the path that was on it",
+            lines.len() - paths.len()
+        ));
+    }
+
+    let mut seen = HashMap::new();
+    for (number, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            return invalid(format!("line {} is empty", number + 1));
+        }
+        if let Some(first) = seen.insert(*line, number) {
+            return invalid(format!(
+                "lines {} and {} are both {:?}",
+                first + 1,
+                number + 1,
+                line
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn validating_changed_lines() {
+        assert!(validate(&paths(&["a", "b"]), &["x", "b"]).is_ok());
+        // swapping names is fine too
+        assert!(validate(&paths(&["a", "b"]), &["b", "a"]).is_ok());
+    }
+
+    #[test]
+    fn refusing_deleted_added_empty_or_repeated_lines() {
+        let paths = paths(&["a", "b", "c"]);
+
+        for (lines, message) in [
+            (&["a", "b"][..], "1 of the lines were deleted"),
+            (&["a", "b", "c", "d"][..], "1 lines were added"),
+            (&["a", " ", "c"][..], "line 2 is empty"),
+            (&["a", "b", "a"][..], "lines 1 and 3 are both \"a\""),
+        ] {
+            let err = validate(&paths, lines).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().starts_with(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn planning_the_edited_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("old.txt");
+        let same = dir.path().join("same.txt");
+
+        // an "editor" that changes the list without asking
+        std::env::set_var("VISUAL", "sed -i s/old.txt$/new.txt/");
+        let plan = plan_edited(&[old.clone(), same.clone()], &BulkRenameOptions::default());
+        std::env::remove_var("VISUAL");
+
+        let plan = plan.unwrap();
+        assert_eq!(plan.renames, [(old, dir.path().join("new.txt"))]);
+        assert_eq!(plan.skipped, [same]);
+    }
+}
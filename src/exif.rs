@@ -0,0 +1,73 @@
+//! The `exif.*` variables, read from the EXIF data of photos, like when they were taken.
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use exif::{Exif, In, Tag, Value};
+use log::*;
+use mrp::{MatchAndReplaceStrategy, Variables};
+
+/// The variables, and the tags they're read from.
+const VARIABLES: [(&str, Tag); 3] = [
+    ("exif.date", Tag::DateTimeOriginal),
+    ("exif.make", Tag::Make),
+    ("exif.model", Tag::Model),
+];
+
+/// Put the values of the `exif.*` variables that the strategy uses, if the file has them.
+pub(crate) fn put_variables<'r, R: MatchAndReplaceStrategy<'r>>(
+    file: &Path,
+    rename: &R,
+    variables: &mut Variables<'_>,
+) {
+    if !VARIABLES.iter().any(|(name, _)| rename.uses_variable(name)) {
+        return;
+    }
+
+    let exif = match read(file) {
+        Ok(exif) => exif,
+        Err(err) => {
+            error!("{:?}: can't read EXIF data: {}", file, err);
+            return;
+        }
+    };
+
+    for (name, tag) in VARIABLES {
+        if !rename.uses_variable(name) {
+            continue;
+        }
+
+        let Some(Value::Ascii(text)) = exif.get_field(tag, In::PRIMARY).map(|f| &f.value) else {
+            warn!("{:?}: there's no {} in its EXIF data", file, name);
+            continue;
+        };
+        let Some(text) = text.first() else {
+            continue;
+        };
+
+        match tag {
+            // written like the other dates, so that it can be reformatted like them
+            Tag::DateTimeOriginal => match exif::DateTime::from_ascii(text) {
+                Ok(date) => variables.put(
+                    name,
+                    format!(
+                        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                        date.year, date.month, date.day, date.hour, date.minute, date.second
+                    ),
+                ),
+                Err(err) => warn!("{:?}: {} isn't a date: {}", file, name, err),
+            },
+            _ => variables.put(
+                name,
+                String::from_utf8_lossy(text)
+                    .trim_end_matches('\0')
+                    .trim()
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+fn read(file: &Path) -> Result<Exif, exif::Error> {
+    let mut reader = BufReader::new(File::open(file)?);
+    exif::Reader::new().read_from_container(&mut reader)
+}
@@ -0,0 +1,165 @@
+//! Picking which of the paths to rename, by their names.
+
+use std::path::{Component, Path, PathBuf};
+
+use glob::Pattern;
+
+/// What a path is, without following it if it's a symbolic link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// Which paths to rename: those that match one of the `include` patterns, if there are any, and
+/// none of the `exclude` ones, and aren't in a directory that matches one of the `exclude_dirs`
+/// ones; and that are one of the `types`, if there are any. A pattern with a `/` in it is matched
+/// against the whole path, and any other one against the name of the file or directory.
+#[derive(Debug, Default, Clone)]
+pub struct PathFilter {
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+    pub exclude_dirs: Vec<Pattern>,
+    pub types: Vec<EntryType>,
+    /// The directories that the paths were found in, like the ones given with `--recursive`; for
+    /// a path in one of them, only the directories below it are matched against `exclude_dirs`,
+    /// so that `--exclude-dir home` doesn't leave out everything in `/home/u/pics`.
+    pub roots: Vec<PathBuf>,
+}
+
+impl PathFilter {
+    pub fn allows(&self, path: &Path) -> bool {
+        if !self.types.is_empty() && !entry_type(path).is_some_and(|t| self.types.contains(&t)) {
+            return false;
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|p| matches(p, path)) {
+            return false;
+        }
+        if self.exclude.iter().any(|p| matches(p, path)) {
+            return false;
+        }
+
+        // the deepest of the roots that the path is in, since they can be in each other
+        let below_root = self
+            .roots
+            .iter()
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .min_by_key(|relative| relative.components().count())
+            .unwrap_or(path);
+
+        let mut dirs: Vec<_> = below_root
+            .parent()
+            .into_iter()
+            .flat_map(|parent| parent.components())
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        if path.is_dir() {
+            dirs.extend(below_root.file_name());
+        }
+
+        !dirs.iter().any(|dir| {
+            self.exclude_dirs
+                .iter()
+                .any(|p| dir.to_str().is_some_and(|dir| p.matches(dir)))
+        })
+    }
+}
+
+fn entry_type(path: &Path) -> Option<EntryType> {
+    let file_type = path.symlink_metadata().ok()?.file_type();
+
+    match () {
+        _ if file_type.is_symlink() => Some(EntryType::Symlink),
+        _ if file_type.is_dir() => Some(EntryType::Dir),
+        _ if file_type.is_file() => Some(EntryType::File),
+        // like a socket or a device
+        _ => None,
+    }
+}
+
+fn matches(pattern: &Pattern, path: &Path) -> bool {
+    match pattern.as_str().contains('/') {
+        true => pattern.matches_path(path),
+        false => path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| pattern.matches(name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<Pattern> {
+        patterns.iter().map(|p| Pattern::new(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn including_and_excluding_by_name_or_path() {
+        let filter = PathFilter {
+            include: patterns(&["*.jpg", "raw/*"]),
+            exclude: patterns(&["draft-*"]),
+            ..Default::default()
+        };
+
+        assert!(filter.allows(Path::new("pics/a.jpg")));
+        assert!(filter.allows(Path::new("raw/a.cr2")));
+        assert!(!filter.allows(Path::new("pics/a.png")));
+        assert!(!filter.allows(Path::new("pics/draft-a.jpg")));
+    }
+
+    #[test]
+    fn excluding_dirs_below_the_roots_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("home").join("u").join("pics");
+        fs::create_dir_all(root.join("home")).unwrap();
+        fs::create_dir_all(root.join("trip")).unwrap();
+        for file in ["a.jpg", "home/b.jpg", "trip/c.jpg"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let filter = PathFilter {
+            exclude_dirs: patterns(&["home"]),
+            roots: vec![root.clone()],
+            ..Default::default()
+        };
+
+        assert!(filter.allows(&root.join("a.jpg")));
+        assert!(filter.allows(&root.join("trip")));
+        assert!(filter.allows(&root.join("trip/c.jpg")));
+        assert!(!filter.allows(&root.join("home")));
+        assert!(!filter.allows(&root.join("home/b.jpg")));
+
+        // without the root, the directories above it are matched too
+        let filter = PathFilter {
+            roots: vec![],
+            ..filter
+        };
+        assert!(!filter.allows(&root.join("a.jpg")));
+    }
+
+    #[test]
+    fn picking_by_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        let sub = dir.path().join("dir");
+        fs::write(&file, "").unwrap();
+        fs::create_dir(&sub).unwrap();
+
+        let filter = PathFilter {
+            types: vec![EntryType::Dir],
+            ..Default::default()
+        };
+
+        assert!(filter.allows(&sub));
+        assert!(!filter.allows(&file));
+        assert!(!filter.allows(&dir.path().join("missing")));
+    }
+}
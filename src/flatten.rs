@@ -0,0 +1,111 @@
+//! Moving the files in the directories of a directory into the directory itself, with the
+//! directories they were in written into their names, for `flatten`.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{sanitize, BulkRenameOptions, RenamePlan};
+
+/// Plan to move each of the paths, which are in `dir` or the directories in it, right into
+/// `dir`, naming it after the directories it was in and its name, with `separator` between
+/// them, like `2024_may_photo.jpg` for `2024/may/photo.jpg`. Paths that are right in `dir`
+/// already are left as they are, and so are the ones that aren't in it at all.
+pub fn plan_flattened(
+    dir: &Path,
+    paths: &[PathBuf],
+    separator: &str,
+    options: &BulkRenameOptions,
+) -> RenamePlan {
+    let mut plan = RenamePlan::default();
+
+    for path in paths {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            plan.skipped.push(path.clone());
+            continue;
+        };
+
+        let mut name = OsString::new();
+        for (idx, component) in relative.iter().enumerate() {
+            if idx > 0 {
+                name.push(separator);
+            }
+            name.push(component);
+        }
+
+        let to = dir.join(name);
+        let to = match options.sanitize {
+            Some(fs) => sanitize::sanitize(path, to, fs),
+            None => to,
+        };
+
+        match to == *path {
+            true => plan.skipped.push(path.clone()),
+            false => plan.renames.push((path.clone(), to)),
+        }
+    }
+
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TargetFs;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn naming_paths_after_the_directories_they_were_in() {
+        let plan = plan_flattened(
+            Path::new("pics"),
+            &paths(&[
+                "pics/2024/may/photo.jpg",
+                "pics/2024/a.jpg",
+                "pics/b.jpg",
+                "c.jpg",
+            ]),
+            "_",
+            &BulkRenameOptions::default(),
+        );
+
+        assert_eq!(
+            plan.renames,
+            [
+                (
+                    PathBuf::from("pics/2024/may/photo.jpg"),
+                    PathBuf::from("pics/2024_may_photo.jpg")
+                ),
+                (
+                    PathBuf::from("pics/2024/a.jpg"),
+                    PathBuf::from("pics/2024_a.jpg")
+                ),
+            ]
+        );
+        assert_eq!(plan.skipped, paths(&["pics/b.jpg", "c.jpg"]));
+    }
+
+    #[test]
+    fn making_the_names_valid() {
+        let options = BulkRenameOptions::builder()
+            .sanitize(Some(TargetFs::Windows))
+            .build();
+        let plan = plan_flattened(
+            Path::new("pics"),
+            &paths(&["pics/a:b/c?.jpg"]),
+            " - ",
+            &options,
+        );
+
+        assert_eq!(
+            plan.renames,
+            [(
+                PathBuf::from("pics/a:b/c?.jpg"),
+                PathBuf::from("pics/a_b - c_.jpg")
+            )]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Renaming paths with `git mv`, so that git sees a rename instead of one file going away and
+//! another one showing up.
+
+use std::{
+    io,
+    path::Path,
+    process::{Command, Output},
+    sync::Mutex,
+};
+
+/// Held while git changes the index, since only one `git mv` of a repository can run at once;
+/// the others would fail to take its lock.
+static INDEX: Mutex<()> = Mutex::new(());
+
+/// Rename the path with `git mv` if git tracks it, or like any other path if it doesn't, as when
+/// it isn't in a repository; over a file that is at the new name only if `overwrite` says to.
+pub(crate) fn rename(from: &Path, to: &Path, overwrite: bool) -> io::Result<()> {
+    let _index = INDEX.lock().unwrap_or_else(|err| err.into_inner());
+
+    let (from, to) = (std::path::absolute(from)?, std::path::absolute(to)?);
+    if !git(&from, &["ls-files", "--error-unmatch", "--"], &[&from])?
+        .status
+        .success()
+    {
+        return match overwrite {
+            true => std::fs::rename(from, to),
+            false => crate::conflict::rename_no_replace(&from, &to),
+        };
+    }
+
+    // without `--force`, git refuses to overwrite a file
+    let args: &[&str] = match overwrite {
+        true => &["mv", "--force", "--"],
+        false => &["mv", "--"],
+    };
+    let output = git(&from, args, &[&from, &to])?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "git mv failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
+
+/// Run git with the arguments, in the directory of `path`, so that it finds the repository the
+/// path is in rather than the one the working directory is in.
+fn git(path: &Path, arguments: &[&str], paths: &[&Path]) -> io::Result<Output> {
+    Command::new("git")
+        .arg("-C")
+        .arg(path.parent().unwrap_or(Path::new("/")))
+        .args(arguments)
+        .args(paths)
+        .output()
+}
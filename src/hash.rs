@@ -0,0 +1,88 @@
+//! The `hash` variable, the SHA-256 digest of the contents of each file, for names that only
+//! change when the contents do.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use mrp::MatchAndReplaceStrategy;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+/// The digests of the files, if the strategy uses them; since each file is read all the way
+/// through, they're worked out in parallel before planning any renames.
+pub(crate) fn digests<'p, 'r, R: MatchAndReplaceStrategy<'r>>(
+    paths: &[&'p PathBuf],
+    rename: &R,
+) -> HashMap<&'p Path, String> {
+    if !rename.uses_variable("hash") {
+        return HashMap::new();
+    }
+
+    paths
+        .par_iter()
+        .filter_map(|path| match digest(path) {
+            Ok(digest) => Some((path.as_path(), digest)),
+            Err(err) => {
+                error!("{:?}: can't hash its contents: {}", path, err);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The SHA-256 digest of the contents of the file, in hexadecimal, read a block at a time.
+fn digest(file: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(file)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, str::FromStr};
+
+    use mrp::{parser::MatchAndReplaceExpression, MatchAndReplacer};
+
+    use super::*;
+
+    fn replacer(expression: &str) -> MatchAndReplacer<'_> {
+        MatchAndReplacer::new(MatchAndReplaceExpression::from_str(expression).unwrap())
+    }
+
+    #[test]
+    fn hashing_the_contents_of_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let [a, b, missing] = ["a", "b", "missing"].map(|name| dir.path().join(name));
+        fs::write(&a, "abc").unwrap();
+        fs::write(&b, "").unwrap();
+        let directory = dir.path().to_path_buf();
+        let paths = [&a, &b, &missing, &directory];
+
+        let digests = digests(&paths, &replacer("(n:int)->(hash)"));
+
+        assert_eq!(
+            digests[a.as_path()],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            digests[b.as_path()],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        // paths that can't be read, like a directory, are left out
+        assert_eq!(digests.len(), 2);
+    }
+
+    #[test]
+    fn not_hashing_unless_the_strategy_uses_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        fs::write(&a, "abc").unwrap();
+
+        assert!(digests(&[&a], &replacer("(n:int)->(n)")).is_empty());
+    }
+}
@@ -0,0 +1,678 @@
+//! A record of the renames that were made, so that the runs of them can be undone, and redone.
+//!
+//! Each line of the journal is one rename: the id of the run it was made in, when it was made,
+//! the path before and after, the command of the run, and `undone` if it was undone, separated
+//! by tabs. Lines written before there were commands have only the first four. A rename is
+//! written again each time it's undone or redone, as soon as it is, so the last of its lines says
+//! whether it's undone; the journal is written over with one line for each once the run is done.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use chrono::Local;
+use log::*;
+
+use crate::{os_str, BulkRenameOptions, RenameObserver, RenamePlan, RenameReport};
+
+/// A rename read from the journal.
+#[derive(Debug, PartialEq, Clone)]
+pub struct JournalEntry {
+    pub run: String,
+    pub timestamp: String,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// What was run to make the rename, like `simple "(n:int)->ep(n)"`; empty for entries from
+    /// before commands were recorded.
+    pub command: String,
+    /// Whether the rename was undone, and can be redone.
+    pub undone: bool,
+}
+
+/// The renames of one run, as [`Journal::runs`] sums them up.
+#[derive(Debug, PartialEq, Clone)]
+pub struct JournalRun {
+    pub id: String,
+    pub timestamp: String,
+    pub command: String,
+    /// How many paths were renamed, including those that were undone.
+    pub renamed: usize,
+    pub undone: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The journal at `$RENAME_JOURNAL`, or else in the state directory of the user, like
+    /// `~/.local/state/rename/journal`.
+    pub fn open_default() -> Option<Self> {
+        if let Some(path) = std::env::var_os("RENAME_JOURNAL") {
+            return Some(Self::new(path));
+        }
+
+        let state = std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state"))
+            })?;
+
+        Some(Self::new(state.join("rename").join("journal")))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add the renames, as one run of the command, to the end of the journal.
+    pub fn record(&self, renames: &[(PathBuf, PathBuf)], command: &str) -> io::Result<()> {
+        let run = self.start_run(command);
+        renames
+            .iter()
+            .try_for_each(|(from, to)| run.record(from, to))
+    }
+
+    /// Start a run of the command, to record each of its renames as soon as it's made; see
+    /// [`RunRecorder`].
+    pub fn start_run(&self, command: &str) -> RunRecorder {
+        let now = Local::now();
+
+        RunRecorder {
+            run: format!("{}-{}", now.timestamp_millis(), std::process::id()),
+            timestamp: now.to_rfc3339(),
+            command: command.to_string(),
+            appender: self.appender(),
+        }
+    }
+
+    fn appender(&self) -> Appender {
+        Appender {
+            path: self.path.clone(),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Every rename in the journal, in the order they were made.
+    pub fn entries(&self) -> io::Result<Vec<JournalEntry>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+
+        let mut entries: Vec<JournalEntry> = vec![];
+        // where each rename is in the entries, for the lines that say it was undone or redone
+        let mut positions = HashMap::new();
+
+        for line in contents.lines().filter(|line| !line.is_empty()) {
+            let entry = entry(line)?;
+            let key = (entry.run.clone(), entry.from.clone(), entry.to.clone());
+            match positions.get(&key) {
+                Some(&position) => entries[position] = entry,
+                None => {
+                    positions.insert(key, entries.len());
+                    entries.push(entry);
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Every run in the journal, in the order they were made.
+    pub fn runs(&self) -> io::Result<Vec<JournalRun>> {
+        let mut runs: Vec<JournalRun> = vec![];
+
+        for entry in self.entries()? {
+            let run = match runs.last_mut() {
+                Some(run) if run.id == entry.run => run,
+                _ => {
+                    runs.push(JournalRun {
+                        id: entry.run,
+                        timestamp: entry.timestamp,
+                        command: entry.command,
+                        renamed: 0,
+                        undone: 0,
+                    });
+                    runs.last_mut().expect("it was just pushed")
+                }
+            };
+
+            run.renamed += 1;
+            if entry.undone {
+                run.undone += 1;
+            }
+        }
+
+        Ok(runs)
+    }
+
+    /// Rename the paths of the last run that isn't undone yet back to what they were, and mark
+    /// the ones that were renamed back as undone.
+    pub fn undo_last(&self, options: &BulkRenameOptions) -> io::Result<RenameReport> {
+        let entries = self.entries()?;
+
+        match entries.iter().rev().find(|e| !e.undone) {
+            Some(last) => self.undo(&last.run, options),
+            None => {
+                info!("there are no renames to undo in {:?}", self.path);
+                Ok(RenameReport::default())
+            }
+        }
+    }
+
+    /// Rename the paths of the run back to what they were, and mark the ones that were renamed
+    /// back as undone; the paths of the run that were renamed again since may not be there.
+    pub fn undo(&self, run: &str, options: &BulkRenameOptions) -> io::Result<RenameReport> {
+        self.apply(run, true, options)
+    }
+
+    /// Make the renames of the run that were undone again, and mark them as not undone.
+    pub fn redo(&self, run: &str, options: &BulkRenameOptions) -> io::Result<RenameReport> {
+        self.apply(run, false, options)
+    }
+
+    /// Undo the renames of the run that aren't undone, or redo the ones that are.
+    fn apply(
+        &self,
+        run: &str,
+        undo: bool,
+        options: &BulkRenameOptions,
+    ) -> io::Result<RenameReport> {
+        let mut entries = self.entries()?;
+
+        if !entries.iter().any(|e| e.run == run) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("there's no run {:?} in the journal", run),
+            ));
+        }
+
+        let affected = |e: &JournalEntry| e.run == run && e.undone != undo;
+        let renames: Vec<_> = match undo {
+            // the other way around, so that the paths a run moved into place are moved back first
+            true => entries
+                .iter()
+                .rev()
+                .filter(|e| affected(e))
+                .map(|e| (e.to.clone(), e.from.clone()))
+                .collect(),
+            false => entries
+                .iter()
+                .filter(|e| affected(e))
+                .map(|e| (e.from.clone(), e.to.clone()))
+                .collect(),
+        };
+        if renames.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the renames of run {} are all {} already",
+                    run,
+                    if undo { "undone" } else { "done" }
+                ),
+            ));
+        }
+
+        let marker = Marker {
+            entries: entries
+                .iter()
+                .filter(|e| affected(e))
+                .map(|e| {
+                    (
+                        if undo {
+                            e.to.as_path()
+                        } else {
+                            e.from.as_path()
+                        },
+                        e,
+                    )
+                })
+                .collect(),
+            undo,
+            appender: self.appender(),
+            unmarked: AtomicBool::new(false),
+        };
+        let report = RenamePlan {
+            renames,
+            ..Default::default()
+        }
+        .execute_with(options, &marker);
+
+        if !options.is_dry_run() {
+            for entry in entries.iter_mut().filter(|e| affected(e)) {
+                let source = if undo { &entry.to } else { &entry.from };
+                if report.renamed.iter().any(|(from, _)| from == source) {
+                    entry.undone = undo;
+                }
+            }
+            self.write(&entries)?;
+        }
+
+        Ok(report)
+    }
+
+    fn write(&self, entries: &[JournalEntry]) -> io::Result<()> {
+        let lines: String = entries.iter().map(line).collect();
+
+        fs::write(&self.path, lines)
+    }
+}
+
+/// Records the renames of a run in the journal one at a time, as they're made, so that the ones
+/// made before the run is stopped partway, like by a second Ctrl-C, can still be undone. The
+/// journal isn't made until there's a rename to record.
+#[derive(Debug)]
+pub struct RunRecorder {
+    run: String,
+    timestamp: String,
+    command: String,
+    appender: Appender,
+}
+
+impl RunRecorder {
+    /// The id of the run, as [`Journal::runs`] gives it.
+    pub fn id(&self) -> &str {
+        &self.run
+    }
+
+    /// Add the rename to the end of the journal, as part of the run.
+    pub fn record(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let line = line(&JournalEntry {
+            run: self.run.clone(),
+            timestamp: self.timestamp.clone(),
+            from: std::path::absolute(from)?,
+            to: std::path::absolute(to)?,
+            command: self.command.clone(),
+            undone: false,
+        });
+
+        self.appender.append(&line)
+    }
+}
+
+/// Marks each rename of a run as undone, or as redone, in the journal as soon as it's made, so
+/// that the journal is right about the renames that were made before an undo was stopped partway.
+struct Marker<'e> {
+    /// The entries being undone or redone, by the path they're renamed from.
+    entries: HashMap<&'e Path, &'e JournalEntry>,
+    undo: bool,
+    appender: Appender,
+    /// Whether marking a rename failed already, so that it's only warned about once.
+    unmarked: AtomicBool,
+}
+
+impl RenameObserver for Marker<'_> {
+    fn on_renamed(&self, from: &Path, _to: &Path) {
+        let Some(entry) = self.entries.get(from) else {
+            return;
+        };
+
+        let marked = JournalEntry {
+            undone: self.undo,
+            ..JournalEntry::clone(entry)
+        };
+        if let Err(err) = self.appender.append(&line(&marked)) {
+            if !self.unmarked.swap(true, Ordering::Relaxed) {
+                warn!("{:?}: {}", self.appender.path, err);
+            }
+        }
+    }
+}
+
+/// Adds lines to the end of the journal, making it when the first one is added.
+#[derive(Debug)]
+struct Appender {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl Appender {
+    fn append(&self, line: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        if file.is_none() {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            *file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+
+        // one write for the whole line, so that it's never left half written
+        file.as_mut()
+            .expect("it was just opened")
+            .write_all(line.as_bytes())
+    }
+}
+
+/// The entry on a line of the journal.
+fn entry(line: &str) -> io::Result<JournalEntry> {
+    let mut fields = line.split('\t');
+    let mut next = || {
+        fields.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed journal entry: {line:?}"),
+            )
+        })
+    };
+
+    Ok(JournalEntry {
+        run: next()?.to_string(),
+        timestamp: next()?.to_string(),
+        from: os_str::encode(&unescape(next()?)).into(),
+        to: os_str::encode(&unescape(next()?)).into(),
+        command: unescape(fields.next().unwrap_or_default()),
+        undone: fields.next() == Some("undone"),
+    })
+}
+
+/// The line of the journal for the entry.
+fn line(entry: &JournalEntry) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\n",
+        entry.run,
+        entry.timestamp,
+        escape(&entry.from),
+        escape(&entry.to),
+        escape_text(&entry.command),
+        if entry.undone { "undone" } else { "" }
+    )
+}
+
+/// The path, with the characters that separate the fields and lines of the journal escaped, and
+/// bytes that aren't unicode written as they are for matching; see [`os_str::decode`].
+fn escape(path: &Path) -> String {
+    escape_text(&os_str::decode(path.as_os_str()).unwrap_or_else(|| path.to_string_lossy()))
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &Path) {
+        fs::write(path, path.to_string_lossy().as_bytes()).unwrap();
+    }
+
+    fn contents(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    /// Make the renames and record them as one run, like `rn` does.
+    fn rename_and_record(journal: &Journal, renames: Vec<(PathBuf, PathBuf)>) {
+        let report = RenamePlan {
+            renames,
+            ..Default::default()
+        }
+        .execute(&BulkRenameOptions::default());
+        assert!(report.is_success());
+        journal.record(&report.renamed, "test").unwrap();
+    }
+
+    #[test]
+    fn escaping_and_unescaping_fields() {
+        for text in [
+            "plain",
+            "a\tb",
+            "tab\there",
+            "line\nbreak",
+            "back\\slash\\",
+            "\t\n\\",
+        ] {
+            assert_eq!(unescape(&escape_text(text)), text);
+            assert!(!escape_text(text).contains(['\t', '\n']));
+        }
+    }
+
+    #[test]
+    fn recording_and_reading_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("journal"));
+        let renames = vec![
+            (dir.path().join("a\tb"), dir.path().join("c\nd")),
+            (dir.path().join("back\\slash"), dir.path().join("e")),
+        ];
+
+        journal.record(&renames, "simple \"a\tb->c\"").unwrap();
+        let entries = journal.entries().unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].run, entries[1].run);
+        for (entry, (from, to)) in entries.iter().zip(&renames) {
+            assert_eq!(&entry.from, from);
+            assert_eq!(&entry.to, to);
+            assert_eq!(entry.command, "simple \"a\tb->c\"");
+            assert!(!entry.undone);
+        }
+    }
+
+    #[test]
+    fn recording_each_rename_of_a_run_as_it_is_made() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("state").join("journal"));
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|name| dir.path().join(name));
+
+        let run = journal.start_run("test");
+        assert!(!journal.path().exists());
+
+        run.record(&a, &b).unwrap();
+        assert_eq!(journal.entries().unwrap().len(), 1);
+        run.record(&c, &d).unwrap();
+
+        let runs = journal.runs().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].id, run.id());
+        assert_eq!(runs[0].renamed, 2);
+        let entries = journal.entries().unwrap();
+        assert_eq!((&entries[1].from, &entries[1].to), (&c, &d));
+    }
+
+    #[test]
+    fn reading_renames_marked_undone_after_they_were_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("journal"));
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|name| dir.path().join(name));
+        journal.record(&[(a, b), (c, d)], "test").unwrap();
+
+        // like an undo that was stopped after the first rename
+        let mut entries = journal.entries().unwrap();
+        entries[0].undone = true;
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(journal.path())
+            .unwrap();
+        file.write_all(line(&entries[0]).as_bytes()).unwrap();
+
+        assert_eq!(journal.entries().unwrap(), entries);
+        assert_eq!(journal.runs().unwrap()[0].undone, 1);
+    }
+
+    #[test]
+    fn reading_entries_from_before_there_were_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("journal"));
+        fs::write(journal.path(), "1-2\t2024-01-01T00:00:00+00:00\t/a\t/b\n").unwrap();
+
+        let entries = journal.entries().unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].from, Path::new("/a"));
+        assert_eq!(entries[0].to, Path::new("/b"));
+        assert_eq!(entries[0].command, "");
+        assert!(!entries[0].undone);
+    }
+
+    #[test]
+    fn undoing_a_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("journal"));
+        let [a, b, c] = ["a", "b", "c"].map(|name| dir.path().join(name));
+        touch(&a);
+        touch(&b);
+
+        // b is moved out of the way to c first, so that a can take its name
+        rename_and_record(
+            &journal,
+            vec![(a.clone(), b.clone()), (b.clone(), c.clone())],
+        );
+        assert_eq!(contents(&b), a.to_string_lossy());
+        assert_eq!(contents(&c), b.to_string_lossy());
+
+        let report = journal.undo_last(&BulkRenameOptions::default()).unwrap();
+
+        assert!(report.is_success());
+        assert_eq!(report.renamed.len(), 2);
+        assert_eq!(contents(&a), a.to_string_lossy());
+        assert_eq!(contents(&b), b.to_string_lossy());
+        assert!(!c.exists());
+        assert!(journal.entries().unwrap().iter().all(|e| e.undone));
+        // the lines marking them undone as they were are written over with one for each
+        assert_eq!(contents(journal.path()).lines().count(), 2);
+    }
+
+    #[test]
+    fn undoing_a_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("journal"));
+        let [a, b] = ["a", "b"].map(|name| dir.path().join(name));
+        touch(&a);
+        touch(&b);
+
+        rename_and_record(
+            &journal,
+            vec![(a.clone(), b.clone()), (b.clone(), a.clone())],
+        );
+        assert_eq!(contents(&a), b.to_string_lossy());
+
+        let report = journal.undo_last(&BulkRenameOptions::default()).unwrap();
+
+        assert!(report.is_success());
+        assert_eq!(contents(&a), a.to_string_lossy());
+        assert_eq!(contents(&b), b.to_string_lossy());
+    }
+
+    #[test]
+    fn refusing_to_undo_an_undone_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("journal"));
+        let [a, b] = ["a", "b"].map(|name| dir.path().join(name));
+        touch(&a);
+
+        rename_and_record(&journal, vec![(a.clone(), b.clone())]);
+        let run = journal.entries().unwrap()[0].run.clone();
+        journal.undo(&run, &BulkRenameOptions::default()).unwrap();
+
+        let err = journal
+            .undo(&run, &BulkRenameOptions::default())
+            .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(a.exists());
+        assert!(!b.exists());
+        // and there's nothing else to undo either
+        let report = journal.undo_last(&BulkRenameOptions::default()).unwrap();
+        assert!(report.renamed.is_empty());
+    }
+
+    #[test]
+    fn redoing_an_undone_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("journal"));
+        let [a, b, c] = ["a", "b", "c"].map(|name| dir.path().join(name));
+        touch(&a);
+        touch(&b);
+
+        rename_and_record(
+            &journal,
+            vec![(a.clone(), b.clone()), (b.clone(), c.clone())],
+        );
+        let run = journal.entries().unwrap()[0].run.clone();
+        journal.undo(&run, &BulkRenameOptions::default()).unwrap();
+
+        let report = journal.redo(&run, &BulkRenameOptions::default()).unwrap();
+
+        assert!(report.is_success());
+        assert!(!a.exists());
+        assert_eq!(contents(&b), a.to_string_lossy());
+        assert_eq!(contents(&c), b.to_string_lossy());
+        assert!(journal.entries().unwrap().iter().all(|e| !e.undone));
+        // it's done again, so there's nothing to redo
+        assert!(journal.redo(&run, &BulkRenameOptions::default()).is_err());
+    }
+
+    #[test]
+    fn summing_up_runs_with_undone_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("journal"));
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|name| dir.path().join(name));
+        touch(&a);
+        touch(&c);
+
+        rename_and_record(&journal, vec![(a.clone(), b.clone())]);
+        // a run of its own, even if it's made in the same millisecond
+        fs::write(
+            journal.path(),
+            contents(journal.path()).replacen('\t', "-first\t", 1),
+        )
+        .unwrap();
+        rename_and_record(&journal, vec![(c.clone(), d.clone())]);
+        journal.undo_last(&BulkRenameOptions::default()).unwrap();
+
+        let runs = journal.runs().unwrap();
+
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[0].renamed, runs[0].undone), (1, 0));
+        assert_eq!((runs[1].renamed, runs[1].undone), (1, 1));
+        assert_eq!(runs[1].command, "test");
+        assert!(c.exists());
+        // the undone run is skipped, so the one before it is undone next
+        journal.undo_last(&BulkRenameOptions::default()).unwrap();
+        assert!(a.exists());
+        assert!(c.exists());
+        assert_eq!(journal.runs().unwrap()[0].undone, 1);
+    }
+}
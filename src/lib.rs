@@ -1,37 +1,758 @@
-use std::path::PathBuf;
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
+use chrono::{DateTime, Local};
 use log::*;
-use mrp::MatchAndReplaceStrategy;
+use mrp::{MatchAndReplaceStrategy, Variables, DATE_TIME_FORMAT};
 use rayon::prelude::*;
 
-pub struct BulkRenameOptions {
-    pub no_rename: bool,
+#[cfg(feature = "async")]
+mod async_rename;
+mod backup;
+mod case;
+mod chain;
+mod conflict;
+mod diff;
+mod edit;
+mod exif;
+mod filter;
+mod flatten;
+mod git;
+mod hash;
+mod journal;
+mod map;
+mod observer;
+mod options;
+mod os_str;
+mod plan_file;
+mod retry;
+mod sanitize;
+mod slug;
+mod sort;
+#[cfg(feature = "tags")]
+mod tags;
+mod walk;
+mod watch;
+
+#[cfg(feature = "async")]
+pub use async_rename::in_bulk_async;
+pub use case::Case;
+pub use conflict::Conflict;
+pub use edit::plan_edited;
+pub use filter::{EntryType, PathFilter};
+pub use flatten::plan_flattened;
+pub use journal::{Journal, JournalEntry, JournalRun, RunRecorder};
+pub use map::plan_mapped;
+pub use observer::RenameObserver;
+pub use options::{
+    BulkRenameOptions, BulkRenameOptionsBuilder, ErrorPolicy, LinkKind, Order, OverwritePolicy,
+    SymlinkPolicy, TargetFs,
+};
+pub use os_str::OsStrMatchAndReplaceStrategy;
+pub use slug::Slugify;
+pub use walk::{walk, WalkOptions};
+pub use watch::watch;
+
+/// What became of each of the paths given to [`in_bulk`].
+#[derive(Debug, Default)]
+pub struct RenameReport {
+    /// Paths that were renamed, with their new names; or, with `no_rename`, that would have been.
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    /// Paths that were left as they were, like ones the strategy didn't match.
+    pub skipped: Vec<PathBuf>,
+    /// Those of the skipped paths that the strategy didn't match.
+    pub unmatched: Vec<PathBuf>,
+    /// Paths that couldn't be renamed.
+    pub failed: Vec<(PathBuf, std::io::Error)>,
+    /// Renames that would have overwritten a file, because of which nothing was renamed; see
+    /// [`OverwritePolicy::Never`].
+    pub conflicts: Vec<Conflict>,
+}
+
+impl RenameReport {
+    /// Whether every path that was matched was renamed.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && self.conflicts.is_empty()
+    }
+
+    /// Whether the strategy matched none of the paths; a path that already had its new name was
+    /// matched, even though it wasn't renamed.
+    pub fn matched_nothing(&self) -> bool {
+        self.renamed.is_empty()
+            && self.failed.is_empty()
+            && self.conflicts.is_empty()
+            && self.skipped.len() == self.unmatched.len()
+    }
+}
+
+pub fn in_bulk<R: for<'a> MatchAndReplaceStrategy<'a> + std::marker::Sync>(
+    paths: &[PathBuf],
+    rename: &R,
+    options: &BulkRenameOptions,
+) -> RenameReport {
+    in_bulk_with(paths, rename, options, &())
+}
+
+/// Like [`in_bulk`], telling the observer about each path as it goes.
+pub fn in_bulk_with<R: for<'a> MatchAndReplaceStrategy<'a> + std::marker::Sync>(
+    paths: &[PathBuf],
+    rename: &R,
+    options: &BulkRenameOptions,
+    observer: &impl RenameObserver,
+) -> RenameReport {
+    plan_with(paths, rename, options, observer).execute_with(options, observer)
+}
+
+/// The renames worked out for some paths, which haven't been made yet; see [`plan`].
+#[derive(Debug, Default)]
+pub struct RenamePlan {
+    /// Each path that the strategy matched, and its new name.
+    pub renames: Vec<(PathBuf, PathBuf)>,
+    /// Paths that are left as they are, like ones the strategy didn't match.
+    pub skipped: Vec<PathBuf>,
+    /// Those of the skipped paths that the strategy didn't match.
+    pub unmatched: Vec<PathBuf>,
+    /// Paths that the strategy couldn't be applied to.
+    pub failed: Vec<(PathBuf, std::io::Error)>,
+}
+
+/// Work out the new name of each path, without renaming anything.
+pub fn plan<R: for<'a> MatchAndReplaceStrategy<'a>>(paths: &[PathBuf], rename: &R) -> RenamePlan {
+    plan_with(paths, rename, &BulkRenameOptions::default(), &())
 }
 
-pub fn in_bulk<'p: 'r, 'r, R: MatchAndReplaceStrategy<'r> + std::marker::Sync>(
-    paths: &'p [PathBuf],
+/// Like [`plan`], with the options for which paths are matched and in what order, and telling
+/// the observer about each path as it's planned.
+pub fn plan_with<R: for<'a> MatchAndReplaceStrategy<'a>>(
+    paths: &[PathBuf],
     rename: &R,
     options: &BulkRenameOptions,
-) {
-    paths
-        .par_iter()
-        .filter_map(|p| {
-            let path_string = p.to_str();
-
-            if path_string.is_none() {
-                error!("Path is invalid unicode: {:?}", p);
-            }
-
-            return match path_string {
-                Some(s) => rename.apply(s).map(|renamed| (s, renamed)),
-                None => None,
-            };
-        })
-        .for_each(|(from, to)| {
-            if options.no_rename {
-                println!("{:?} -> {:?}", from, to);
-            } else if let Err(err) = std::fs::rename(from, to.to_string()) {
-                error!("{:?}: {}", from, err);
-            };
-        })
+    observer: &impl RenameObserver,
+) -> RenamePlan {
+    // one moment for the whole run, so that every path gets the same `now`
+    let now = Local::now().format(DATE_TIME_FORMAT).to_string();
+    let mut plan = RenamePlan::default();
+
+    let mut paths: Vec<_> = paths.iter().collect();
+    sort::sort(&mut paths, options.order);
+    if options.reverse {
+        paths.reverse();
+    }
+
+    let digests = hash::digests(&paths, rename);
+    let mut targets = HashSet::new();
+
+    // Renames are worked out in the order of the given paths, so that strategies carrying
+    // state from one path to the next (e.g. the `(#)` counter) give deterministic results.
+    for given in paths {
+        let p = match resolve_symlink(given, options.symlinks, &mut targets) {
+            Ok(Some(p)) => p,
+            Ok(None) => {
+                let reason = match options.symlinks {
+                    SymlinkPolicy::Skip => "it's a symbolic link",
+                    _ => "the file it links to is renamed already",
+                };
+                plan.skip(given, reason, observer);
+                continue;
+            }
+            Err(err) => {
+                error!("{:?}: {}", given, err);
+                observer.on_error(given, &err);
+                plan.failed.push((given.clone(), err));
+                continue;
+            }
+        };
+        let p = p.as_ref();
+
+        let Some(decoded) = os_str::decode(p.as_os_str()) else {
+            error!("Path is invalid unicode: {:?}", p);
+            let err = std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid unicode");
+            observer.on_error(p, &err);
+            plan.failed.push((p.to_path_buf(), err));
+            continue;
+        };
+
+        let mut variables = path_variables(Path::new(decoded.as_ref()), p, rename, &now);
+        if let Some(digest) = digests.get(given.as_path()) {
+            variables.put("hash", digest.as_str());
+        }
+
+        let renamed = match (options.file_name_only, &options.into) {
+            (true, into) => p
+                .file_name()
+                .and_then(|name| apply(name, rename, &variables, options))
+                .map(|name| match into {
+                    Some(into) => into.join(name),
+                    None => p.with_file_name(name),
+                }),
+            // an absolute path is put in the directory like a relative one
+            (false, Some(into)) => apply(p.as_os_str(), rename, &variables, options).map(|to| {
+                into.join(
+                    Path::new(&to)
+                        .components()
+                        .filter(|c| !matches!(c, Component::Prefix(_) | Component::RootDir))
+                        .collect::<PathBuf>(),
+                )
+            }),
+            (false, None) => apply(p.as_os_str(), rename, &variables, options).map(PathBuf::from),
+        };
+        let renamed = match options.sanitize {
+            Some(fs) => renamed.map(|to| sanitize::sanitize(p, to, fs)),
+            None => renamed,
+        };
+
+        match renamed {
+            Some(to) if to == p => plan.skip(p, "its new name is the same", observer),
+            Some(to) => {
+                observer.on_planned(p, &to);
+                plan.renames.push((p.to_path_buf(), to));
+            }
+            None => {
+                plan.skip(p, "it isn't matched", observer);
+                plan.unmatched.push(p.to_path_buf());
+            }
+        }
+    }
+
+    plan
+}
+
+impl RenamePlan {
+    /// Leave the path as it is, telling the observer why.
+    fn skip(&mut self, path: &Path, reason: &str, observer: &impl RenameObserver) {
+        debug!("{:?} is left as it is, since {}", path, reason);
+        observer.on_skipped(path, reason);
+        self.skipped.push(path.to_path_buf());
+    }
+
+    /// Make the renames, or only print them with `no_rename`. What happens to those that would
+    /// overwrite a file is up to the [`OverwritePolicy`]; see [`RenamePlan::conflicts`].
+    pub fn execute(self, options: &BulkRenameOptions) -> RenameReport {
+        self.execute_with(options, &())
+    }
+
+    /// The report so far, with the renames left to make once the conflicts are dealt with as the
+    /// [`OverwritePolicy`] says; there are none left when the conflicts stop the whole run.
+    fn resolve_conflicts(
+        mut self,
+        options: &BulkRenameOptions,
+        observer: &impl RenameObserver,
+    ) -> (RenameReport, Vec<(PathBuf, PathBuf)>) {
+        if options.overwrite == OverwritePolicy::Number {
+            self.number_conflicting();
+        }
+
+        let conflicts: Vec<_> = self
+            .conflicts()
+            .into_iter()
+            .filter(|c| match (options.overwrite, c) {
+                (OverwritePolicy::Always, Conflict::TargetExists { .. }) => false,
+                (OverwritePolicy::Prompt, Conflict::TargetExists { source, target }) => {
+                    !conflict::confirm_overwrite(source, target)
+                }
+                _ => true,
+            })
+            .collect();
+
+        let mut report = RenameReport {
+            skipped: self.skipped,
+            unmatched: self.unmatched,
+            failed: self.failed,
+            ..Default::default()
+        };
+        let mut renames = self.renames;
+
+        if options.overwrite == OverwritePolicy::Never {
+            for conflict in &conflicts {
+                error!("{}", conflict);
+                let err =
+                    std::io::Error::new(std::io::ErrorKind::AlreadyExists, conflict.to_string());
+                for source in conflict.sources() {
+                    observer.on_error(source, &err);
+                }
+            }
+
+            report.conflicts = conflicts;
+            if !report.conflicts.is_empty() && !options.no_rename {
+                return (report, vec![]);
+            }
+        } else {
+            for conflict in &conflicts {
+                warn!("{}; leaving it as it is", conflict);
+                for source in conflict.sources() {
+                    observer.on_skipped(source, "its new name conflicts");
+                }
+            }
+
+            let (conflicting, rest): (Vec<_>, _) = renames
+                .into_iter()
+                .partition(|(from, _)| conflicts.iter().any(|c| c.sources().contains(from)));
+            report
+                .skipped
+                .extend(conflicting.into_iter().map(|(from, _)| from));
+            renames = rest;
+        }
+
+        (report, renames)
+    }
+
+    /// Like [`RenamePlan::execute`], telling the observer about each rename as it's made.
+    pub fn execute_with(
+        self,
+        options: &BulkRenameOptions,
+        observer: &impl RenameObserver,
+    ) -> RenameReport {
+        let (mut report, renames) = self.resolve_conflicts(options, observer);
+
+        if options.no_rename {
+            report.renamed = preview(renames, options, observer);
+            return report;
+        }
+
+        let renames = backup::back_up(renames, options, observer, &mut report.failed);
+
+        let stopped = AtomicBool::new(false);
+        let rename_group = |group: Vec<chain::Move>| {
+            let interruption = || options.interruption(stopped.load(Ordering::Relaxed));
+            let made = chain::make(group, interruption, |from, to| {
+                rename_path(from, to, options)
+            });
+
+            made.into_iter()
+                .map(|((from, to), result)| match result {
+                    Ok(()) => {
+                        if options.verbose && !options.silent {
+                            println!("{:?} -> {:?}", from, to);
+                        }
+                        observer.on_renamed(&from, &to);
+                        Ok((from, to))
+                    }
+                    Err(err) => {
+                        if !chain::is_not_tried(&err) {
+                            error!("{:?}: {}", from, err);
+                            if options.on_error == ErrorPolicy::Stop {
+                                stopped.store(true, Ordering::Relaxed);
+                            }
+                        }
+                        observer.on_error(&from, &err);
+                        Err((from, err))
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // a pool of its own, so that the threads of the global one aren't given up to renames
+        let pool = options
+            .threads
+            .filter(|_| !options.serial)
+            .and_then(|threads| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .map_err(|err| warn!("couldn't start {} threads: {}", threads, err))
+                    .ok()
+            });
+
+        let mut results = vec![];
+        for groups in chain::waves(&renames, options) {
+            match &pool {
+                _ if options.serial => results.extend(groups.into_iter().flat_map(rename_group)),
+                Some(pool) => pool.install(|| {
+                    results.par_extend(groups.into_par_iter().flat_map_iter(rename_group))
+                }),
+                None => results.par_extend(groups.into_par_iter().flat_map_iter(rename_group)),
+            }
+        }
+
+        for result in results {
+            match result {
+                Ok(renamed) => report.renamed.push(renamed),
+                Err(failed) => report.failed.push(failed),
+            }
+        }
+
+        report
+    }
+}
+
+/// Print the renames as a diff, instead of making them.
+fn preview(
+    renames: Vec<(PathBuf, PathBuf)>,
+    options: &BulkRenameOptions,
+    observer: &impl RenameObserver,
+) -> Vec<(PathBuf, PathBuf)> {
+    if !options.silent {
+        print!("{}", diff::render(&renames));
+    }
+    for (from, to) in &renames {
+        observer.on_renamed(from, to);
+    }
+    renames
+}
+
+/// The path to rename for one that was given, which is another one when it's a symbolic link and
+/// the policy is to rename what it links to; it's `None` if the path is skipped, like a second
+/// link to the same file.
+fn resolve_symlink<'p>(
+    path: &'p Path,
+    policy: SymlinkPolicy,
+    targets: &mut HashSet<PathBuf>,
+) -> std::io::Result<Option<Cow<'p, Path>>> {
+    if policy == SymlinkPolicy::Link || !path.is_symlink() {
+        return Ok(Some(Cow::Borrowed(path)));
+    }
+
+    match policy {
+        SymlinkPolicy::Skip => Ok(None),
+        // this fails for links that loop back on themselves, instead of following them forever
+        _ => {
+            let target = std::fs::canonicalize(path)?;
+            Ok(targets.insert(target.clone()).then_some(Cow::Owned(target)))
+        }
+    }
+}
+
+/// Rename the path, or link to it, first making the directories that the new name is in, if the
+/// options say to; it's tried again after transient errors, as many times as they say.
+fn rename_path(from: &Path, to: &Path, options: &BulkRenameOptions) -> std::io::Result<()> {
+    retry::retrying(from, options, || try_rename_path(from, to, options))
+}
+
+fn try_rename_path(from: &Path, to: &Path, options: &BulkRenameOptions) -> std::io::Result<()> {
+    if options.create_dirs || options.into.is_some() {
+        if let Some(dir) = to.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+
+    let may_overwrite = conflict::make_way(from, to, options)?;
+
+    match options.link {
+        None if options.git => git::rename(from, to, may_overwrite),
+        None if may_overwrite => std::fs::rename(from, to),
+        None => conflict::rename_no_replace(from, to),
+        Some(LinkKind::Hard) => std::fs::hard_link(from, to),
+        Some(LinkKind::Symbolic) => symlink(&std::path::absolute(from)?, to),
+    }
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    match original.is_dir() {
+        true => std::os::windows::fs::symlink_dir(original, link),
+        false => std::os::windows::fs::symlink_file(original, link),
+    }
+}
+
+/// The new name the strategy gives a value; with `keep_extension`, the extension is left out of
+/// what's matched, and put back on after.
+fn apply<R: for<'a> MatchAndReplaceStrategy<'a>>(
+    value: &OsStr,
+    rename: &R,
+    variables: &Variables,
+    options: &BulkRenameOptions,
+) -> Option<OsString> {
+    let value = Path::new(value);
+
+    match value.extension() {
+        Some(ext) if options.keep_extension => {
+            let mut renamed = rename.apply_os(value.with_extension("").as_os_str(), variables)?;
+            renamed.push(".");
+            renamed.push(ext);
+            Some(renamed)
+        }
+        _ => rename.apply_os(value.as_os_str(), variables),
+    }
+}
+
+/// The values of the built-in variables for a path, leaving out those the strategy doesn't use;
+/// they're worked out from `path`, as it's matched, except for those read from the `file`.
+fn path_variables<'p, 'r, R: MatchAndReplaceStrategy<'r>>(
+    path: &'p Path,
+    file: &Path,
+    rename: &R,
+    now: &'p str,
+) -> Variables<'p> {
+    let mut variables = Variables::new();
+
+    if rename.uses_variable("ext") {
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            variables.put("ext", ext);
+        }
+    }
+
+    if rename.uses_variable("stem") {
+        if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+            variables.put("stem", stem);
+        }
+    }
+
+    if rename.uses_variable("parent") {
+        match path
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|p| p.to_str())
+        {
+            Some(parent) => variables.put("parent", parent),
+            // e.g. "file.txt", "./file.txt" or "../file.txt", where the name of the directory
+            // isn't written; it's left unset if it can't be found, like for "/file.txt"
+            None => {
+                let dir = match file.parent() {
+                    Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+                    Some(dir) => dir,
+                    None => Path::new("/"),
+                };
+                if let Some(parent) = dir.canonicalize().ok().and_then(|dir| {
+                    dir.file_name()
+                        .and_then(|name| name.to_str())
+                        .map(String::from)
+                }) {
+                    variables.put("parent", parent);
+                }
+            }
+        }
+    }
+
+    if rename.uses_variable("now") {
+        variables.put("now", now);
+    }
+
+    if rename.uses_variable("mtime") {
+        match file.metadata().and_then(|m| m.modified()) {
+            Ok(mtime) => variables.put(
+                "mtime",
+                DateTime::<Local>::from(mtime)
+                    .format(DATE_TIME_FORMAT)
+                    .to_string(),
+            ),
+            Err(err) => error!("{:?}: can't read modification time: {}", file, err),
+        }
+    }
+
+    if rename.uses_variable("ctime") {
+        match file.metadata().and_then(|m| m.created()) {
+            Ok(ctime) => variables.put(
+                "ctime",
+                DateTime::<Local>::from(ctime)
+                    .format(DATE_TIME_FORMAT)
+                    .to_string(),
+            ),
+            Err(err) => error!("{:?}: can't read creation time: {}", file, err),
+        }
+    }
+
+    if rename.uses_variable("size") {
+        match file.metadata() {
+            Ok(metadata) => variables.put("size", metadata.len().to_string()),
+            Err(err) => error!("{:?}: can't read size: {}", file, err),
+        }
+    }
+
+    exif::put_variables(file, rename, &mut variables);
+    #[cfg(feature = "tags")]
+    tags::put_variables(file, rename, &mut variables);
+
+    variables
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, str::FromStr, sync::Arc};
+
+    use mrp::{parser::MatchAndReplaceExpression, MatchAndReplacer};
+
+    use super::*;
+
+    /// A directory with a file for each name, holding its name.
+    fn files(names: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for name in names {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+        dir
+    }
+
+    fn contents(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    /// Plan the renames from one name to another, in the directory.
+    fn renames(dir: &Path, renames: &[(&str, &str)]) -> RenamePlan {
+        RenamePlan {
+            renames: renames
+                .iter()
+                .map(|(from, to)| (dir.join(from), dir.join(to)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn overwriting(overwrite: OverwritePolicy) -> BulkRenameOptions {
+        BulkRenameOptions::builder().overwrite(overwrite).build()
+    }
+
+    #[test]
+    fn renaming_matched_paths_and_leaving_the_rest() {
+        let dir = files(&["a.txt", "b.md"]);
+        let paths = ["a.txt", "b.md"].map(|name| dir.path().join(name));
+        let expression = MatchAndReplaceExpression::from_str(".txt->.md").unwrap();
+        let options = BulkRenameOptions::builder().file_name_only(true).build();
+
+        let report = in_bulk(&paths, &MatchAndReplacer::new(expression), &options);
+
+        assert_eq!(
+            report.renamed,
+            [(paths[0].clone(), dir.path().join("a.md"))]
+        );
+        assert_eq!(report.unmatched, [paths[1].clone()]);
+        assert_eq!(report.skipped, report.unmatched);
+        assert!(report.is_success() && !report.matched_nothing());
+        assert_eq!(contents(&dir.path().join("a.md")), "a.txt");
+    }
+
+    #[test]
+    fn overwriting_nothing_by_default() {
+        let dir = files(&["a", "b", "c"]);
+
+        let report = renames(dir.path(), &[("a", "b"), ("c", "d")])
+            .execute(&overwriting(OverwritePolicy::Never));
+
+        assert_eq!(report.conflicts.len(), 1);
+        assert!(report.renamed.is_empty() && !report.is_success());
+        assert_eq!(contents(&dir.path().join("b")), "b");
+        assert!(dir.path().join("c").exists());
+    }
+
+    #[test]
+    fn skipping_renames_over_files() {
+        let dir = files(&["a", "b", "c"]);
+
+        let report = renames(dir.path(), &[("a", "b"), ("c", "d")])
+            .execute(&overwriting(OverwritePolicy::Skip));
+
+        assert!(report.is_success());
+        assert_eq!(report.skipped, [dir.path().join("a")]);
+        assert_eq!(contents(&dir.path().join("b")), "b");
+        assert_eq!(contents(&dir.path().join("d")), "c");
+    }
+
+    #[test]
+    fn overwriting_files() {
+        let dir = files(&["a", "b", "c"]);
+
+        let report = renames(dir.path(), &[("a", "b"), ("c", "d")])
+            .execute(&overwriting(OverwritePolicy::Always));
+
+        assert!(report.is_success());
+        assert_eq!(report.renamed.len(), 2);
+        assert!(!dir.path().join("a").exists());
+        assert_eq!(contents(&dir.path().join("b")), "a");
+        assert_eq!(contents(&dir.path().join("d")), "c");
+    }
+
+    #[test]
+    fn numbering_names_that_are_taken() {
+        let dir = files(&["a.txt", "b.txt", "b (1).txt"]);
+
+        let report = renames(dir.path(), &[("a.txt", "b.txt")])
+            .execute(&overwriting(OverwritePolicy::Number));
+
+        assert!(report.is_success());
+        assert_eq!(contents(&dir.path().join("b.txt")), "b.txt");
+        assert_eq!(contents(&dir.path().join("b (2).txt")), "a.txt");
+    }
+
+    #[test]
+    fn leaving_paths_renamed_to_the_same_name_when_prompting() {
+        let dir = files(&["a", "b", "c"]);
+
+        // nothing is there yet at their new names, so there's nothing to ask about
+        let report = renames(dir.path(), &[("a", "x"), ("b", "x"), ("c", "y")])
+            .execute(&overwriting(OverwritePolicy::Prompt));
+
+        assert!(report.is_success());
+        assert_eq!(report.skipped, [dir.path().join("a"), dir.path().join("b")]);
+        assert!(!dir.path().join("x").exists());
+        assert_eq!(contents(&dir.path().join("y")), "c");
+    }
+
+    #[test]
+    fn stopping_after_a_failure() {
+        let dir = files(&["b", "c"]);
+        let options = BulkRenameOptions::builder()
+            .on_error(ErrorPolicy::Stop)
+            .serial(true)
+            .build();
+
+        let report =
+            renames(dir.path(), &[("missing", "a"), ("b", "x"), ("c", "y")]).execute(&options);
+
+        assert!(report.renamed.is_empty());
+        assert_eq!(report.failed.len(), 3);
+        assert!(!chain::is_not_tried(&report.failed[0].1));
+        assert!(report.failed[1..]
+            .iter()
+            .all(|(_, err)| chain::is_not_tried(err)));
+        assert!(dir.path().join("b").exists() && dir.path().join("c").exists());
+
+        // by default, the rest are made anyway
+        let report = renames(dir.path(), &[("missing", "a"), ("b", "x"), ("c", "y")])
+            .execute(&BulkRenameOptions::builder().serial(true).build());
+        assert_eq!(report.renamed.len(), 2);
+        assert_eq!(report.failed.len(), 1);
+    }
+
+    #[test]
+    fn renaming_nothing_once_cancelled() {
+        let dir = files(&["a", "b"]);
+        let cancel = Arc::new(AtomicBool::new(true));
+        let options = BulkRenameOptions::builder()
+            .cancel_on(Arc::clone(&cancel))
+            .build();
+
+        let report = renames(dir.path(), &[("a", "x"), ("b", "y")]).execute(&options);
+
+        assert!(report.renamed.is_empty());
+        assert!(report
+            .failed
+            .iter()
+            .all(|(_, err)| chain::is_not_tried(err)));
+        assert!(dir.path().join("a").exists() && dir.path().join("b").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linking_instead_of_renaming() {
+        let dir = files(&["a", "b"]);
+        let options = BulkRenameOptions::builder()
+            .link(Some(LinkKind::Symbolic))
+            .build();
+
+        let report = renames(dir.path(), &[("a", "x")]).execute(&options);
+
+        assert!(report.is_success());
+        let link = dir.path().join("x");
+        assert!(link.is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), dir.path().join("a"));
+        assert_eq!(contents(&link), "a");
+
+        let options = BulkRenameOptions::builder()
+            .link(Some(LinkKind::Hard))
+            .build();
+        let report = renames(dir.path(), &[("b", "y")]).execute(&options);
+
+        assert!(report.is_success());
+        assert!(dir.path().join("b").exists());
+        assert_eq!(contents(&dir.path().join("y")), "b");
+    }
 }
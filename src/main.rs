@@ -1,7 +1,24 @@
-use std::process::ExitCode;
+use std::{
+    collections::{HashMap, HashSet},
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
-use clap::{Args, Parser, Subcommand};
-use mrp::{parser::MatchAndReplaceExpression, MatchAndReplaceStrategy, MatchAndReplacer};
+use clap::{ArgEnum, Args, ColorChoice, CommandFactory, ErrorKind, Parser, Subcommand};
+use colored::Colorize;
+use log::{error, info, warn};
+use mrp::{
+    parser::MatchAndReplaceRulesOwned, MatchAndReplaceChain, MatchAndReplaceStrategy,
+    MatchAndReplacer,
+};
+use rename::Journal;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, setting = clap::AppSettings::DeriveDisplayOrder)]
@@ -23,12 +40,287 @@ struct RenameArgs {
     verbosity: usize,
 
     /// One or more paths to rename.
-    #[clap(global = true)]
+    #[clap(global = true, parse(from_os_str))]
     paths: Vec<std::path::PathBuf>,
 
+    /// Rename the paths in the given directories, and in the directories in them, instead of the
+    /// directories; hidden paths, `.git` directories, and paths that `.gitignore` files ignore
+    /// are left out.
+    #[clap(short, long, global = true)]
+    recursive: bool,
+
+    /// How many levels of directories to go into, where 1 is only the paths right in the given
+    /// ones; implies --recursive.
+    #[clap(long, global = true, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Rename the directories that are gone into too, with --recursive.
+    #[clap(long, global = true)]
+    include_dirs: bool,
+
+    /// Rename hidden paths, whose names start with a `.`, and go into hidden directories, with
+    /// --recursive.
+    #[clap(long, global = true)]
+    hidden: bool,
+
+    /// Rename the paths that `.gitignore` and `.ignore` files ignore too, with --recursive.
+    #[clap(long, global = true)]
+    no_ignore: bool,
+
+    /// Only rename the paths whose names match the pattern, like `*.jpg`; a pattern with a `/`
+    /// in it is matched against the whole path. Can be given more than once.
+    #[clap(
+        long,
+        global = true,
+        value_name = "GLOB",
+        multiple_occurrences = true,
+        parse(try_from_str = glob::Pattern::new)
+    )]
+    include: Vec<glob::Pattern>,
+
+    /// Leave the paths whose names match the pattern as they are. Can be given more than once.
+    #[clap(
+        long,
+        global = true,
+        value_name = "GLOB",
+        multiple_occurrences = true,
+        parse(try_from_str = glob::Pattern::new)
+    )]
+    exclude: Vec<glob::Pattern>,
+
+    /// Leave the paths in directories whose names match the pattern as they are, like
+    /// `thumbnails`. Can be given more than once.
+    #[clap(
+        long,
+        global = true,
+        value_name = "GLOB",
+        multiple_occurrences = true,
+        parse(try_from_str = glob::Pattern::new)
+    )]
+    exclude_dir: Vec<glob::Pattern>,
+
+    /// Only rename regular files, directories, or symbolic links. Can be given more than once.
+    #[clap(
+        long = "type",
+        global = true,
+        arg_enum,
+        value_name = "TYPE",
+        multiple_occurrences = true
+    )]
+    entry_types: Vec<Type>,
+
     /// Don't actually rename the files, instead just print each rename that would happen.
     #[clap(long, global = true)]
     dry_run: bool,
+
+    /// Print a line of JSON for each path, instead of the renames, with its old name (`from`),
+    /// its new name (`to`), what happened to it (`status`, which is `renamed`, `planned` with
+    /// --dry-run, `skipped`, `conflict` or `failed`), and why it didn't work (`error`).
+    #[clap(long, global = true)]
+    json: bool,
+
+    /// How many paths to rename at once; by default, as many as there are CPUs.
+    #[clap(long, global = true, value_name = "N", conflicts_with = "serial")]
+    threads: Option<usize>,
+
+    /// Sort the paths before renaming them, which is the order that `(#)` counts them in;
+    /// `natural` sorts by name, with `file2` before `file10`.
+    #[clap(long, global = true, arg_enum, value_name = "KEY")]
+    sort: Option<Sort>,
+
+    /// Take the paths in the opposite order.
+    #[clap(long, global = true)]
+    reverse: bool,
+
+    /// How many times to try a rename again after an error that is likely to go away on its
+    /// own, like a file being busy.
+    #[clap(long, global = true, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// How long to wait before trying a rename again the first time, in milliseconds; the wait
+    /// is twice as long each time after.
+    #[clap(long, global = true, value_name = "MS", default_value_t = 100)]
+    retry_delay: u64,
+
+    /// Rename one path at a time.
+    #[clap(long, global = true)]
+    serial: bool,
+
+    /// Match the whole of each path, including the directories it's in, instead of only its
+    /// file name.
+    #[clap(long, global = true)]
+    full_path: bool,
+
+    /// Match each name without its extension, and keep the extension as it is.
+    #[clap(long, global = true)]
+    keep_ext: bool,
+
+    /// Make the directories that new names are in, when they aren't there.
+    #[clap(long, global = true)]
+    mkdir: bool,
+
+    /// Move the paths into the directory under their new names, making it if it isn't there,
+    /// instead of leaving them in the directories they're in.
+    #[clap(long, global = true, value_name = "DIR", parse(from_os_str))]
+    into: Option<std::path::PathBuf>,
+
+    /// Make links to the paths under their new names, instead of renaming them.
+    #[clap(long, global = true, arg_enum, value_name = "KIND")]
+    link: Option<Link>,
+
+    /// What to do with paths that are symbolic links: rename the link, rename the file it
+    /// links to, or leave it as it is.
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        value_name = "POLICY",
+        default_value = "link"
+    )]
+    symlinks: Symlinks,
+
+    /// What to do about a path that would be renamed over a file that is already there: leave
+    /// it as it is, overwrite the file, add a number to the new name, or ask. By default,
+    /// nothing is renamed if any rename would overwrite a file.
+    #[clap(long, global = true, arg_enum, value_name = "POLICY")]
+    on_conflict: Option<OnConflict>,
+
+    /// Never rename over a file, not even one that is made while renaming. This is the default,
+    /// unless `--on-conflict overwrite` or `prompt` is given.
+    #[clap(long, global = true)]
+    no_clobber: bool,
+
+    /// Move files that would be overwritten to the trash, and rename over them; unless
+    /// `--on-conflict` says otherwise, that's every file that is in the way.
+    #[clap(long, global = true)]
+    trash: bool,
+
+    /// Rename the paths that git tracks with `git mv`, so that the renames are staged and
+    /// history follows them.
+    #[clap(long, global = true, conflicts_with = "link")]
+    git: bool,
+
+    /// Copy each file before renaming it, to its name with the suffix added; `.bak` when no
+    /// suffix is given.
+    #[clap(
+        long,
+        global = true,
+        value_name = "SUFFIX",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = ".bak"
+    )]
+    backup: Option<String>,
+
+    /// Copy each file to the directory before renaming it, instead of next to it.
+    #[clap(long, global = true, value_name = "DIR", parse(from_os_str))]
+    backup_dir: Option<std::path::PathBuf>,
+
+    /// Make the new names valid on a file system, by replacing the characters it doesn't allow
+    /// with `_`, and changing names it keeps for itself, like `CON`.
+    #[clap(long, global = true, arg_enum, value_name = "FS")]
+    target_fs: Option<TargetFs>,
+
+    /// When to color the output; `auto` colors it for a terminal, unless `NO_COLOR` is set.
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        value_name = "WHEN",
+        default_value = "auto"
+    )]
+    color: Color,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    /// Whether to leave colors out.
+    fn is_plain(self) -> bool {
+        match self {
+            Color::Auto => {
+                std::env::var_os("NO_COLOR").is_some() || !std::io::stderr().is_terminal()
+            }
+            Color::Always => false,
+            Color::Never => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Link {
+    Sym,
+    Hard,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Sort {
+    Name,
+    Natural,
+    Mtime,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum OnConflict {
+    Skip,
+    Overwrite,
+    Number,
+    Prompt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum)]
+enum Type {
+    F,
+    D,
+    L,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Case {
+    Snake,
+    Kebab,
+    Camel,
+    Pascal,
+    Title,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Time {
+    Mtime,
+    #[clap(alias = "created")]
+    Ctime,
+}
+
+impl Time {
+    /// The variable that holds the time.
+    fn variable(self) -> &'static str {
+        match self {
+            Time::Mtime => "mtime",
+            Time::Ctime => "ctime",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum TargetFs {
+    Windows,
+    Exfat,
+    Posix,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+enum Symlinks {
+    Link,
+    Target,
+    Skip,
 }
 
 #[derive(Debug, Subcommand)]
@@ -37,20 +329,104 @@ enum Command {
     Simple(SimpleArgs),
     /// Use and apply a regex replace on each filename
     Regex(RegexArgs),
+    /// Describe what a simple match-and-replace-protocol expression does, without renaming
+    /// anything.
+    Explain(ExplainArgs),
+    /// Rename the paths of the last run, or of the run with the given id, back to what they
+    /// were.
+    Undo(UndoArgs),
+    /// Make the renames of a run that were undone again.
+    Redo(RedoArgs),
+    /// List the runs in the journal, with the ids to undo or redo them by.
+    History,
+    /// Rename the paths to names that are valid on the file system given with `--target-fs`,
+    /// which is Windows unless it says otherwise.
+    Sanitize,
+    /// Write the names of the paths in another case, like `my_file` or `MyFile`, keeping their
+    /// extensions as they are.
+    Case(CaseArgs),
+    /// Make the names of the paths safe to use in URLs: lowercase ASCII letters and digits, with
+    /// a `-` between the words.
+    Slugify,
+    /// Number the paths in order, naming each as the template says; they're sorted naturally,
+    /// so that `file2` comes before `file10`, unless `--sort` says otherwise.
+    Number(NumberArgs),
+    /// Name each path after when it was last modified, or created; paths with the same time get
+    /// a number added, like `2024-05-01_120000 (1).jpg`, unless `--on-conflict` says otherwise.
+    Date(DateArgs),
+    /// Move the files in the directories of a directory into the directory itself, with the
+    /// directories they were in written into their names, like `2024_may_photo.jpg` for
+    /// `2024/may/photo.jpg`.
+    Flatten(FlattenArgs),
+    /// Open the list of paths in the editor that `VISUAL` or `EDITOR` names, and rename each
+    /// path to what its line is changed to.
+    Edit,
+    /// Rename each path in a CSV or TSV file to the new name next to it, like
+    /// `old_path,new_path`.
+    Map(MapArgs),
+    /// Work out the renames of a simple match-and-replace-protocol expression and save them to a
+    /// file, to be looked over or edited, and made with `apply`.
+    Plan(PlanArgs),
+    /// Make the renames of a plan that `plan` saved, if the paths are still there and their new
+    /// names are still free.
+    Apply(ApplyArgs),
+    /// Rename each path that is made in, or moved into, the given directories with a simple
+    /// match-and-replace-protocol expression, as it shows up, until Ctrl-C is pressed.
+    Watch(SimpleArgs),
 }
 
 fn main() -> ExitCode {
-    let base_args = RenameArgs::parse();
+    let mut base_args = RenameArgs::parse();
+
+    // with `-e` or a rules file, there's no expression before the paths, so what was taken for it
+    // is a path
+    if let Command::Simple(args)
+    | Command::Watch(args)
+    | Command::Plan(PlanArgs { simple: args, .. }) = &mut base_args.command
+    {
+        if !args.expressions.is_empty() || args.rules_file.is_some() {
+            if let Some(path) = args.expression.take() {
+                base_args.paths.insert(0, path.into());
+            }
+        }
+    }
+
+    let plain = base_args.color.is_plain();
+    colored::control::set_override(!plain);
+
+    if base_args.no_clobber
+        && !base_args.trash
+        && matches!(
+            base_args.on_conflict,
+            Some(OnConflict::Overwrite | OnConflict::Prompt)
+        )
+    {
+        command(plain)
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--no-clobber can't be used with --on-conflict overwrite or prompt, unless \
+                 overwritten files go to the --trash",
+            )
+            .exit()
+    }
 
     stderrlog::new()
         .module("rename")
+        .module(module_path!())
         .quiet(base_args.quiet)
         .verbosity(base_args.verbosity)
         .timestamp(stderrlog::Timestamp::Millisecond)
+        .color(match plain {
+            true => stderrlog::ColorChoice::Never,
+            false => stderrlog::ColorChoice::Always,
+        })
         .init()
         .unwrap();
 
-    let paths = if let Some(aw) = &base_args.glob {
+    let is_flatten = matches!(base_args.command, Command::Flatten(_));
+    let paths = if let Command::Flatten(args) = &base_args.command {
+        vec![args.dir.clone()]
+    } else if let Some(aw) = &base_args.glob {
         glob::glob(aw)
             .expect("invalid glob pattern")
             .flatten()
@@ -58,42 +434,930 @@ fn main() -> ExitCode {
     } else {
         base_args.paths
     };
+    // `watch` watches the directories themselves
+    let recursive = base_args.recursive || base_args.max_depth.is_some() || is_flatten;
+    let is_watch = matches!(base_args.command, Command::Watch(_));
+    let roots = match recursive || is_watch {
+        true => paths.iter().filter(|p| p.is_dir()).cloned().collect(),
+        false => vec![],
+    };
+    let paths = match recursive && !is_watch {
+        true => rename::walk(
+            &paths,
+            &rename::WalkOptions {
+                max_depth: base_args.max_depth,
+                // only files are moved out of the directories, which are left where they are
+                dirs: !is_flatten
+                    && (base_args.include_dirs || base_args.entry_types.contains(&Type::D)),
+                hidden: base_args.hidden,
+                ignore_files: !base_args.no_ignore,
+            },
+        ),
+        false => paths,
+    };
+    let filter = rename::PathFilter {
+        include: base_args.include,
+        exclude: base_args.exclude,
+        exclude_dirs: base_args.exclude_dir,
+        types: base_args
+            .entry_types
+            .iter()
+            .map(|t| match t {
+                Type::F => rename::EntryType::File,
+                Type::D => rename::EntryType::Dir,
+                Type::L => rename::EntryType::Symlink,
+            })
+            .collect(),
+        roots,
+    };
+    let paths: Vec<_> = match base_args.command {
+        Command::Watch(_) => paths,
+        _ => paths.into_iter().filter(|p| filter.allows(p)).collect(),
+    };
 
-    let options = &rename::BulkRenameOptions {
-        no_rename: base_args.dry_run,
+    // the first Ctrl-C lets the renames that are underway finish; each one is recorded as soon as
+    // it's made, so those made before a second one can be undone too
+    let cancel = Arc::new(AtomicBool::new(false));
+    let cancelled = Arc::clone(&cancel);
+    if let Err(err) = ctrlc::set_handler(move || {
+        if cancelled.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        eprintln!("stopping after the renames that are underway; press Ctrl-C again to quit now");
+    }) {
+        warn!("can't handle Ctrl-C: {}", err);
+    }
+
+    let mut options = rename::BulkRenameOptions::builder()
+        .dry_run(base_args.dry_run)
+        .verbose(base_args.verbosity > 0)
+        .silent(base_args.json)
+        .order(match base_args.sort {
+            None if matches!(base_args.command, Command::Number(_) | Command::Date(_)) => {
+                rename::Order::Natural
+            }
+            None => rename::Order::Given,
+            Some(Sort::Name) => rename::Order::Sorted,
+            Some(Sort::Natural) => rename::Order::Natural,
+            Some(Sort::Mtime) => rename::Order::Modified,
+            Some(Sort::Size) => rename::Order::Size,
+        })
+        .reverse(base_args.reverse)
+        .serial(base_args.serial)
+        .file_name_only(!base_args.full_path)
+        .keep_extension(base_args.keep_ext)
+        .create_dirs(base_args.mkdir)
+        .into(base_args.into)
+        .symlinks(match base_args.symlinks {
+            Symlinks::Link => rename::SymlinkPolicy::Link,
+            Symlinks::Target => rename::SymlinkPolicy::Target,
+            Symlinks::Skip => rename::SymlinkPolicy::Skip,
+        })
+        .trash(base_args.trash)
+        .git(base_args.git)
+        .backup(base_args.backup)
+        .backup_dir(base_args.backup_dir)
+        .sanitize(
+            match base_args.target_fs {
+                None if matches!(base_args.command, Command::Sanitize) => Some(TargetFs::Windows),
+                target_fs => target_fs,
+            }
+            .map(|target_fs| match target_fs {
+                TargetFs::Windows => rename::TargetFs::Windows,
+                TargetFs::Exfat => rename::TargetFs::Exfat,
+                TargetFs::Posix => rename::TargetFs::Posix,
+            }),
+        )
+        .retry(
+            base_args.retries,
+            Duration::from_millis(base_args.retry_delay),
+        )
+        .overwrite(match base_args.on_conflict {
+            None if base_args.trash => rename::OverwritePolicy::Always,
+            None if base_args.no_clobber => rename::OverwritePolicy::Never,
+            None if matches!(base_args.command, Command::Date(_)) => {
+                rename::OverwritePolicy::Number
+            }
+            None => rename::OverwritePolicy::Never,
+            Some(OnConflict::Skip) => rename::OverwritePolicy::Skip,
+            Some(OnConflict::Overwrite) => rename::OverwritePolicy::Always,
+            Some(OnConflict::Number) => rename::OverwritePolicy::Number,
+            Some(OnConflict::Prompt) => rename::OverwritePolicy::Prompt,
+        })
+        .link(base_args.link.map(|link| match link {
+            Link::Sym => rename::LinkKind::Symbolic,
+            Link::Hard => rename::LinkKind::Hard,
+        }))
+        .cancel_on(Arc::clone(&cancel));
+    if let Some(threads) = base_args.threads {
+        options = options.threads(threads);
+    }
+    let options = &options.build();
+
+    let is_undo = matches!(base_args.command, Command::Undo(_) | Command::Redo(_));
+    let run = base_args.command.run();
+    let is_watch = matches!(base_args.command, Command::Watch(_));
+    if is_watch && paths.is_empty() {
+        command(plain)
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "give the directories to watch",
+            )
+            .exit()
+    }
+
+    // links are left out, since undoing would rename them over the paths they link to; a watch
+    // records each batch of renames as a run of its own
+    let records = !base_args.dry_run
+        && base_args.link.is_none()
+        && !matches!(
+            base_args.command,
+            Command::Explain(_)
+                | Command::Undo(_)
+                | Command::Redo(_)
+                | Command::History
+                | Command::Plan(_)
+                | Command::Watch(_)
+        );
+    let observer = Observer {
+        show_skipped: (base_args.verbosity > 0 || base_args.dry_run) && !base_args.json,
+        journal: match records {
+            true => journal().map(|journal| journal.start_run(&run)),
+            false => None,
+        },
+        ..Default::default()
     };
+    let report = match base_args.command {
+        Command::Regex(args) => {
+            let pattern = regex::Regex::new(&args.pattern)
+                .unwrap_or_else(|e| invalid_expression(e.to_string(), plain));
+            let replacer = RegexReplacer {
+                pattern,
+                replacement: args.replacement,
+                all: args.all,
+            };
+            rename::in_bulk_with(&paths, &replacer, options, &observer)
+        }
+        Command::Simple(args) | Command::Watch(args) => {
+            let rules = simple_rules(&args, base_args.quiet, plain);
+            let replacer = simple_replacer(&rules, &args);
+
+            if is_watch {
+                return watch(&paths, &replacer, options, &filter, &run, base_args.json);
+            }
+            rename::in_bulk_with(&paths, &replacer, options, &observer)
+        }
+        Command::Sanitize => rename::in_bulk_with(&paths, &Unchanged, options, &observer),
+        Command::Case(args) => {
+            let case = match args.case {
+                Case::Snake => rename::Case::Snake,
+                Case::Kebab => rename::Case::Kebab,
+                Case::Camel => rename::Case::Camel,
+                Case::Pascal => rename::Case::Pascal,
+                Case::Title => rename::Case::Title,
+                Case::Lower => rename::Case::Lower,
+                Case::Upper => rename::Case::Upper,
+            };
+            rename::in_bulk_with(&paths, &case, options, &observer)
+        }
+        Command::Date(args) => {
+            let time = args.from.variable();
+            let template = format!("({}:{})(ext ? \".(ext)\" : \"\")", time, args.format);
+            let template = mrp::parser::Parser::new(mrp::lexer::Lexer::new(&template))
+                .parse_template()
+                .unwrap_or_else(|e| invalid_expression(e.render(plain), plain));
+            let dated = Dated {
+                time,
+                replacer: MatchAndReplacer::from_template(template),
+            };
+
+            rename::in_bulk_with(&paths, &dated, options, &observer)
+        }
+        Command::Flatten(args) => observer.execute(
+            rename::plan_flattened(&args.dir, &paths, &args.separator, options),
+            options,
+        ),
+        Command::Slugify => rename::in_bulk_with(&paths, &rename::Slugify, options, &observer),
+        Command::Number(args) => {
+            let template = mrp::parser::Parser::new(mrp::lexer::Lexer::new(&args.template))
+                .parse_template()
+                .unwrap_or_else(|e| invalid_expression(e.render(plain), plain));
+            let mut replacer = MatchAndReplacer::from_template(template);
+            // wide enough for the last number, so that the names sort in order
+            let last = args
+                .step
+                .checked_mul(paths.len().saturating_sub(1))
+                .and_then(|steps| args.start.checked_add(steps))
+                .unwrap_or_else(|| {
+                    command(plain)
+                        .error(
+                            ErrorKind::ValueValidation,
+                            format!(
+                                "numbering {} paths from --start {} by --step {} would count past the largest number, {}",
+                                paths.len(),
+                                args.start,
+                                args.step,
+                                usize::MAX
+                            ),
+                        )
+                        .exit()
+                });
+            let width = args.width.unwrap_or_else(|| last.to_string().len());
+            replacer.set_counter(args.start, args.step, width);
+
+            rename::in_bulk_with(&paths, &replacer, options, &observer)
+        }
+        Command::Edit => match rename::plan_edited(&paths, options) {
+            Ok(plan) => observer.execute(plan, options),
+            Err(err) => {
+                error!("{}", err);
+                return ExitCode::FAILURE;
+            }
+        },
+        Command::Plan(args) => {
+            let rules = simple_rules(&args.simple, base_args.quiet, plain);
+            let replacer = simple_replacer(&rules, &args.simple);
+
+            let plan = rename::plan_with(&paths, &replacer, options, &observer);
+            if let Err(err) = plan.save(&args.output) {
+                error!("{:?}: {}", args.output, err);
+                return ExitCode::FAILURE;
+            }
+            info!(
+                "{} renames planned in {:?}",
+                plan.renames.len(),
+                args.output
+            );
 
-    match base_args.command {
-        Command::Regex(args) => rename::in_bulk(&paths, &args, options),
-        Command::Simple(args) => {
-            let mut replacer = MatchAndReplacer::new(args.expression);
-            replacer.set_strip(args.strip);
-            rename::in_bulk(&paths, &replacer, options);
+            return match plan.failed.is_empty() {
+                true => ExitCode::SUCCESS,
+                false => ExitCode::FAILURE,
+            };
+        }
+        Command::Apply(args) => match rename::RenamePlan::load(&args.file, options) {
+            Ok(plan) => observer.execute(plan, options),
+            Err(err) => {
+                error!("{:?}: {}", args.file, err);
+                return ExitCode::FAILURE;
+            }
+        },
+        Command::Map(args) => match rename::plan_mapped(&args.file, options) {
+            Ok(plan) => observer.execute(plan, options),
+            Err(err) => {
+                error!("{:?}: {}", args.file, err);
+                return ExitCode::FAILURE;
+            }
+        },
+        Command::Explain(args) => {
+            let rules = parse_rules(&args.expression, args.types.as_deref(), plain);
+            println!("{}", rules.rules().explain());
+            return ExitCode::SUCCESS;
+        }
+        Command::Undo(_) | Command::Redo(_) | Command::History => {
+            let Some(journal) = Journal::open_default() else {
+                error!("can't find the journal; set RENAME_JOURNAL to where it is");
+                return ExitCode::FAILURE;
+            };
+
+            let result = match &base_args.command {
+                Command::Undo(UndoArgs { run: Some(run) }) => journal.undo(run, options),
+                Command::Undo(UndoArgs { run: None }) => journal.undo_last(options),
+                Command::Redo(args) => journal.redo(&args.run, options),
+                _ => {
+                    return match journal.runs() {
+                        Ok(runs) => {
+                            print_history(&runs);
+                            ExitCode::SUCCESS
+                        }
+                        Err(err) => {
+                            error!("{:?}: {}", journal.path(), err);
+                            ExitCode::FAILURE
+                        }
+                    }
+                }
+            };
+
+            match result {
+                Ok(report) => report,
+                Err(err) => {
+                    error!("{:?}: {}", journal.path(), err);
+                    return ExitCode::FAILURE;
+                }
+            }
         }
     };
 
-    ExitCode::SUCCESS
+    if base_args.json {
+        print_json(&report, &observer.planned, base_args.dry_run);
+    }
+
+    info!(
+        "{} renamed, {} skipped, {} failed, {} conflicting",
+        report.renamed.len(),
+        report.skipped.len(),
+        report.failed.len(),
+        report.conflicts.len()
+    );
+
+    if !report.failed.is_empty() {
+        error!(
+            "{} of {} paths couldn't be renamed",
+            report.failed.len(),
+            report.renamed.len() + report.failed.len()
+        );
+    }
+
+    match report.is_success() {
+        true if report.matched_nothing() && !is_undo => ExitCode::from(NOTHING_MATCHED),
+        true => ExitCode::SUCCESS,
+        false if cancel.load(Ordering::Relaxed) => ExitCode::from(CANCELLED),
+        false => ExitCode::FAILURE,
+    }
+}
+
+/// What `rn` exits with when the expression didn't match any path; it's 1 when some paths
+/// couldn't be renamed, and 2 for arguments that can't be used. When every path that was matched
+/// already had its new name, there's nothing to do, which is a success.
+const NOTHING_MATCHED: u8 = 4;
+/// What `rn` exits with when the expression can't be parsed.
+const INVALID_EXPRESSION: u8 = 3;
+/// What `rn` exits with when it was stopped with Ctrl-C, like other programs.
+const CANCELLED: u8 = 130;
+
+fn command(plain: bool) -> clap::Command<'static> {
+    RenameArgs::command().color(match plain {
+        true => ColorChoice::Never,
+        false => ColorChoice::Always,
+    })
+}
+
+/// The journal to record the renames in, so that they can be undone.
+fn journal() -> Option<Journal> {
+    let journal = Journal::open_default();
+    if journal.is_none() {
+        warn!("couldn't record the renames, since there's no journal to undo them from");
+    }
+    journal
+}
+
+/// Record the renames in the journal, as a run of the command.
+fn record(renamed: &[(std::path::PathBuf, std::path::PathBuf)], run: &str) {
+    if let Some(journal) = journal() {
+        if let Err(err) = journal.record(renamed, run) {
+            warn!(
+                "couldn't record the renames in {:?}: {}",
+                journal.path(),
+                err
+            );
+        }
+    }
+}
+
+/// Rename the paths that show up in the directories until the run is cancelled, recording each
+/// batch of renames as a run of its own.
+fn watch(
+    dirs: &[std::path::PathBuf],
+    replacer: &MatchAndReplaceChain<MatchAndReplacer>,
+    options: &rename::BulkRenameOptions,
+    filter: &rename::PathFilter,
+    run: &str,
+    json: bool,
+) -> ExitCode {
+    let result = rename::watch(dirs, replacer, options, filter, |report| {
+        if !options.is_dry_run() && options.link_kind().is_none() {
+            record(&report.renamed, run);
+        }
+        if json {
+            print_json(&report, &Planned::default(), options.is_dry_run());
+        }
+        if !report.failed.is_empty() {
+            error!(
+                "{} of {} paths couldn't be renamed",
+                report.failed.len(),
+                report.renamed.len() + report.failed.len()
+            );
+        }
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The new name of each path, as it's planned, for the JSON of the ones that fail.
+#[derive(Default)]
+struct Planned(Mutex<HashMap<PathBuf, PathBuf>>);
+
+impl rename::RenameObserver for Planned {
+    fn on_planned(&self, from: &Path, to: &Path) {
+        self.0
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(from.to_path_buf(), to.to_path_buf());
+    }
+}
+
+/// Follows the run as it happens: notes the new name of each path for the JSON, says why the
+/// paths that are skipped are left as they are, when there's a reason to, and records each rename
+/// in the journal as soon as it's made.
+#[derive(Default)]
+struct Observer {
+    planned: Planned,
+    show_skipped: bool,
+    journal: Option<rename::RunRecorder>,
+    /// Whether recording a rename failed already, so that it's only warned about once.
+    unrecorded: AtomicBool,
+}
+
+impl rename::RenameObserver for Observer {
+    fn on_planned(&self, from: &Path, to: &Path) {
+        self.planned.on_planned(from, to);
+    }
+
+    fn on_renamed(&self, from: &Path, to: &Path) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        if let Err(err) = journal.record(from, to) {
+            if !self.unrecorded.swap(true, Ordering::Relaxed) {
+                warn!("couldn't record the renames in the journal: {}", err);
+            }
+        }
+    }
+
+    fn on_skipped(&self, path: &Path, reason: &str) {
+        if self.show_skipped {
+            println!("{:?} is left as it is, since {}", path, reason);
+        }
+    }
+}
+
+impl Observer {
+    /// Make the renames of a plan that was worked out some other way, noting their new names.
+    fn execute(
+        &self,
+        plan: rename::RenamePlan,
+        options: &rename::BulkRenameOptions,
+    ) -> rename::RenameReport {
+        for (from, to) in &plan.renames {
+            rename::RenameObserver::on_planned(self, from, to);
+        }
+        plan.execute_with(options, self)
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JsonLine<'a> {
+    from: std::borrow::Cow<'a, str>,
+    to: Option<std::borrow::Cow<'a, str>>,
+    status: &'a str,
+    error: Option<String>,
+}
+
+/// Print a line of JSON for each path of the report, with its old and new names, what happened
+/// to it, and why it didn't work, if it didn't.
+fn print_json(report: &rename::RenameReport, planned: &Planned, dry_run: bool) {
+    let planned = planned.0.lock().unwrap_or_else(|err| err.into_inner());
+    let line = |from: &Path, to: Option<&Path>, status: &str, error: Option<String>| {
+        let line = JsonLine {
+            from: from.to_string_lossy(),
+            to: to.map(|to| to.to_string_lossy()),
+            status,
+            error,
+        };
+        match serde_json::to_string(&line) {
+            Ok(json) => println!("{}", json),
+            Err(err) => error!("{:?}: {}", from, err),
+        }
+    };
+
+    // with --dry-run, the paths that conflict are in the renames too
+    let conflicting: HashSet<&Path> = report
+        .conflicts
+        .iter()
+        .flat_map(|conflict| conflict.sources())
+        .map(PathBuf::as_path)
+        .collect();
+
+    for (from, to) in &report.renamed {
+        if !conflicting.contains(from.as_path()) {
+            let status = if dry_run { "planned" } else { "renamed" };
+            line(from, Some(to), status, None);
+        }
+    }
+    for conflict in &report.conflicts {
+        for source in conflict.sources() {
+            let error = Some(conflict.to_string());
+            line(source, Some(conflict.target()), "conflict", error);
+        }
+    }
+    for (path, err) in &report.failed {
+        let to = planned.get(path).map(PathBuf::as_path);
+        line(path, to, "failed", Some(err.to_string()));
+    }
+    for path in &report.skipped {
+        line(path, None, "skipped", None);
+    }
+}
+
+/// Parse the expression, after the type declarations in the file of `types`, or exit with an
+/// error.
+fn parse_rules(expression: &str, types: Option<&Path>, plain: bool) -> MatchAndReplaceRulesOwned {
+    let types = match types {
+        Some(path) => std::fs::read_to_string(path).unwrap_or_else(|e| {
+            command(plain)
+                .error(ErrorKind::Io, format!("couldn't read {path:?}: {e}"))
+                .exit()
+        }),
+        None => String::new(),
+    };
+
+    MatchAndReplaceRulesOwned::from_str(&format!("{}{}", types.trim_start(), expression))
+        .unwrap_or_else(|e| invalid_expression(e.render(plain), plain))
+}
+
+/// The rules of the rules file and of each expression, in the order they're applied in, after
+/// printing their warnings, unless `quiet`; or exit with an error.
+fn simple_rules(args: &SimpleArgs, quiet: bool, plain: bool) -> Vec<MatchAndReplaceRulesOwned> {
+    let rules_file = args
+        .rules_file
+        .as_deref()
+        .map(|file| read_rules(file, plain));
+    let rules: Vec<_> = rules_file
+        .iter()
+        .chain(args.expressions())
+        .map(|expression| parse_rules(expression, args.types.as_deref(), plain))
+        .collect();
+
+    if !quiet {
+        for warning in rules.iter().flat_map(|rules| rules.rules().warnings()) {
+            eprintln!("{} {warning}", "warning:".yellow().bold());
+        }
+    }
+
+    rules
+}
+
+/// A replacer that applies the rules one after the other, set up as the arguments say.
+fn simple_replacer<'r>(
+    rules: &'r [MatchAndReplaceRulesOwned],
+    args: &SimpleArgs,
+) -> MatchAndReplaceChain<MatchAndReplacer<'r>> {
+    MatchAndReplaceChain::new(
+        rules
+            .iter()
+            .map(|rules| {
+                let mut replacer = MatchAndReplacer::from_rules(rules.rules().clone());
+                replacer.set_strip(args.strip);
+                replacer.set_global(args.all);
+                replacer.set_anchored(args.whole);
+                replacer.set_case_insensitive(args.ignore_case);
+                replacer.set_rightmost(args.last);
+                replacer.set_counter(args.start, args.step, args.width);
+                replacer
+            })
+            .collect(),
+    )
+}
+
+/// The rules in the file, one on each line, as one expression with a `;` between each of them,
+/// or exit with an error.
+fn read_rules(file: &Path, plain: bool) -> String {
+    let contents = std::fs::read_to_string(file).unwrap_or_else(|e| {
+        command(plain)
+            .error(ErrorKind::Io, format!("couldn't read {file:?}: {e}"))
+            .exit()
+    });
+
+    let mut rules = String::new();
+    for line in contents.lines() {
+        // a comment starts at a `#` at the start of the line, or after whitespace
+        let comment = line
+            .char_indices()
+            .find(|&(i, c)| c == '#' && (i == 0 || line[..i].ends_with(char::is_whitespace)))
+            .map_or(line.len(), |(i, _)| i);
+        let rule = line[..comment].trim();
+        if rule.is_empty() {
+            continue;
+        }
+
+        rules.push_str(rule);
+        if !rule.ends_with(';') {
+            rules.push(';');
+        }
+        rules.push('\n');
+    }
+
+    rules
+}
+
+/// Print the error in the expression, and exit.
+fn invalid_expression(message: impl std::fmt::Display, plain: bool) -> ! {
+    let _ = command(plain)
+        .error(ErrorKind::ValueValidation, message)
+        .print();
+    std::process::exit(INVALID_EXPRESSION.into())
 }
 
 #[derive(Debug, Args)]
 struct SimpleArgs {
-    /// A Match & Replace expression in the custom MRP syntax.
-    expression: MatchAndReplaceExpression<'static>,
+    /// A Match & Replace expression in the custom MRP syntax. Several can be given, separated by
+    /// `;`, to be applied one after the other.
+    #[clap(required_unless_present_any = &["expressions", "rules-file"])]
+    expression: Option<String>,
+    /// An expression to apply to the result of the ones given before it; instead of the one
+    /// before the paths. Can be given more than once.
+    #[clap(
+        short = 'e',
+        long = "expression",
+        value_name = "EXPRESSION",
+        multiple_occurrences = true
+    )]
+    expressions: Vec<String>,
+    /// A file of type declarations, like `type ep = int{2};`, for the expression to use.
+    #[clap(long, value_name = "FILE")]
+    types: Option<std::path::PathBuf>,
+    /// A file of rules, one on each line, applied in order before the expressions; lines that
+    /// are empty or start with `#` are left out.
+    #[clap(long, value_name = "FILE")]
+    rules_file: Option<std::path::PathBuf>,
     /// Strip off anything not explicitly matched for while replacting.
     #[clap(short, long)]
     strip: bool,
+    /// Replace every match in a path, instead of only the first one.
+    #[clap(short, long)]
+    all: bool,
+    /// Only rename paths that the expression matches as a whole.
+    #[clap(long)]
+    whole: bool,
+    /// Match the literal text of the expression in any case.
+    #[clap(short, long)]
+    ignore_case: bool,
+    /// Replace the last match in a path, instead of the first one.
+    #[clap(long, conflicts_with = "all")]
+    last: bool,
+    /// The first value of the (#) counter variable.
+    #[clap(long, default_value_t = 1)]
+    start: usize,
+    /// How much the (#) counter variable increases for each renamed path.
+    #[clap(long, default_value_t = 1)]
+    step: usize,
+    /// Pad the (#) counter variable with zeroes to at least this many digits.
+    #[clap(long, default_value_t = 0)]
+    width: usize,
+}
+
+impl SimpleArgs {
+    /// The expressions, in the order they're applied in.
+    fn expressions(&self) -> impl Iterator<Item = &String> {
+        self.expression.iter().chain(&self.expressions)
+    }
+
+    /// The expressions as they were given, for the journal.
+    fn quoted(&self) -> String {
+        let flag = match self.expressions.is_empty() && self.rules_file.is_none() {
+            true => "",
+            false => "-e ",
+        };
+        self.rules_file
+            .iter()
+            .map(|file| format!("--rules-file {file:?}"))
+            .chain(
+                self.expressions()
+                    .map(|expression| format!("{flag}{expression:?}")),
+            )
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Debug, Args)]
+struct ExplainArgs {
+    /// A Match & Replace expression in the custom MRP syntax.
+    expression: String,
+    /// A file of type declarations, like `type ep = int{2};`, for the expression to use.
+    #[clap(long, value_name = "FILE")]
+    types: Option<std::path::PathBuf>,
 }
 
 #[derive(Debug, Args, Clone)]
 struct RegexArgs {
     /// The regex pattern with which to search.
-    pattern: regex::Regex,
+    pattern: String,
     /// The replacement format based on the regex capture groups.
     replacement: String,
+    /// Replace every match in a path, instead of only the first one.
+    #[clap(short, long)]
+    all: bool,
+}
+
+#[derive(Debug, Args)]
+struct UndoArgs {
+    /// The id of the run to undo, as `history` shows it; by default, the last one that isn't
+    /// undone yet.
+    run: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct RedoArgs {
+    /// The id of the run to redo, as `history` shows it.
+    run: String,
+}
+
+#[derive(Debug, Args)]
+struct CaseArgs {
+    /// The case to write the names in: `my_file`, `my-file`, `myFile`, `MyFile`, `My File`,
+    /// or all in lowercase or uppercase.
+    #[clap(arg_enum)]
+    case: Case,
+}
+
+#[derive(Debug, Args)]
+struct NumberArgs {
+    /// The first number.
+    #[clap(long, default_value_t = 1)]
+    start: usize,
+    /// How much the number increases for each path.
+    #[clap(long, default_value_t = 1)]
+    step: usize,
+    /// Pad the numbers with zeroes to at least this many digits; by default, as many as the last
+    /// number has.
+    #[clap(long)]
+    width: Option<usize>,
+    /// The new name of each path, where `(#)` is its number; it can use the variables of the
+    /// simple match-and-replace-protocol syntax, like `(stem)`, `(ext)` or `(mtime:%Y%m%d)`.
+    #[clap(long, default_value = "(stem)-(#)(ext ? \".(ext)\" : \"\")")]
+    template: String,
+}
+
+#[derive(Debug, Args)]
+struct DateArgs {
+    /// Which time to name the paths after.
+    #[clap(long, arg_enum, default_value = "mtime")]
+    from: Time,
+    /// How to write the time, like `%Y-%m-%d_%H%M%S`; see `strftime` for what each `%` stands
+    /// for.
+    #[clap(long, default_value = "%Y-%m-%d_%H%M%S")]
+    format: String,
+}
+
+#[derive(Debug, Args)]
+struct FlattenArgs {
+    /// The directory to move the files into.
+    #[clap(parse(from_os_str))]
+    dir: std::path::PathBuf,
+    /// What to put between the names of the directories a file was in, and its own name.
+    #[clap(long, default_value = "_")]
+    separator: String,
+}
+
+#[derive(Debug, Args)]
+struct PlanArgs {
+    #[clap(flatten)]
+    simple: SimpleArgs,
+    /// The file to save the plan to, as JSON.
+    #[clap(short, long, value_name = "FILE", parse(from_os_str))]
+    output: std::path::PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct ApplyArgs {
+    /// The file that `plan` saved the plan to.
+    #[clap(parse(from_os_str))]
+    file: std::path::PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct MapArgs {
+    /// The file with a path and its new name on each row; it's TSV when its extension is `.tsv`
+    /// or its first line has a tab in it, and CSV otherwise.
+    #[clap(parse(from_os_str))]
+    file: std::path::PathBuf,
 }
 
-impl<'s> MatchAndReplaceStrategy<'s> for RegexArgs {
+impl Command {
+    /// What was run, for the journal; like `simple "(n:int)->ep(n)"`.
+    fn run(&self) -> String {
+        match self {
+            Command::Simple(args) => format!("simple {}", args.quoted()),
+            Command::Watch(args) => format!("watch {}", args.quoted()),
+            Command::Regex(args) => format!("regex {:?} {:?}", args.pattern, args.replacement),
+            Command::Sanitize => String::from("sanitize"),
+            Command::Case(args) => format!("case {:?}", args.case).to_lowercase(),
+            Command::Slugify => String::from("slugify"),
+            Command::Flatten(args) => {
+                format!("flatten {:?} --separator {:?}", args.dir, args.separator)
+            }
+            Command::Date(args) => format!(
+                "date --from {} --format {:?}",
+                args.from.variable(),
+                args.format
+            ),
+            Command::Number(args) => format!(
+                "number --start {} --step {}{} --template {:?}",
+                args.start,
+                args.step,
+                args.width
+                    .map(|width| format!(" --width {}", width))
+                    .unwrap_or_default(),
+                args.template
+            ),
+            Command::Edit => String::from("edit"),
+            Command::Map(args) => format!("map {:?}", args.file),
+            Command::Apply(args) => format!("apply {:?}", args.file),
+            Command::Explain(_)
+            | Command::Plan(_)
+            | Command::Undo(_)
+            | Command::Redo(_)
+            | Command::History => String::new(),
+        }
+    }
+}
+
+/// Print a line for each run: its id, when it was, how many paths it renamed and how many of
+/// those were undone, and what was run.
+fn print_history(runs: &[rename::JournalRun]) {
+    for run in runs {
+        let undone = match run.undone {
+            0 => String::new(),
+            n if n == run.renamed => String::from(", undone"),
+            n => format!(", {} undone", n),
+        };
+        let timestamp = chrono::DateTime::parse_from_rfc3339(&run.timestamp)
+            .map(|t| {
+                t.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|_| run.timestamp.clone());
+
+        println!(
+            "{}  {}  {} renamed{}  {}",
+            run.id.bold(),
+            timestamp,
+            run.renamed,
+            undone,
+            run.command
+        );
+    }
+}
+
+/// The arguments of `regex`, once the pattern is parsed.
+struct RegexReplacer {
+    pattern: regex::Regex,
+    replacement: String,
+    all: bool,
+}
+
+impl<'s> MatchAndReplaceStrategy<'s> for RegexReplacer {
+    fn apply(&self, value: &'s str) -> Option<std::borrow::Cow<'s, str>> {
+        if !self.pattern.is_match(value) {
+            return None;
+        }
+
+        if self.all {
+            Some(self.pattern.replace_all(value, self.replacement.as_str()))
+        } else {
+            Some(self.pattern.replace(value, self.replacement.as_str()))
+        }
+    }
+}
+
+/// Names each path after one of its times, for `date`; a path whose time can't be read isn't
+/// matched, instead of being named after nothing.
+struct Dated<'t> {
+    time: &'static str,
+    replacer: MatchAndReplacer<'t>,
+}
+
+impl<'s> MatchAndReplaceStrategy<'s> for Dated<'_> {
+    fn apply(&self, value: &'s str) -> Option<std::borrow::Cow<'s, str>> {
+        self.apply_with(value, &mrp::Variables::new())
+    }
+
+    fn apply_with(
+        &self,
+        value: &'s str,
+        variables: &mrp::Variables,
+    ) -> Option<std::borrow::Cow<'s, str>> {
+        variables.get(self.time)?;
+        self.replacer.apply_with(value, variables)
+    }
+
+    fn uses_variable(&self, name: &str) -> bool {
+        self.replacer.uses_variable(name)
+    }
+}
+
+/// Leaves each name as it is, for `sanitize` to make it valid.
+struct Unchanged;
+
+impl<'s> MatchAndReplaceStrategy<'s> for Unchanged {
     fn apply(&self, value: &'s str) -> Option<std::borrow::Cow<'s, str>> {
-        Some(self.pattern.replace(value, self.replacement.clone()))
+        Some(std::borrow::Cow::Borrowed(value))
     }
 }
@@ -0,0 +1,162 @@
+//! Renaming paths to the names that a mapping file gives them, like one made in a spreadsheet,
+//! for renames that no expression fits.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+
+use crate::{sanitize, BulkRenameOptions, RenamePlan};
+
+/// What the first row can be to be taken for a header, instead of a path and its new name.
+const HEADERS: [(&str, &str); 3] = [("old_path", "new_path"), ("old", "new"), ("from", "to")];
+
+/// Read the mapping file, where each row is a path and its new name, like `old_path,new_path`,
+/// and plan to rename each path to its new name. The file is TSV when its extension is `.tsv` or
+/// its first line has a tab in it, and CSV otherwise; lines that start with `#` are left out,
+/// and so is a first row like `old_path,new_path`, which is taken for a header.
+/// A row that doesn't have two names, or a path that is on more than one row, is an error; a path
+/// that isn't there fails without stopping the others.
+pub fn plan_mapped(file: &Path, options: &BulkRenameOptions) -> io::Result<RenamePlan> {
+    let text = std::fs::read(file)?;
+    let first_line = text.split(|&b| b == b'\n').next().unwrap_or_default();
+    let is_tsv = file.extension().is_some_and(|ext| ext == "tsv") || first_line.contains(&b'\t');
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .delimiter(if is_tsv { b'\t' } else { b',' })
+        .from_reader(text.as_slice());
+
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+
+    let mut plan = RenamePlan::default();
+    let mut seen = HashMap::new();
+    for (idx, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| invalid(err.to_string()))?;
+        let line = record.position().map_or(idx + 1, |p| p.line() as usize);
+
+        let (from, to) = match (record.get(0), record.get(1), record.len()) {
+            (Some(from), Some(to), 2) if !from.is_empty() && !to.is_empty() => (from, to),
+            _ => {
+                return Err(invalid(format!(
+                    "line {} should be a path and its new name, like `old_path,new_path`",
+                    line
+                )))
+            }
+        };
+        if idx == 0 && is_header(from, to) {
+            continue;
+        }
+        if let Some(first) = seen.insert(from.to_string(), line) {
+            return Err(invalid(format!(
+                "lines {} and {} both rename {:?}",
+                first, line, from
+            )));
+        }
+
+        let from = PathBuf::from(from);
+        let to = PathBuf::from(to);
+        let to = match options.sanitize {
+            Some(fs) => sanitize::sanitize(&from, to, fs),
+            None => to,
+        };
+
+        if from.symlink_metadata().is_err() {
+            let err = io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("it isn't there, on line {}", line),
+            );
+            error!("{:?}: {}", from, err);
+            plan.failed.push((from, err));
+        } else if to == from {
+            plan.skipped.push(from);
+        } else {
+            plan.renames.push((from, to));
+        }
+    }
+
+    Ok(plan)
+}
+
+fn is_header(from: &str, to: &str) -> bool {
+    !Path::new(from).exists()
+        && HEADERS
+            .iter()
+            .any(|(old, new)| from.eq_ignore_ascii_case(old) && to.eq_ignore_ascii_case(new))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn plan(dir: &Path, name: &str, text: &str) -> io::Result<RenamePlan> {
+        let file = dir.join(name);
+        fs::write(&file, text).unwrap();
+        plan_mapped(&file, &BulkRenameOptions::default())
+    }
+
+    #[test]
+    fn reading_csv_with_a_header_and_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a", "same", "b, with a comma"] {
+            fs::write(path(name), "").unwrap();
+        }
+
+        let text = format!(
+            "old_path,new_path\n# a comment\n{a},{x}\n{same},{same}\n\"{b}\",{y}\n{missing},{z}\n",
+            a = path("a").display(),
+            x = path("x").display(),
+            same = path("same").display(),
+            b = path("b, with a comma").display(),
+            y = path("y").display(),
+            missing = path("missing").display(),
+            z = path("z").display(),
+        );
+        let plan = plan(dir.path(), "map.csv", &text).unwrap();
+
+        assert_eq!(
+            plan.renames,
+            [(path("a"), path("x")), (path("b, with a comma"), path("y"))]
+        );
+        assert_eq!(plan.skipped, [path("same")]);
+        assert_eq!(plan.failed.len(), 1);
+        assert_eq!(plan.failed[0].0, path("missing"));
+    }
+
+    #[test]
+    fn reading_tsv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("a,b"), "").unwrap();
+
+        let text = format!("{}\t{}\n", path("a,b").display(), path("c").display());
+        // by the tab on the first line, and by the extension
+        for name in ["map.txt", "map.tsv"] {
+            let plan = plan(dir.path(), name, &text).unwrap();
+            assert_eq!(plan.renames, [(path("a,b"), path("c"))]);
+        }
+    }
+
+    #[test]
+    fn refusing_bad_rows_and_paths_on_more_than_one() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let err = plan(dir.path(), "map.csv", "a,b\nc\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("line 2"), "{}", err);
+
+        let err = plan(dir.path(), "map.csv", "a,b,c\n").unwrap_err();
+        assert!(err.to_string().contains("line 1"), "{}", err);
+
+        let err = plan(dir.path(), "map.csv", "a,b\nc,d\na,e\n").unwrap_err();
+        assert_eq!(err.to_string(), "lines 1 and 3 both rename \"a\"");
+    }
+}
@@ -0,0 +1,23 @@
+//! Hooks for following a bulk rename as it happens, like to show its progress.
+
+use std::{io, path::Path};
+
+/// Told about each path as it's planned and renamed; every method does nothing by default.
+/// Renames are made in parallel, so the methods can be called from more than one thread.
+pub trait RenameObserver: Sync {
+    /// The new name of a path was worked out.
+    fn on_planned(&self, _from: &Path, _to: &Path) {}
+
+    /// A path was renamed, or would have been with `no_rename`.
+    fn on_renamed(&self, _from: &Path, _to: &Path) {}
+
+    /// A path couldn't be renamed.
+    fn on_error(&self, _path: &Path, _err: &io::Error) {}
+
+    /// A path is left as it is, like one that wasn't matched, or whose new name is the same; the
+    /// reason reads like "it isn't matched".
+    fn on_skipped(&self, _path: &Path, _reason: &str) {}
+}
+
+/// Observes nothing.
+impl RenameObserver for () {}
@@ -0,0 +1,315 @@
+//! How a bulk rename is carried out.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// What to do about a rename to a name that is already taken; see [`crate::Conflict`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+    /// Rename nothing if any rename would overwrite a file.
+    #[default]
+    Never,
+    /// Leave the paths that would overwrite a file as they are, and rename the rest.
+    Skip,
+    /// Rename over files that are already there. Paths that would be renamed to the same name
+    /// are still left as they are, since which of them would be kept is up to chance.
+    Always,
+    /// Add a number to each name that is taken, like `notes (1).txt`, until it's one that isn't.
+    Number,
+    /// Ask on the terminal about each path that would overwrite a file, renaming it over the
+    /// file if the answer is yes, and leaving it as it is otherwise; like with `Skip`, paths that
+    /// would be renamed to the same name are left as they are.
+    Prompt,
+}
+
+/// What to do after a rename fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Go on with the other renames.
+    #[default]
+    Continue,
+    /// Make no more renames; those that are already underway are still finished.
+    Stop,
+}
+
+/// The order that the paths are worked out in, which is what the `(#)` counter counts them in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    /// The order they were given in.
+    #[default]
+    Given,
+    /// Sorted by path.
+    Sorted,
+    /// Sorted by path, with the numbers in it compared by their value, so that `file2` comes
+    /// before `file10`.
+    Natural,
+    /// By the time they were last modified, from the oldest.
+    Modified,
+    /// By size, from the smallest.
+    Size,
+}
+
+/// The file system that new names have to be valid on; see
+/// [`BulkRenameOptionsBuilder::sanitize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetFs {
+    /// Any name without a `/` or a null character, as on Linux or macOS.
+    Posix,
+    /// No `<>:"/\|?*` or control characters, no dot or space at the end, and none of the names
+    /// kept for devices, like `CON` or `LPT1.txt`.
+    Windows,
+    /// No `<>:"/\|?*` or control characters, as on memory cards and USB drives.
+    Exfat,
+}
+
+/// What to do with a path that is a symbolic link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Rename the link.
+    #[default]
+    Link,
+    /// Rename the file that it links to, where that is; each file is renamed once, however many
+    /// of the paths link to it.
+    Target,
+    /// Leave it as it is.
+    Skip,
+}
+
+/// A kind of link to make under the new name of a path, instead of renaming it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// A link to the absolute path of the original, so that it works from any directory.
+    Symbolic,
+    Hard,
+}
+
+/// How to carry out a bulk rename, made with [`BulkRenameOptions::builder`]; `no_rename` can
+/// also be set on the [`Default`] options, like before there were others. Since 0.6 there are, so
+/// the options can't be written out as `BulkRenameOptions { no_rename }` anymore, which breaks
+/// code that did; the others are only set with the builder, so that more can be added without
+/// breaking it again.
+#[derive(Debug, Clone, Default)]
+pub struct BulkRenameOptions {
+    /// Only print the renames, instead of making them; the same as
+    /// [`BulkRenameOptionsBuilder::dry_run`].
+    pub no_rename: bool,
+    pub(crate) verbose: bool,
+    pub(crate) silent: bool,
+    pub(crate) overwrite: OverwritePolicy,
+    pub(crate) on_error: ErrorPolicy,
+    pub(crate) order: Order,
+    pub(crate) reverse: bool,
+    pub(crate) threads: Option<usize>,
+    pub(crate) serial: bool,
+    pub(crate) cancel: Option<Arc<AtomicBool>>,
+    pub(crate) file_name_only: bool,
+    pub(crate) keep_extension: bool,
+    pub(crate) create_dirs: bool,
+    pub(crate) into: Option<PathBuf>,
+    pub(crate) link: Option<LinkKind>,
+    pub(crate) symlinks: SymlinkPolicy,
+    pub(crate) trash: bool,
+    pub(crate) git: bool,
+    pub(crate) sanitize: Option<TargetFs>,
+    pub(crate) backup_suffix: Option<String>,
+    pub(crate) backup_dir: Option<PathBuf>,
+    pub(crate) retries: u32,
+    pub(crate) retry_delay: Duration,
+}
+
+impl BulkRenameOptions {
+    pub fn builder() -> BulkRenameOptionsBuilder {
+        BulkRenameOptionsBuilder::default()
+    }
+
+    /// Whether the renames are only printed, instead of made.
+    pub fn is_dry_run(&self) -> bool {
+        self.no_rename
+    }
+
+    /// The kind of link that is made under each new name, if paths aren't renamed.
+    pub fn link_kind(&self) -> Option<LinkKind> {
+        self.link
+    }
+
+    /// Whether the flag given to [`BulkRenameOptionsBuilder::cancel_on`] has been set.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Why a rename shouldn't be started, if the run was cancelled or `stopped` after a failure.
+    pub(crate) fn interruption(&self, stopped: bool) -> Option<std::io::Error> {
+        let reason = match () {
+            _ if self.is_cancelled() => "not renamed, since the run was cancelled",
+            _ if stopped => "not renamed, since an earlier rename failed",
+            _ => return None,
+        };
+
+        Some(crate::chain::not_tried(reason))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BulkRenameOptionsBuilder {
+    options: BulkRenameOptions,
+}
+
+impl BulkRenameOptionsBuilder {
+    /// Don't rename anything, but print each rename that would be made.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.options.no_rename = dry_run;
+        self
+    }
+
+    /// Print each rename as it's made.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.options.verbose = verbose;
+        self
+    }
+
+    /// Print nothing, not even the renames of a dry run, like when the report is shown some other
+    /// way; errors and warnings are still logged.
+    pub fn silent(mut self, silent: bool) -> Self {
+        self.options.silent = silent;
+        self
+    }
+
+    pub fn overwrite(mut self, overwrite: OverwritePolicy) -> Self {
+        self.options.overwrite = overwrite;
+        self
+    }
+
+    pub fn on_error(mut self, on_error: ErrorPolicy) -> Self {
+        self.options.on_error = on_error;
+        self
+    }
+
+    /// Match only the last part of each path, its file name, so that the names of the
+    /// directories it's in are left as they are.
+    pub fn file_name_only(mut self, file_name_only: bool) -> Self {
+        self.options.file_name_only = file_name_only;
+        self
+    }
+
+    /// Match without the extension, and put it back on the new name after, so that an expression
+    /// doesn't have to match it, or can't replace it by accident.
+    pub fn keep_extension(mut self, keep_extension: bool) -> Self {
+        self.options.keep_extension = keep_extension;
+        self
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.options.order = order;
+        self
+    }
+
+    /// Work out the paths in the opposite of their order.
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.options.reverse = reverse;
+        self
+    }
+
+    /// How many threads make the renames; by default, there's one for each CPU.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = Some(threads);
+        self
+    }
+
+    /// Make the directories that a new name is in, when they aren't there already, like
+    /// `albums/1/` for `albums/1/track`.
+    pub fn create_dirs(mut self, create_dirs: bool) -> Self {
+        self.options.create_dirs = create_dirs;
+        self
+    }
+
+    /// Move each path into the directory under its new name, like `sorted/track1.mp3` for a new
+    /// name of `track1.mp3`, instead of leaving it in the directory it's in; the directories are
+    /// made when they aren't there, as with [`BulkRenameOptionsBuilder::create_dirs`].
+    pub fn into(mut self, dir: Option<PathBuf>) -> Self {
+        self.options.into = dir;
+        self
+    }
+
+    /// Whether a symbolic link among the paths is renamed, or the file it links to, or neither.
+    pub fn symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.options.symlinks = symlinks;
+        self
+    }
+
+    /// Move a file that a rename would overwrite to the trash of the system first, so that it
+    /// can still be brought back; files are only overwritten with [`OverwritePolicy::Always`]
+    /// or [`OverwritePolicy::Prompt`].
+    pub fn trash(mut self, trash: bool) -> Self {
+        self.options.trash = trash;
+        self
+    }
+
+    /// Rename the paths that git tracks with `git mv`, so that the renames are staged in their
+    /// repository; other paths are renamed as usual.
+    pub fn git(mut self, git: bool) -> Self {
+        self.options.git = git;
+        self
+    }
+
+    /// Replace the characters of new names that aren't allowed on the file system with `_`,
+    /// and, for Windows, the dots and spaces at the end of a name, and add a `_` to names that
+    /// Windows keeps for devices, like `CON_.txt`.
+    pub fn sanitize(mut self, target: Option<TargetFs>) -> Self {
+        self.options.sanitize = target;
+        self
+    }
+
+    /// Copy each file before renaming it, to its name with the suffix added, like `notes.txt.bak`.
+    pub fn backup(mut self, suffix: Option<String>) -> Self {
+        self.options.backup_suffix = suffix;
+        self
+    }
+
+    /// Copy each file to the directory before renaming it, under its name with the suffix given
+    /// to [`BulkRenameOptionsBuilder::backup`], if there's one.
+    pub fn backup_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.options.backup_dir = dir;
+        self
+    }
+
+    /// Leave the paths as they are, and make links to them under their new names instead.
+    pub fn link(mut self, link: Option<LinkKind>) -> Self {
+        self.options.link = link;
+        self
+    }
+
+    /// Try a rename again, up to `retries` times, after an error that is likely to go away on
+    /// its own, like a file being busy; the first time after `delay`, and then after twice as
+    /// long as the time before.
+    pub fn retry(mut self, retries: u32, delay: Duration) -> Self {
+        self.options.retries = retries;
+        self.options.retry_delay = delay;
+        self
+    }
+
+    /// Make the renames one after the other on the calling thread, without starting any others;
+    /// this is quicker for a few paths, and easier on a network file system.
+    pub fn serial(mut self, serial: bool) -> Self {
+        self.options.serial = serial;
+        self
+    }
+
+    /// Stop once the flag is set, like by a handler of Ctrl-C; the renames that are underway are
+    /// finished, and the rest are reported as failed.
+    pub fn cancel_on(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.options.cancel = Some(cancel);
+        self
+    }
+
+    pub fn build(self) -> BulkRenameOptions {
+        self.options
+    }
+}
@@ -0,0 +1,89 @@
+//! Matching paths that aren't valid unicode.
+//!
+//! On Unix, where a path can be any bytes, the bytes that aren't part of a character are written
+//! as characters of a private use area, from U+10FF00 to U+10FFFF, for the strategy to match
+//! around; those characters are turned back into the bytes they stand for in the new name, so
+//! nothing about the path is lost.
+
+use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+};
+
+use mrp::{MatchAndReplaceStrategy, Variables};
+
+/// Where the characters that stand for bytes start.
+#[cfg(unix)]
+const BYTE_ESCAPES: u32 = 0x10FF00;
+
+/// Match and replace on an [`OsStr`], which isn't always valid unicode.
+pub trait OsStrMatchAndReplaceStrategy {
+    /// Match and replace, given values for the built-in variables; it's `None` if nothing was
+    /// matched, or if the value can't be matched on this platform.
+    fn apply_os(&self, value: &OsStr, variables: &Variables) -> Option<OsString>;
+}
+
+impl<R: for<'a> MatchAndReplaceStrategy<'a>> OsStrMatchAndReplaceStrategy for R {
+    fn apply_os(&self, value: &OsStr, variables: &Variables) -> Option<OsString> {
+        if let Some(value) = value.to_str() {
+            return self
+                .apply_with(value, variables)
+                .map(|renamed| renamed.into_owned().into());
+        }
+
+        let decoded = decode(value)?;
+        self.apply_with(&decoded, variables)
+            .map(|renamed| encode(&renamed))
+    }
+}
+
+/// The value as a string, with the bytes that aren't part of a character escaped.
+#[cfg(unix)]
+pub(crate) fn decode(value: &OsStr) -> Option<Cow<'_, str>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    if let Some(value) = value.to_str() {
+        return Some(Cow::Borrowed(value));
+    }
+
+    let mut decoded = String::with_capacity(value.len());
+
+    for chunk in value.as_bytes().utf8_chunks() {
+        decoded.push_str(chunk.valid());
+        decoded.extend(
+            chunk
+                .invalid()
+                .iter()
+                .filter_map(|b| char::from_u32(BYTE_ESCAPES + u32::from(*b))),
+        );
+    }
+
+    Some(Cow::Owned(decoded))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn decode(value: &OsStr) -> Option<Cow<'_, str>> {
+    value.to_str().map(Cow::Borrowed)
+}
+
+/// The value with the escaped bytes put back.
+#[cfg(unix)]
+pub(crate) fn encode(value: &str) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut bytes = Vec::with_capacity(value.len());
+
+    for c in value.chars() {
+        match u32::from(c).checked_sub(BYTE_ESCAPES) {
+            Some(byte) => bytes.push(byte as u8),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn encode(value: &str) -> OsString {
+    value.into()
+}
@@ -0,0 +1,170 @@
+//! Saving a plan to a JSON file, to be looked over or edited before it's applied, maybe much
+//! later. Paths are written as strings, with the bytes of a path that aren't part of a character
+//! escaped, like they are for matching; see [`crate::os_str`].
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{os_str, sanitize, BulkRenameOptions, RenamePlan};
+
+#[derive(Serialize, Deserialize)]
+struct PlanFile {
+    renames: Vec<Rename>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Rename {
+    from: String,
+    to: String,
+}
+
+impl RenamePlan {
+    /// Write the renames to the file as JSON, with absolute paths, so that the plan can be
+    /// applied from any directory; the paths that are skipped or failed are left out.
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        let renames = self
+            .renames
+            .iter()
+            .map(|(from, to)| {
+                Ok(Rename {
+                    from: text(&std::path::absolute(from)?)?,
+                    to: text(&std::path::absolute(to)?)?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        let mut json = serde_json::to_string_pretty(&PlanFile { renames })?;
+        json.push('\n');
+        fs::write(file, json)
+    }
+
+    /// Read a plan that was saved to the file, checking again that each path is still there; a
+    /// path that isn't fails without stopping the others. A path that is renamed more than once
+    /// is an error, and whether the new names are still free is checked when the plan is
+    /// executed, like for any other plan.
+    pub fn load(file: &Path, options: &BulkRenameOptions) -> io::Result<RenamePlan> {
+        let saved: PlanFile = serde_json::from_slice(&fs::read(file)?)?;
+
+        let mut plan = RenamePlan::default();
+        let mut seen = HashMap::new();
+        for (idx, Rename { from, to }) in saved.renames.into_iter().enumerate() {
+            if from.is_empty() || to.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("rename {} should have both a `from` and a `to`", idx + 1),
+                ));
+            }
+            let (from, to) = (path(&from), path(&to));
+            if let Some(first) = seen.insert(from.clone(), idx) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "renames {} and {} both rename {:?}",
+                        first + 1,
+                        idx + 1,
+                        from
+                    ),
+                ));
+            }
+
+            let to = match options.sanitize {
+                Some(fs) => sanitize::sanitize(&from, to, fs),
+                None => to,
+            };
+
+            if from.symlink_metadata().is_err() {
+                let err = io::Error::new(io::ErrorKind::NotFound, "it isn't there anymore");
+                error!("{:?}: {}", from, err);
+                plan.failed.push((from, err));
+            } else if to == from {
+                plan.skipped.push(from);
+            } else {
+                plan.renames.push((from, to));
+            }
+        }
+
+        Ok(plan)
+    }
+}
+
+fn text(path: &Path) -> io::Result<String> {
+    os_str::decode(path.as_os_str())
+        .map(|text| text.into_owned())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?}: invalid unicode", path),
+            )
+        })
+}
+
+fn path(text: &str) -> PathBuf {
+    PathBuf::from(os_str::encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saving_and_loading_a_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("a"), "").unwrap();
+        fs::write(path("b"), "").unwrap();
+        let file = path("plan.json");
+
+        let plan = RenamePlan {
+            renames: vec![(path("a"), path("x")), (path("b"), path("y"))],
+            ..Default::default()
+        };
+        plan.save(&file).unwrap();
+        fs::remove_file(path("b")).unwrap();
+
+        let loaded = RenamePlan::load(&file, &BulkRenameOptions::default()).unwrap();
+        assert_eq!(loaded.renames, [(path("a"), path("x"))]);
+        assert_eq!(loaded.failed.len(), 1);
+        assert_eq!(loaded.failed[0].0, path("b"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_and_loading_names_that_arent_unicode() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join(OsStr::from_bytes(b"caf\xe9.txt"));
+        let to = dir.path().join(OsStr::from_bytes(b"caf\xe9 \xff.txt"));
+        fs::write(&from, "").unwrap();
+        let file = dir.path().join("plan.json");
+
+        let plan = RenamePlan {
+            renames: vec![(from.clone(), to.clone())],
+            ..Default::default()
+        };
+        plan.save(&file).unwrap();
+
+        let loaded = RenamePlan::load(&file, &BulkRenameOptions::default()).unwrap();
+        assert_eq!(loaded.renames, [(from, to)]);
+    }
+
+    #[test]
+    fn refusing_a_path_renamed_twice() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("plan.json");
+        fs::write(
+            &file,
+            r#"{"renames":[{"from":"a","to":"b"},{"from":"a","to":"c"}]}"#,
+        )
+        .unwrap();
+
+        let err = RenamePlan::load(&file, &BulkRenameOptions::default()).unwrap_err();
+        assert_eq!(err.to_string(), "renames 1 and 2 both rename \"a\"");
+    }
+}
@@ -0,0 +1,101 @@
+//! Trying a rename again after an error that is likely to go away on its own, like when the file
+//! is held open by an antivirus scanner on Windows, or a network file system is busy.
+
+use std::{io, path::Path};
+
+use log::*;
+
+use crate::BulkRenameOptions;
+
+/// Call `f` until it works, it fails with an error that isn't transient, or it has been tried
+/// again as many times as the options say; each wait before trying again is twice as long as
+/// the one before.
+pub(crate) fn retrying<T>(
+    path: &Path,
+    options: &BulkRenameOptions,
+    mut f: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut delay = options.retry_delay;
+
+    for _ in 0..options.retries {
+        match f() {
+            Err(err) if is_transient(&err) && !options.is_cancelled() => {
+                warn!("{:?}: {}; trying again in {:?}", path, err, delay);
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+            result => return result,
+        }
+    }
+
+    f()
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION, when another program has the file open
+    #[cfg(windows)]
+    if matches!(err.raw_os_error(), Some(32 | 33)) {
+        return true;
+    }
+
+    matches!(
+        err.kind(),
+        io::ErrorKind::ResourceBusy
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use super::*;
+
+    /// Try `f` with up to 3 tries after the first, counting how many times it's called.
+    fn tries(mut f: impl FnMut(u32) -> io::Result<()>) -> (io::Result<()>, u32) {
+        let options = BulkRenameOptions::builder()
+            .retry(3, Duration::from_millis(1))
+            .build();
+        let calls = Cell::new(0);
+
+        let result = retrying(Path::new("a"), &options, || {
+            calls.set(calls.get() + 1);
+            f(calls.get())
+        });
+        (result, calls.get())
+    }
+
+    #[test]
+    fn trying_again_until_it_works() {
+        let (result, calls) = tries(|call| match call {
+            1 => Err(io::ErrorKind::ResourceBusy.into()),
+            2 => Err(io::ErrorKind::Interrupted.into()),
+            _ => Ok(()),
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn giving_up_after_the_last_try() {
+        let (result, calls) = tries(|_| Err(io::ErrorKind::TimedOut.into()));
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(calls, 4);
+    }
+
+    #[test]
+    fn not_trying_again_after_other_errors() {
+        let (result, calls) = tries(|_| Err(io::ErrorKind::NotFound.into()));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(calls, 1);
+
+        // like one after a rename before it failed, which isn't an error of the file system
+        let (result, calls) = tries(|_| Err(crate::chain::not_tried("not renamed")));
+        assert!(crate::chain::is_not_tried(&result.unwrap_err()));
+        assert_eq!(calls, 1);
+    }
+}
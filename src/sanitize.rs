@@ -0,0 +1,141 @@
+//! Making new names valid on the file system they're meant for, like replacing the `:` that
+//! Windows doesn't allow in a name.
+
+use std::{
+    ffi::OsStr,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{os_str, TargetFs};
+
+/// What characters that aren't allowed are replaced with.
+const REPLACEMENT: char = '_';
+
+/// Names that Windows keeps for devices, with or without an extension.
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The new name of `from`, with each part of it that the rename made valid on the file system;
+/// the directories that `from` is in already are left as they are.
+pub(crate) fn sanitize(from: &Path, to: PathBuf, fs: TargetFs) -> PathBuf {
+    let dir = from.parent().unwrap_or(Path::new(""));
+    let Ok(made) = to.strip_prefix(dir) else {
+        return match to.file_name() {
+            Some(name) => to.with_file_name(name_for(name, fs)),
+            None => to,
+        };
+    };
+
+    let mut sanitized = dir.to_path_buf();
+    for component in made.components() {
+        match component {
+            Component::Normal(name) => sanitized.push(name_for(name, fs)),
+            other => sanitized.push(other),
+        }
+    }
+    sanitized
+}
+
+/// The name, made valid on the file system.
+fn name_for(name: &OsStr, fs: TargetFs) -> std::ffi::OsString {
+    let Some(decoded) = os_str::decode(name) else {
+        return name.to_os_string();
+    };
+
+    let mut sanitized: String = decoded
+        .chars()
+        .map(|c| match is_allowed(c, fs) {
+            true => c,
+            false => REPLACEMENT,
+        })
+        .collect();
+
+    if fs == TargetFs::Windows {
+        // Windows drops them, so the name wouldn't be the one that was asked for
+        let kept = sanitized.trim_end_matches(['.', ' ']).len();
+        if kept < sanitized.len() {
+            sanitized.truncate(kept);
+            sanitized.push(REPLACEMENT);
+        }
+
+        let stem = sanitized.split('.').next().unwrap_or_default();
+        if RESERVED
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(stem.trim_end()))
+        {
+            sanitized.insert(stem.len(), REPLACEMENT);
+        }
+    }
+
+    os_str::encode(&sanitized)
+}
+
+fn is_allowed(c: char, fs: TargetFs) -> bool {
+    match fs {
+        TargetFs::Posix => c != '\0',
+        TargetFs::Windows | TargetFs::Exfat => {
+            !c.is_ascii_control()
+                && !matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*')
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str, fs: TargetFs) -> String {
+        name_for(OsStr::new(name), fs).into_string().unwrap()
+    }
+
+    #[test]
+    fn replacing_characters_that_arent_allowed() {
+        assert_eq!(name("a:b?<c>.txt", TargetFs::Windows), "a_b__c_.txt");
+        assert_eq!(name("a:b\u{1}.txt", TargetFs::Exfat), "a_b_.txt");
+        assert_eq!(name("a:b\0.txt", TargetFs::Posix), "a:b_.txt");
+    }
+
+    #[test]
+    fn keeping_names_off_the_ones_windows_keeps_for_devices() {
+        for (reserved, sanitized) in [
+            ("CON", "CON_"),
+            ("con.txt", "con_.txt"),
+            ("Lpt1.tar.gz", "Lpt1_.tar.gz"),
+            ("NUL .txt", "NUL _.txt"),
+            ("CONSOLE.txt", "CONSOLE.txt"),
+            ("COM10", "COM10"),
+        ] {
+            assert_eq!(name(reserved, TargetFs::Windows), sanitized, "{}", reserved);
+        }
+
+        assert_eq!(name("CON", TargetFs::Exfat), "CON");
+    }
+
+    #[test]
+    fn replacing_dots_and_spaces_at_the_end_on_windows() {
+        assert_eq!(name("notes.", TargetFs::Windows), "notes_");
+        assert_eq!(name("notes. . ", TargetFs::Windows), "notes_");
+        assert_eq!(name("notes.txt", TargetFs::Windows), "notes.txt");
+        assert_eq!(name("notes.", TargetFs::Exfat), "notes.");
+    }
+
+    #[test]
+    fn leaving_the_directories_a_path_is_in_already() {
+        let sanitized = sanitize(
+            Path::new("a:b/file.txt"),
+            PathBuf::from("a:b/c:d/e?.txt"),
+            TargetFs::Windows,
+        );
+        assert_eq!(sanitized, Path::new("a:b/c_d/e_.txt"));
+
+        // moved somewhere else, so only the name is its own
+        let sanitized = sanitize(
+            Path::new("a:b/file.txt"),
+            PathBuf::from("c:d/e?.txt"),
+            TargetFs::Windows,
+        );
+        assert_eq!(sanitized, Path::new("c:d/e_.txt"));
+    }
+}
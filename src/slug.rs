@@ -0,0 +1,89 @@
+//! Making names safe to use in URLs, like `Café Menu (2).JPG` as `cafe-menu-2.jpg`, for
+//! `slugify`.
+
+use std::borrow::Cow;
+
+use mrp::MatchAndReplaceStrategy;
+
+use crate::case::split_name;
+
+/// Writes the name of each path in lowercase ASCII letters and digits, with a `-` between the
+/// words: letters like `é` or `ß` are written as the ASCII letters closest to them, and each run
+/// of spaces, punctuation and other characters becomes a single `-`. The extension is lowercased
+/// too, and the directories and any dots at the start are left as they are. A name with no
+/// letters or digits in it isn't matched.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Slugify;
+
+impl<'s> MatchAndReplaceStrategy<'s> for Slugify {
+    fn apply(&self, value: &'s str) -> Option<Cow<'s, str>> {
+        let (dir, dots, stem, ext) = split_name(value);
+
+        let stem = slug(stem);
+        if stem.is_empty() {
+            return None;
+        }
+        let ext = match slug(ext) {
+            ext if ext.is_empty() => ext,
+            ext => format!(".{ext}"),
+        };
+
+        Some(Cow::Owned(format!("{dir}{dots}{stem}{ext}")))
+    }
+}
+
+/// The text in lowercase ASCII letters and digits, with a `-` between each run of them.
+fn slug(text: &str) -> String {
+    let ascii = deunicode::deunicode(text).to_lowercase();
+    let mut slug = String::with_capacity(ascii.len());
+
+    for word in ascii
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(word);
+    }
+
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slugify(name: &str) -> Option<String> {
+        Slugify.apply(name).map(Cow::into_owned)
+    }
+
+    #[test]
+    fn writing_letters_as_the_ascii_ones_closest_to_them() {
+        assert_eq!(slugify("Café Menu (2).JPG").unwrap(), "cafe-menu-2.jpg");
+        assert_eq!(slugify("Straße.txt").unwrap(), "strasse.txt");
+        assert_eq!(slugify("日本").unwrap(), "ri-ben");
+    }
+
+    #[test]
+    fn making_each_run_of_punctuation_one_dash() {
+        assert_eq!(slugify("a -- b__c!!d.txt").unwrap(), "a-b-c-d.txt");
+        assert_eq!(slugify("(draft) notes_.md").unwrap(), "draft-notes.md");
+    }
+
+    #[test]
+    fn not_matching_names_that_are_all_punctuation() {
+        assert_eq!(slugify("---.txt"), None);
+        assert_eq!(slugify("(!).md"), None);
+    }
+
+    #[test]
+    fn leaving_the_dots_at_the_start_and_the_directories() {
+        assert_eq!(slugify(".My Config").unwrap(), ".my-config");
+        assert_eq!(
+            slugify("Photos/.Été 2020.JPG").unwrap(),
+            "Photos/.ete-2020.jpg"
+        );
+        assert_eq!(slugify("..."), None);
+    }
+}
@@ -0,0 +1,141 @@
+//! Putting the paths in the order they're worked out in, which is what the `(#)` counter counts.
+
+use std::{cmp::Ordering, path::PathBuf, time::SystemTime};
+
+use crate::Order;
+
+/// Sort the paths; ties, like files modified at the same moment, are broken by path, so that the
+/// order doesn't depend on how the paths were listed.
+pub(crate) fn sort(paths: &mut [&PathBuf], order: Order) {
+    match order {
+        Order::Given => {}
+        Order::Sorted => paths.sort(),
+        Order::Natural => paths.sort_by(|a, b| {
+            natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()).then_with(|| a.cmp(b))
+        }),
+        // paths that can't be looked at come first, and fail once they're renamed
+        Order::Modified => paths.sort_by_cached_key(|p| {
+            let modified = p.metadata().and_then(|m| m.modified()).ok();
+            (modified.unwrap_or(SystemTime::UNIX_EPOCH), p.to_path_buf())
+        }),
+        Order::Size => {
+            paths.sort_by_cached_key(|p| (p.metadata().map_or(0, |m| m.len()), p.to_path_buf()))
+        }
+    }
+}
+
+/// Compare the two like text, except for runs of digits, which are compared by the number they
+/// make; with the same numbers, the one with fewer leading zeros comes first.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    let mut zeros = Ordering::Equal;
+
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len()).then(zeros);
+        };
+
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (digits_a, rest_a) = split_digits(a);
+            let (digits_b, rest_b) = split_digits(b);
+            let (value_a, value_b) = (
+                digits_a.trim_start_matches('0'),
+                digits_b.trim_start_matches('0'),
+            );
+
+            let by_value = value_a.len().cmp(&value_b.len()).then(value_a.cmp(value_b));
+            if by_value != Ordering::Equal {
+                return by_value;
+            }
+            zeros = zeros.then(digits_a.len().cmp(&digits_b.len()));
+            (a, b) = (rest_a, rest_b);
+        } else if x != y {
+            return x.cmp(&y);
+        } else {
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+        }
+    }
+}
+
+/// The digits at the start of the text, and what comes after them.
+fn split_digits(text: &str) -> (&str, &str) {
+    text.split_at(
+        text.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        time::Duration,
+    };
+
+    use super::*;
+
+    fn sorted(paths: &[PathBuf], order: Order) -> Vec<&PathBuf> {
+        let mut sorted: Vec<_> = paths.iter().collect();
+        sort(&mut sorted, order);
+        sorted
+    }
+
+    #[test]
+    fn comparing_numbers_by_their_value() {
+        let mut names = [
+            "file10", "file2", "file02", "file1", "file", "File3", "file1a",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+
+        assert_eq!(
+            names,
+            ["File3", "file", "file1", "file1a", "file2", "file02", "file10"]
+        );
+    }
+
+    #[test]
+    fn sorting_by_name() {
+        let paths = ["b10", "a", "b9"].map(PathBuf::from);
+
+        assert_eq!(
+            sorted(&paths, Order::Given),
+            [&paths[0], &paths[1], &paths[2]]
+        );
+        assert_eq!(
+            sorted(&paths, Order::Sorted),
+            [&paths[1], &paths[0], &paths[2]]
+        );
+        assert_eq!(
+            sorted(&paths, Order::Natural),
+            [&paths[1], &paths[2], &paths[0]]
+        );
+    }
+
+    #[test]
+    fn sorting_by_size_and_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = ["a", "b", "c"].map(|name| dir.path().join(name));
+        for (path, (size, age)) in paths.iter().zip([(3, 1), (1, 2), (3, 3)]) {
+            fs::write(path, "x".repeat(size)).unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(age * 60);
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        let mut paths = paths.to_vec();
+        paths.push(dir.path().join("missing"));
+
+        // ties are broken by path, and paths that can't be looked at come first
+        assert_eq!(
+            sorted(&paths, Order::Size),
+            [&paths[3], &paths[1], &paths[0], &paths[2]]
+        );
+        assert_eq!(
+            sorted(&paths, Order::Modified),
+            [&paths[3], &paths[2], &paths[1], &paths[0]]
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! The `tag.*` variables, read from the tags of audio files, like ID3 tags or Vorbis comments.
+
+use std::path::Path;
+
+use lofty::prelude::*;
+use log::*;
+use mrp::{MatchAndReplaceStrategy, Variables};
+
+/// The names of the variables.
+const VARIABLES: [&str; 4] = ["tag.artist", "tag.album", "tag.title", "tag.track"];
+
+/// Put the values of the `tag.*` variables that the strategy uses, if the file has them.
+pub(crate) fn put_variables<'r, R: MatchAndReplaceStrategy<'r>>(
+    file: &Path,
+    rename: &R,
+    variables: &mut Variables<'_>,
+) {
+    if !VARIABLES.iter().any(|name| rename.uses_variable(name)) {
+        return;
+    }
+
+    let tagged = match lofty::read_from_path(file) {
+        Ok(tagged) => tagged,
+        Err(err) => {
+            error!("{:?}: can't read tags: {}", file, err);
+            return;
+        }
+    };
+    let Some(tag) = tagged.primary_tag().or_else(|| tagged.first_tag()) else {
+        warn!("{:?}: there are no tags", file);
+        return;
+    };
+
+    for name in VARIABLES {
+        if !rename.uses_variable(name) {
+            continue;
+        }
+
+        let value = match name {
+            "tag.artist" => tag.artist().map(|artist| artist.into_owned()),
+            "tag.album" => tag.album().map(|album| album.into_owned()),
+            "tag.title" => tag.title().map(|title| title.into_owned()),
+            _ => tag.track().map(|track| track.to_string()),
+        };
+
+        match value {
+            Some(value) => variables.put(name, value),
+            None => warn!("{:?}: there's no {} in its tags", file, name),
+        }
+    }
+}
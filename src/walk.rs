@@ -0,0 +1,193 @@
+//! Finding the paths to rename in directories and the directories in them.
+
+use std::path::PathBuf;
+
+use ignore::WalkBuilder;
+use log::*;
+
+/// Directories of version control systems, which aren't gone into, since renaming what's in
+/// them would break the repository.
+const SKIPPED: [&str; 3] = [".git", ".hg", ".svn"];
+
+/// Which paths [`walk`] finds.
+#[derive(Debug, Default, Clone)]
+pub struct WalkOptions {
+    /// How many levels of directories to go into, where the paths right in a directory are on
+    /// the first; all of them by default.
+    pub max_depth: Option<usize>,
+    /// Whether the directories that are gone into are among the paths too.
+    pub dirs: bool,
+    /// Whether to find hidden paths, whose names start with a `.`, and go into hidden
+    /// directories.
+    pub hidden: bool,
+    /// Whether to leave out the paths that `.gitignore` and `.ignore` files ignore, like build
+    /// artifacts; `.gitignore` files only count in a git repository.
+    pub ignore_files: bool,
+}
+
+/// The paths in each of the directories, and in the directories in them; paths that aren't
+/// directories are kept as they are. Each directory's paths are in the order of their names,
+/// after the directory itself, if it's one of the paths. Symbolic links to directories aren't
+/// followed.
+pub fn walk(paths: &[PathBuf], options: &WalkOptions) -> Vec<PathBuf> {
+    let mut found = vec![];
+
+    for path in paths {
+        if !path.is_dir() {
+            found.push(path.clone());
+            continue;
+        }
+
+        let walker = WalkBuilder::new(path)
+            .max_depth(options.max_depth)
+            .hidden(!options.hidden)
+            .ignore(options.ignore_files)
+            .git_ignore(options.ignore_files)
+            .git_global(options.ignore_files)
+            .git_exclude(options.ignore_files)
+            .parents(options.ignore_files)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(|entry| {
+                let skipped = entry.depth() > 0
+                    && entry.file_type().is_some_and(|t| t.is_dir())
+                    && SKIPPED.iter().any(|skipped| entry.file_name() == *skipped);
+                if skipped {
+                    debug!(
+                        "{:?} is skipped, since it's for version control",
+                        entry.path()
+                    );
+                }
+                !skipped
+            })
+            .build();
+
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("{}", err);
+                    continue;
+                }
+            };
+
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            if entry.depth() > 0 && (options.dirs || !is_dir) {
+                found.push(entry.into_path());
+            }
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    /// A directory with a few files, some in directories of their own, some hidden, and some
+    /// that are ignored or for version control.
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["sub", ".hsub", ".git"] {
+            fs::create_dir(dir.path().join(name)).unwrap();
+        }
+        for name in [
+            "b.txt",
+            "a.txt",
+            "build.log",
+            "sub/c.txt",
+            ".hidden",
+            ".hsub/d.txt",
+            ".git/config",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        fs::write(dir.path().join(".ignore"), "*.log\n").unwrap();
+        dir
+    }
+
+    fn found(dir: &Path, options: &WalkOptions) -> Vec<String> {
+        walk(&[dir.to_path_buf()], options)
+            .iter()
+            .map(|path| {
+                let relative = path.strip_prefix(dir).unwrap();
+                relative.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn walking_in_order_of_the_names() {
+        let dir = tree();
+
+        assert_eq!(
+            found(dir.path(), &WalkOptions::default()),
+            ["a.txt", "b.txt", "build.log", "sub/c.txt"]
+        );
+
+        // paths that aren't directories are kept as they are
+        let file = dir.path().join("b.txt");
+        assert_eq!(
+            walk(std::slice::from_ref(&file), &WalkOptions::default()),
+            [file]
+        );
+    }
+
+    #[test]
+    fn finding_directories_down_to_a_depth() {
+        let dir = tree();
+        let options = WalkOptions {
+            dirs: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            found(dir.path(), &options),
+            ["a.txt", "b.txt", "build.log", "sub", "sub/c.txt"]
+        );
+
+        let options = WalkOptions {
+            max_depth: Some(1),
+            ..options
+        };
+        assert_eq!(
+            found(dir.path(), &options),
+            ["a.txt", "b.txt", "build.log", "sub"]
+        );
+    }
+
+    #[test]
+    fn finding_hidden_paths_but_not_version_control() {
+        let dir = tree();
+        let options = WalkOptions {
+            hidden: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            found(dir.path(), &options),
+            [
+                ".hidden",
+                ".hsub/d.txt",
+                ".ignore",
+                "a.txt",
+                "b.txt",
+                "build.log",
+                "sub/c.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn leaving_out_ignored_paths() {
+        let dir = tree();
+        let options = WalkOptions {
+            ignore_files: true,
+            ..Default::default()
+        };
+
+        assert_eq!(found(dir.path(), &options), ["a.txt", "b.txt", "sub/c.txt"]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Renaming paths as they show up in a directory, like a download folder.
+
+use std::{
+    collections::HashSet,
+    io,
+    path::PathBuf,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use log::*;
+use mrp::MatchAndReplaceStrategy;
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind, RecursiveMode, Watcher,
+};
+
+use crate::{in_bulk, BulkRenameOptions, PathFilter, RenameReport};
+
+/// How long a new path has to be left alone before it's renamed, since a file is usually written
+/// to for a while after it's made.
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// Rename each path that is made in, or moved into, one of the directories, and that the filter
+/// allows, until the options are cancelled; `on_report` is told what became of each batch of
+/// paths. Paths that were there already are left as they are.
+pub fn watch<R: for<'a> MatchAndReplaceStrategy<'a> + Sync>(
+    dirs: &[PathBuf],
+    rename: &R,
+    options: &BulkRenameOptions,
+    filter: &PathFilter,
+    mut on_report: impl FnMut(RenameReport),
+) -> io::Result<()> {
+    let (events, received) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events).map_err(io::Error::other)?;
+    for dir in dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|err| io::Error::other(format!("{:?}: {}", dir, err)))?;
+        info!("watching {:?}", dir);
+    }
+
+    let mut pending: Vec<PathBuf> = vec![];
+    let mut last_event = Instant::now();
+    // the new names are moved into the directory too, and mustn't be renamed again
+    let mut renamed = HashSet::new();
+
+    while !options.is_cancelled() {
+        match received.recv_timeout(SETTLE / 5) {
+            Ok(Ok(event)) => {
+                for path in new_paths(event) {
+                    if !renamed.remove(&path) && !pending.contains(&path) && filter.allows(&path) {
+                        pending.push(path);
+                    }
+                }
+                last_event = Instant::now();
+            }
+            Ok(Err(err)) => warn!("{}", err),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if pending.is_empty() || last_event.elapsed() < SETTLE {
+                    continue;
+                }
+
+                // like the temporary names of renames that go in a cycle
+                pending.retain(|path| path.symlink_metadata().is_ok());
+
+                let report = in_bulk(&std::mem::take(&mut pending), rename, options);
+                renamed.extend(report.renamed.iter().map(|(_, to)| to.clone()));
+                on_report(report);
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// The paths that the event says were made, or moved in under a new name.
+fn new_paths(event: Event) -> Vec<PathBuf> {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => event.paths,
+        _ => vec![],
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+/// Run `rn` with the arguments in the directory, with a journal of its own.
+fn rn(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rn"))
+        .args(args)
+        .current_dir(dir)
+        .env("RENAME_JOURNAL", dir.join(".journal"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn exiting_with_4_when_nothing_matched() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "").unwrap();
+
+    for args in [
+        &["regex", r"\d+", "n", "a.txt"][..],
+        &["simple", "(n:int)->n", "a.txt"],
+    ] {
+        let output = rn(dir.path(), args);
+        assert_eq!(output.status.code(), Some(4), "{:?}", args);
+    }
+    assert!(dir.path().join("a.txt").exists());
+}
+
+#[test]
+fn renaming_what_a_regex_matches() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a1.txt"), "").unwrap();
+    fs::write(dir.path().join("b.txt"), "").unwrap();
+
+    let output = rn(dir.path(), &["regex", r"(\d+)", "_$1", "a1.txt", "b.txt"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(dir.path().join("a_1.txt").exists());
+    assert!(dir.path().join("b.txt").exists());
+}
+
+#[test]
+fn undoing_the_renames_recorded_as_they_were_made() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a1.txt"), "").unwrap();
+
+    let output = rn(dir.path(), &["simple", "(n:int)->_(n)", "a1.txt"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(dir.path().join("a_1.txt").exists());
+
+    let output = rn(dir.path(), &["undo"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(dir.path().join("a1.txt").exists());
+    assert!(!dir.path().join("a_1.txt").exists());
+}